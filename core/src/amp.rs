@@ -0,0 +1,180 @@
+//! Amplitude compensation curve for the `amp` DAC mode: a DAC code at every
+//! octave of pitch, interpolated in between, so each unit's output level can
+//! be flattened against its own VCA or filter.
+//!
+//! The curve is kept in the settings storage in a record of its own, with a
+//! version and a CRC like the settings, and falls back to the default when
+//! that record is missing or unreadable.
+//!
+//! Read from the publish task and written from the serial interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::crc::Crc32;
+use crate::settings::Error;
+use crate::storage::{self, Flash, Storage};
+
+/// Breakpoints, one an octave from C0 at 0 mV up to C8.
+pub const POINTS: usize = 9;
+
+/// Pitch between neighbouring breakpoints.
+pub const SPACING_MV: i32 = 1000;
+
+/// The old fixed mapping, 16 Hz a ladder step, at every breakpoint, clamped
+/// to full scale at the top instead of wrapping.
+const DEFAULT: [u16; POINTS] = [262, 523, 1046, 2093, 4186, 8372, 16744, 33488, 0xffff];
+
+/// Envelope reading at which the curve's code passes unchanged: the ADC's
+/// full scale.
+pub const ENV_FULL: u16 = 4095;
+
+/// Layout version, bumped whenever the meaning of the codes changes.
+const VERSION: u8 = 1;
+
+/// Storage key of the curve record, next to the settings.
+const KEY: u8 = 0x02;
+
+const CRC: usize = 4;
+
+/// Version, point count, every code as a little-endian `u16`, then a CRC-32
+/// over all of it.
+const LEN: usize = 2 + 2 * POINTS + CRC;
+
+pub struct Curve {
+    codes: [AtomicU16; POINTS],
+}
+
+impl Curve {
+    pub const fn new() -> Self {
+        Curve {
+            codes: [
+                AtomicU16::new(DEFAULT[0]),
+                AtomicU16::new(DEFAULT[1]),
+                AtomicU16::new(DEFAULT[2]),
+                AtomicU16::new(DEFAULT[3]),
+                AtomicU16::new(DEFAULT[4]),
+                AtomicU16::new(DEFAULT[5]),
+                AtomicU16::new(DEFAULT[6]),
+                AtomicU16::new(DEFAULT[7]),
+                AtomicU16::new(DEFAULT[8]),
+            ],
+        }
+    }
+
+    /// Pitch of breakpoint `point`.
+    pub fn mv(point: usize) -> i32 {
+        (point as i32).saturating_mul(SPACING_MV)
+    }
+
+    pub fn get(&self, point: usize) -> Option<u16> {
+        self.codes.get(point).map(|c| c.load(Ordering::Relaxed))
+    }
+
+    /// Sets one breakpoint, returning false if there is no such point.
+    pub fn set(&self, point: usize, code: u16) -> bool {
+        match self.codes.get(point) {
+            Some(c) => {
+                c.store(code, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every breakpoint back to the default.
+    pub fn reset(&self) {
+        for (c, &code) in self.codes.iter().zip(DEFAULT.iter()) {
+            c.store(code, Ordering::Relaxed);
+        }
+    }
+
+    /// DAC code for a pitch, held at the end points outside the curve.
+    pub fn code(&self, pitch_mv: f32) -> u16 {
+        let x = (pitch_mv / SPACING_MV as f32).max(0.0);
+        // Saturates, so a huge pitch lands past the last point
+        let i = x as usize;
+        match (self.get(i), self.get(i.saturating_add(1))) {
+            (Some(low), Some(high)) => {
+                let t = x - i as f32;
+                let (low, high) = (low as f32, high as f32);
+                (low + (high - low) * t) as u16
+            }
+            _ => self.get(POINTS.saturating_sub(1)).unwrap_or(0),
+        }
+    }
+
+    fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0; LEN];
+        let (body, crc) = bytes.split_at_mut(LEN.saturating_sub(CRC));
+        if let [version, count, codes @ ..] = body {
+            *version = VERSION;
+            *count = POINTS as u8;
+            for (chunk, c) in codes.chunks_exact_mut(2).zip(self.codes.iter()) {
+                chunk.copy_from_slice(&c.load(Ordering::Relaxed).to_le_bytes());
+            }
+        }
+        let mut sum = Crc32::new();
+        sum.update(body);
+        crc.copy_from_slice(&sum.finish().to_le_bytes());
+        bytes
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(), Error> {
+        let split = bytes.len().checked_sub(CRC).ok_or(Error::Corrupt)?;
+        let body = bytes.get(..split).ok_or(Error::Corrupt)?;
+        let mut sum = Crc32::new();
+        sum.update(body);
+        if bytes.get(split..) != Some(&sum.finish().to_le_bytes()[..]) {
+            return Err(Error::Corrupt);
+        }
+
+        match *body {
+            [VERSION, count, ref codes @ ..]
+                if count as usize == POINTS && codes.len() == LEN.saturating_sub(2 + CRC) =>
+            {
+                for (c, chunk) in self.codes.iter().zip(codes.chunks_exact(2)) {
+                    if let &[low, high] = chunk {
+                        c.store(u16::from_le_bytes([low, high]), Ordering::Relaxed);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(Error::OldLayout),
+        }
+    }
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Scales a code from the curve by an envelope reading, from silent at 0 up
+/// to the code itself at [`ENV_FULL`].
+pub fn shape(code: u16, env: u16) -> u16 {
+    (code as u32)
+        .saturating_mul(env.min(ENV_FULL) as u32)
+        .checked_div(ENV_FULL as u32)
+        .unwrap_or(0) as u16
+}
+
+/// Replaces the curve with the saved one, or leaves it alone if none can be
+/// read.
+pub fn load<F: Flash>(storage: &Storage<F>, curve: &Curve) -> Result<(), Error> {
+    let mut buf = [0; storage::MAX_LEN];
+    let len = storage.read(KEY, &mut buf).ok_or(Error::Missing)?;
+    curve.decode(buf.get(..len).unwrap_or(&[]))
+}
+
+/// Saves the curve, leaving the flash alone if it hasn't changed.
+pub fn save<F: Flash>(storage: &mut Storage<F>, curve: &Curve) -> Result<(), storage::Error> {
+    storage.write(KEY, &curve.encode())
+}
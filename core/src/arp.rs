@@ -0,0 +1,152 @@
+//! Arpeggiator: steps through the held MIDI notes, lowest first, or with no
+//! note held through a set of intervals above the pitch, one step per clock.
+//!
+//! Stepped from the sync and serial interrupts and from the arpeggiator's
+//! clock task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::midi::{NoteStack, HELD};
+use crate::params::{ARP_DOWN, ARP_RANDOM, ARP_UP, ARP_UP_DOWN};
+
+/// Semitones above the pitch stepped through when no note is held.
+pub struct Intervals {
+    pub name: &'static str,
+    pub semitones: &'static [u8],
+}
+
+pub const INTERVALS: [Intervals; 5] = [
+    Intervals {
+        name: "oct",
+        semitones: &[0, 12],
+    },
+    Intervals {
+        name: "5th",
+        semitones: &[0, 7, 12],
+    },
+    Intervals {
+        name: "maj",
+        semitones: &[0, 4, 7, 12],
+    },
+    Intervals {
+        name: "min",
+        semitones: &[0, 3, 7, 12],
+    },
+    Intervals {
+        name: "7th",
+        semitones: &[0, 4, 7, 10],
+    },
+];
+
+/// Interval set names in table order, for the menu.
+pub const NAMES: [&str; 5] = [
+    INTERVALS[0].name,
+    INTERVALS[1].name,
+    INTERVALS[2].name,
+    INTERVALS[3].name,
+    INTERVALS[4].name,
+];
+
+const MV_IN_SEMITONE: f32 = 1000.0 / 12.0;
+
+/// What one step plays.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    Note(u8),
+    /// Millivolts above the pitch.
+    Offset(f32),
+}
+
+pub struct Arp {
+    count: usize,
+    ticks: u32,
+    rng: u32,
+}
+
+impl Arp {
+    pub const fn new() -> Self {
+        Arp {
+            count: 0,
+            ticks: 0,
+            // Any non-zero seed runs through the full 2^32 - 1 sequence
+            rng: 0x2545_f491,
+        }
+    }
+
+    /// Starts the pattern over from its first step, on the first note held or
+    /// a MIDI start.
+    pub fn restart(&mut self) {
+        self.count = 0;
+        self.ticks = 0;
+    }
+
+    /// Counts one MIDI clock, true on every `ticks`th.
+    pub fn clock(&mut self, ticks: u32) -> bool {
+        self.ticks = self.ticks.saturating_add(1);
+        if self.ticks >= ticks {
+            self.ticks = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// One step of `pattern`: the next held note, or the next of `intervals`
+    /// with none held. `None` with the arpeggiator off.
+    pub fn step(&mut self, pattern: i32, notes: &NoteStack, intervals: &Intervals) -> Option<Step> {
+        let held = notes.held();
+        if held.is_empty() {
+            let i = self.next(pattern, intervals.semitones.len())?;
+            let &semitones = intervals.semitones.get(i)?;
+            return Some(Step::Offset(semitones as f32 * MV_IN_SEMITONE));
+        }
+        let mut sorted = [0; HELD];
+        let sorted = sorted.get_mut(..held.len())?;
+        sorted.copy_from_slice(held);
+        sorted.sort_unstable();
+        let i = self.next(pattern, sorted.len())?;
+        sorted.get(i).copied().map(Step::Note)
+    }
+
+    /// Index of the next of `len` steps.
+    fn next(&mut self, pattern: i32, len: usize) -> Option<usize> {
+        let last = len.checked_sub(1)?;
+        let count = self.count;
+        let index = match pattern {
+            ARP_UP => count.checked_rem(len)?,
+            ARP_DOWN => last.saturating_sub(count.checked_rem(len)?),
+            // Up and back without playing either end twice
+            ARP_UP_DOWN => {
+                let period = last.saturating_mul(2).max(1);
+                let at = count.checked_rem(period)?;
+                if at <= last {
+                    at
+                } else {
+                    period.saturating_sub(at)
+                }
+            }
+            ARP_RANDOM => {
+                let mut x = self.rng;
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                self.rng = x;
+                (x as usize).checked_rem(len)?
+            }
+            _ => return None,
+        };
+        self.count = count.wrapping_add(1);
+        Some(index)
+    }
+}
+
+impl Default for Arp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
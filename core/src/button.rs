@@ -0,0 +1,105 @@
+//! Debounced push button.
+
+/// Consecutive polls a new level has to hold before it is accepted.
+const DEBOUNCE_POLLS: u8 = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Event {
+    Press,
+    Release,
+}
+
+#[derive(Default)]
+pub struct Button {
+    pressed: bool,
+    count: u8,
+}
+
+impl Button {
+    pub const fn new() -> Self {
+        Button {
+            pressed: false,
+            count: 0,
+        }
+    }
+
+    /// Feeds the raw level sampled once per poll period and returns the
+    /// debounced edge, if any.
+    pub fn update(&mut self, pressed: bool) -> Option<Event> {
+        if pressed == self.pressed {
+            self.count = 0;
+            return None;
+        }
+
+        self.count += 1;
+        if self.count < DEBOUNCE_POLLS {
+            return None;
+        }
+
+        self.count = 0;
+        self.pressed = pressed;
+
+        Some(if pressed {
+            Event::Press
+        } else {
+            Event::Release
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Click {
+    Single,
+    Double,
+    Long,
+}
+
+/// Tells single, double and long clicks apart. A press waits out the double
+/// click window, or for the long press time while still held, before it
+/// counts as a single click.
+pub struct Clicks {
+    window: u16,
+    long: u16,
+    /// Polls since an unanswered press.
+    pending: Option<u16>,
+    held: bool,
+}
+
+impl Clicks {
+    pub const fn new(window_polls: u16, long_polls: u16) -> Self {
+        Clicks {
+            window: window_polls,
+            long: long_polls,
+            pending: None,
+            held: false,
+        }
+    }
+
+    /// Feeds the debounced event from every poll, including polls without one.
+    pub fn update(&mut self, event: Option<Event>) -> Option<Click> {
+        match event {
+            Some(Event::Press) => {
+                self.held = true;
+                if self.pending.take().is_some() {
+                    return Some(Click::Double);
+                }
+                self.pending = Some(0);
+                return None;
+            }
+            Some(Event::Release) => self.held = false,
+            None => {}
+        }
+
+        let polls = self.pending?.saturating_add(1);
+        if self.held && polls >= self.long {
+            self.pending = None;
+            Some(Click::Long)
+        } else if !self.held && polls >= self.window {
+            self.pending = None;
+            Some(Click::Single)
+        } else {
+            self.pending = Some(polls);
+            None
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Self-calibration of the pitch CV input against a reference oscillator at
+//! the sync input.
+//!
+//! With one CV patched into both the reference and the DCO, the capture
+//! measures what the reference plays for two settled notes at least an
+//! octave apart. The scale and offset that make the CV reading give those
+//! pitches correct the input stage's gain and offset errors, and with them
+//! any error of the clock the capture shares with the oscillator. They are
+//! kept in the settings storage in a record of their own, with a version and
+//! a CRC like the settings, and the input stays uncorrected when that record
+//! is missing or unreadable.
+//!
+//! Applied to every published pitch, with whatever offsets the flash holds.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use eurorack_oxide_utils::voct::{MvOct, Voltage};
+
+use crate::config::{CV_GAIN, CV_OFFSET_MV};
+use crate::crc::Crc32;
+use crate::note;
+use crate::settings::Error;
+use crate::storage::{self, Flash, Storage};
+
+/// Polls a note has to hold still for before it counts.
+const SETTLE_POLLS: u16 = 10;
+
+/// Most the CV reading may wander while a note settles.
+const CV_STILL_MV: f32 = 4.0;
+
+/// Most the reference may drift while a note settles, about half a cent.
+const PITCH_STILL_MV: f32 = 0.5;
+
+/// Least distance between the two notes, an octave of pitch.
+const SPAN_MV: f32 = 1000.0 / CV_GAIN;
+
+/// Polls before a run without two notes gives up.
+const TIMEOUT_POLLS: u16 = 600;
+
+/// Largest correction taken, past which the patch is more likely wrong than
+/// the unit: 20% of scale, an octave of offset.
+const MAX_SCALE_ERROR: f32 = 0.2;
+const MAX_OFFSET_MV: f32 = 1000.0 / CV_GAIN;
+
+/// `1.0_f32.to_bits()`, which isn't a `const fn` here.
+const ONE: u32 = 0x3f80_0000;
+
+/// Layout version, bumped whenever the meaning of the values changes.
+const VERSION: u8 = 1;
+
+/// Storage key of the calibration record, next to the amplitude curve.
+const KEY: u8 = 0x03;
+
+const CRC: usize = 4;
+
+/// Version, then the scale and the offset as little-endian `f32`s, then a
+/// CRC-32 over all of it.
+const LEN: usize = 1 + 4 + 4 + CRC;
+
+/// Correction of the CV reading at the input, before the pitch conversion.
+pub struct Calibration {
+    /// `f32` bits.
+    scale: AtomicU32,
+    /// `f32` bits, in mV at the input.
+    offset: AtomicU32,
+}
+
+impl Calibration {
+    /// No correction.
+    pub const fn new() -> Self {
+        Calibration {
+            scale: AtomicU32::new(ONE),
+            offset: AtomicU32::new(0),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        f32::from_bits(self.scale.load(Ordering::Relaxed))
+    }
+
+    pub fn offset_mv(&self) -> f32 {
+        f32::from_bits(self.offset.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, scale: f32, offset_mv: f32) {
+        self.scale.store(scale.to_bits(), Ordering::Relaxed);
+        self.offset.store(offset_mv.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Back to no correction.
+    pub fn reset(&self) {
+        self.set(1.0, 0.0);
+    }
+
+    /// Corrects a CV reading from [`crate::pitch::cv_mv`].
+    pub fn apply(&self, cv_mv: f32) -> f32 {
+        cv_mv * self.scale() + self.offset_mv()
+    }
+
+    fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0; LEN];
+        let (body, crc) = bytes.split_at_mut(LEN.saturating_sub(CRC));
+        if let [version, values @ ..] = body {
+            *version = VERSION;
+            let (scale, offset) = values.split_at_mut(4);
+            scale.copy_from_slice(&self.scale.load(Ordering::Relaxed).to_le_bytes());
+            offset.copy_from_slice(&self.offset.load(Ordering::Relaxed).to_le_bytes());
+        }
+        let mut sum = Crc32::new();
+        sum.update(body);
+        crc.copy_from_slice(&sum.finish().to_le_bytes());
+        bytes
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<(), Error> {
+        let split = bytes.len().checked_sub(CRC).ok_or(Error::Corrupt)?;
+        let body = bytes.get(..split).ok_or(Error::Corrupt)?;
+        let mut sum = Crc32::new();
+        sum.update(body);
+        if bytes.get(split..) != Some(&sum.finish().to_le_bytes()[..]) {
+            return Err(Error::Corrupt);
+        }
+
+        match *body {
+            [VERSION, a, b, c, d, e, f, g, h] => {
+                let scale = f32::from_bits(u32::from_le_bytes([a, b, c, d]));
+                let offset = f32::from_bits(u32::from_le_bytes([e, f, g, h]));
+                // Held to what a run could have produced, NaN included
+                if !plausible(scale, offset) {
+                    return Err(Error::Corrupt);
+                }
+                self.set(scale, offset);
+                Ok(())
+            }
+            _ => Err(Error::OldLayout),
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Replaces the calibration with the saved one, or leaves it alone if none
+/// can be read.
+pub fn load<F: Flash>(storage: &Storage<F>, calibration: &Calibration) -> Result<(), Error> {
+    let mut buf = [0; storage::MAX_LEN];
+    let len = storage.read(KEY, &mut buf).ok_or(Error::Missing)?;
+    calibration.decode(buf.get(..len).unwrap_or(&[]))
+}
+
+/// Saves the calibration, leaving the flash alone if it hasn't changed.
+pub fn save<F: Flash>(
+    storage: &mut Storage<F>,
+    calibration: &Calibration,
+) -> Result<(), storage::Error> {
+    storage.write(KEY, &calibration.encode())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Failure {
+    /// No two notes an octave apart within a minute.
+    Timeout,
+    /// A correction too large to trust.
+    OutOfRange,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    /// The first note is in, play the second.
+    FirstNote,
+    Done {
+        scale: f32,
+        offset_mv: f32,
+    },
+    Failed(Failure),
+}
+
+/// Averages of one settled note: the CV reading and the reference's pitch.
+#[derive(Clone, Copy)]
+struct Point {
+    cv_mv: f32,
+    pitch_mv: f32,
+}
+
+/// A calibration run, polled from a low priority task.
+pub struct Calibrator {
+    running: bool,
+    polls: u16,
+    /// Polls the current note has held still for.
+    settled: u16,
+    /// Where the current note started, to tell it held still.
+    anchor: Point,
+    sum: Point,
+    first: Option<Point>,
+    /// Correction before the run, put back if it fails.
+    previous: (f32, f32),
+}
+
+impl Calibrator {
+    pub const fn new() -> Self {
+        Calibrator {
+            running: false,
+            polls: 0,
+            settled: 0,
+            anchor: Point {
+                cv_mv: 0.0,
+                pitch_mv: 0.0,
+            },
+            sum: Point {
+                cv_mv: 0.0,
+                pitch_mv: 0.0,
+            },
+            first: None,
+            previous: (1.0, 0.0),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Starts a run, measuring the input uncorrected until it ends.
+    pub fn start(&mut self, calibration: &Calibration) {
+        if !self.running {
+            self.previous = (calibration.scale(), calibration.offset_mv());
+        }
+        *self = Calibrator {
+            running: true,
+            previous: self.previous,
+            ..Calibrator::new()
+        };
+        calibration.reset();
+    }
+
+    /// Feeds one poll: the CV reading at the input and the frequency at the
+    /// sync input, `None` while nothing is patched there. Sets `calibration`
+    /// once the run ends, to the new correction or back to the old one.
+    pub fn poll(
+        &mut self,
+        cv_mv: f32,
+        reference_hz: Option<f32>,
+        calibration: &Calibration,
+    ) -> Option<Event> {
+        if !self.running {
+            return None;
+        }
+        self.polls = self.polls.saturating_add(1);
+        if self.polls > TIMEOUT_POLLS {
+            return Some(self.fail(Failure::Timeout, calibration));
+        }
+
+        let hz = match reference_hz {
+            Some(hz) if hz > 0.0 => hz,
+            _ => {
+                self.settled = 0;
+                return None;
+            }
+        };
+        let point = Point {
+            cv_mv,
+            pitch_mv: 1000.0 * note::log2(hz / MvOct(0.0).hz()),
+        };
+
+        let moved = distance(point.cv_mv, self.anchor.cv_mv) > CV_STILL_MV
+            || distance(point.pitch_mv, self.anchor.pitch_mv) > PITCH_STILL_MV;
+        if self.settled == 0 || moved {
+            self.anchor = point;
+            self.sum = point;
+            self.settled = 1;
+            return None;
+        }
+        self.sum.cv_mv += point.cv_mv;
+        self.sum.pitch_mv += point.pitch_mv;
+        self.settled = self.settled.saturating_add(1);
+        if self.settled < SETTLE_POLLS {
+            return None;
+        }
+
+        let n = self.settled as f32;
+        let note = Point {
+            cv_mv: self.sum.cv_mv / n,
+            pitch_mv: self.sum.pitch_mv / n,
+        };
+        self.settled = 0;
+        let first = match self.first {
+            None => {
+                self.first = Some(note);
+                return Some(Event::FirstNote);
+            }
+            // Still the first note, or back on it
+            Some(first) if distance(note.cv_mv, first.cv_mv) < SPAN_MV => return None,
+            Some(first) => first,
+        };
+
+        // The CV readings that would have given the reference's pitches
+        let ideal = |pitch_mv: f32| (CV_OFFSET_MV - pitch_mv) / CV_GAIN;
+        let scale = (ideal(note.pitch_mv) - ideal(first.pitch_mv)) / (note.cv_mv - first.cv_mv);
+        let offset_mv = ideal(first.pitch_mv) - scale * first.cv_mv;
+        if !plausible(scale, offset_mv) {
+            return Some(self.fail(Failure::OutOfRange, calibration));
+        }
+        self.running = false;
+        calibration.set(scale, offset_mv);
+        Some(Event::Done { scale, offset_mv })
+    }
+
+    fn fail(&mut self, failure: Failure, calibration: &Calibration) -> Event {
+        self.running = false;
+        let (scale, offset_mv) = self.previous;
+        calibration.set(scale, offset_mv);
+        Event::Failed(failure)
+    }
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn plausible(scale: f32, offset_mv: f32) -> bool {
+    distance(scale, 1.0) <= MAX_SCALE_ERROR && distance(offset_mv, 0.0) <= MAX_OFFSET_MV
+}
+
+/// `f32::abs` of the difference needs `std`; NaN comes out as NaN.
+fn distance(a: f32, b: f32) -> f32 {
+    if a < b {
+        b - a
+    } else {
+        a - b
+    }
+}
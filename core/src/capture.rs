@@ -0,0 +1,96 @@
+//! Frequency of the signal at the sync input, from TIM3 input capture.
+//!
+//! TIM3 also generates the tick, so the capture is polled from the tick
+//! interrupt instead of raising its own: a second interrupt source on the
+//! vector would advance the oscillators twice. Runs at the highest priority.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::wide::Wide;
+
+/// Extends the 16-bit capture register with a 64-bit count of timer updates,
+/// so periods of any length, minutes between clock pulses included, are
+/// measured to one timer clock.
+pub struct Capture {
+    /// Timer clocks per update.
+    reload: u32,
+    updates: Wide,
+    last: Wide,
+    has_last: AtomicBool,
+    period: Wide,
+}
+
+impl Capture {
+    pub const fn new(reload: u32) -> Self {
+        Capture {
+            reload,
+            updates: Wide::new(0),
+            last: Wide::new(0),
+            has_last: AtomicBool::new(false),
+            period: Wide::new(0),
+        }
+    }
+
+    /// Handles a capture of `ccr` found pending in the update interrupt, where
+    /// the counter has since reached `cnt`. A value above `cnt` was latched
+    /// before the update that raised the interrupt.
+    pub fn captured(&self, ccr: u16, cnt: u16) {
+        let updates = self.updates.load();
+        let updates = if ccr > cnt {
+            updates
+        } else {
+            updates.wrapping_add(1)
+        };
+        let now = updates
+            .wrapping_mul(self.reload as u64)
+            .wrapping_add(ccr as u64);
+
+        let last = self.last.load();
+        self.last.store(now);
+        if self.has_last.swap(true, Ordering::Relaxed) {
+            self.period.store(now.wrapping_sub(last));
+        }
+    }
+
+    /// Counts a timer update, after any capture pending with it.
+    pub fn update(&self) {
+        self.updates.store(self.updates.load().wrapping_add(1));
+    }
+
+    /// Last measured period in timer clocks, `None` before two edges or once
+    /// the input has been quiet for two periods.
+    pub fn period(&self) -> Option<u64> {
+        let period = self.period.load();
+        // End of the current timer period, never before the last capture
+        let now = self
+            .updates
+            .load()
+            .wrapping_add(1)
+            .wrapping_mul(self.reload as u64);
+        let quiet = now.wrapping_sub(self.last.load());
+
+        if period == 0 || quiet > period.saturating_mul(2) {
+            None
+        } else {
+            Some(period)
+        }
+    }
+
+    /// Input frequency for a timer clock of `timer_hz`.
+    pub fn hz(&self, timer_hz: u32) -> Option<f32> {
+        self.period().map(|period| timer_hz as f32 / period as f32)
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
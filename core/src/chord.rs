@@ -0,0 +1,110 @@
+//! Chord mode: extra phase accumulators a fixed number of semitones above the
+//! oscillator, stepped with it and mixed into the DAC output, for paraphonic
+//! chords from a single pitch CV.
+//!
+//! Runs inside the tick interrupt, once for every chord tone.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Notes stacked above the root at most.
+pub const STACKED: usize = 3;
+
+/// Semitones above the root of every stacked note.
+pub struct Shape {
+    pub name: &'static str,
+    pub semitones: &'static [u8],
+}
+
+pub const SHAPES: [Shape; 7] = [
+    Shape {
+        name: "5th",
+        semitones: &[7],
+    },
+    Shape {
+        name: "oct",
+        semitones: &[12],
+    },
+    Shape {
+        name: "maj",
+        semitones: &[4, 7],
+    },
+    Shape {
+        name: "min",
+        semitones: &[3, 7],
+    },
+    Shape {
+        name: "sus4",
+        semitones: &[5, 7],
+    },
+    Shape {
+        name: "7th",
+        semitones: &[4, 7, 10],
+    },
+    Shape {
+        name: "min7",
+        semitones: &[3, 7, 10],
+    },
+];
+
+/// Shape names in table order, for the menu.
+pub const NAMES: [&str; 7] = [
+    SHAPES[0].name,
+    SHAPES[1].name,
+    SHAPES[2].name,
+    SHAPES[3].name,
+    SHAPES[4].name,
+    SHAPES[5].name,
+    SHAPES[6].name,
+];
+
+/// Frequency ratio of each interval up to an octave, 2^(n/12) in Q16.
+const RATIO: [u32; 13] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715, 131072,
+];
+
+/// Phases of the stacked notes.
+pub struct Chord {
+    phases: [u32; STACKED],
+}
+
+impl Chord {
+    pub const fn new() -> Self {
+        Chord {
+            phases: [0; STACKED],
+        }
+    }
+
+    /// Steps every note of `shape` by one tick, a root `step` scaled by its
+    /// interval, and returns the root `sample` mixed equally with one `wave`
+    /// sample of each.
+    pub fn mix<F>(&mut self, sample: u8, step: u32, shape: &Shape, wave: F) -> u8
+    where
+        F: Fn(u32, u32) -> u8,
+    {
+        let mut sum = sample as u32;
+        let mut count = 1u32;
+        for (phase, &semitones) in self.phases.iter_mut().zip(shape.semitones) {
+            let ratio = match RATIO.get(semitones as usize) {
+                Some(&ratio) => ratio,
+                None => continue,
+            };
+            let note_step =
+                ((step as u64).saturating_mul(ratio as u64) >> 16).min(u32::MAX as u64) as u32;
+            *phase = phase.wrapping_add(note_step);
+            sum = sum.saturating_add(wave(*phase, note_step) as u32);
+            count = count.saturating_add(1);
+        }
+        sum.checked_div(count).unwrap_or(0) as u8
+    }
+}
+
+impl Default for Chord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,285 @@
+//! Serial console: line editing and command parsing.
+//!
+//! Characters are echoed from the serial interrupt, whatever arrives; the
+//! commands themselves run from a low priority task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Console serial rate.
+pub const BAUD: u32 = 115_200;
+
+/// Longest command line.
+pub const LINE: usize = 64;
+
+pub const PROMPT: &str = "> ";
+
+pub const HELP: &str = "\
+help                 this list
+freq?                frequency, pitch and note
+load?                handler timings in cycles and the CPU load
+crash?               the last panic or HardFault since power-up
+pages                every page with its value
+<page>?              one page, e.g. glide?
+set <page> <value>   number or label, e.g. set glide 50
+reset                every page back to its default
+presets              every saved preset with its name
+save <n> [name]      the current sound as preset n, 1-8
+recall <n>           preset n, as the preset page does
+amp                  the amplitude curve, a DAC code per octave
+amp <n> <code>       sets breakpoint n, 0-8, to 0-65535 and saves
+cal                  calibrates the CV against a reference at the sync input
+cal?                 the CV calibration, scale and offset
+watch <args>         RTT streaming: off, all, rate <ms>, channel names
+snapshot             support snapshot over RTT
+bootloader           reboot into the ROM bootloader on USART1
+update               receive new firmware here with XMODEM
+";
+
+/// One finished command line, copied out of the editor for the task that
+/// runs it.
+#[derive(Clone, Copy)]
+pub struct Line {
+    buf: [u8; LINE],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Line {
+            buf: [0; LINE],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only printable ASCII is ever stored
+        let bytes = self.buf.get(..self.len).unwrap_or(&[]);
+        core::str::from_utf8(bytes).unwrap_or("")
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    /// After ESC.
+    Start,
+    /// Inside a CSI sequence, up to its final byte.
+    Csi,
+}
+
+/// Line editor: echoes what is typed, handles backspace, Ctrl-U (kill line)
+/// and Ctrl-C (cancel), and ignores cursor keys instead of storing their
+/// escape sequences.
+pub struct Editor {
+    line: Line,
+    escape: Escape,
+}
+
+impl Editor {
+    pub const fn new() -> Self {
+        Editor {
+            line: Line::new(),
+            escape: Escape::None,
+        }
+    }
+
+    /// Feeds one received byte, echoing through `out`. Returns the line when
+    /// Enter is pressed.
+    pub fn feed(&mut self, byte: u8, mut out: impl FnMut(&[u8])) -> Option<Line> {
+        match (self.escape, byte) {
+            (Escape::Start, b'[') => {
+                self.escape = Escape::Csi;
+                return None;
+            }
+            (Escape::Start, _) => {
+                self.escape = Escape::None;
+                return None;
+            }
+            (Escape::Csi, 0x40..=0x7e) => {
+                self.escape = Escape::None;
+                return None;
+            }
+            (Escape::Csi, _) => return None,
+            (Escape::None, _) => {}
+        }
+
+        match byte {
+            b'\r' | b'\n' => {
+                out(b"\r\n");
+                let line = self.line;
+                self.line.len = 0;
+                // CR LF terminals send an empty line after every real one
+                if byte == b'\n' && line.len == 0 {
+                    return None;
+                }
+                return Some(line);
+            }
+            0x1b => self.escape = Escape::Start,
+            // Backspace and DEL
+            0x08 | 0x7f => {
+                if let Some(len) = self.line.len.checked_sub(1) {
+                    self.line.len = len;
+                    out(b"\x08 \x08");
+                }
+            }
+            // Ctrl-U
+            0x15 => {
+                for _ in 0..self.line.len {
+                    out(b"\x08 \x08");
+                }
+                self.line.len = 0;
+            }
+            // Ctrl-C
+            0x03 => {
+                self.line.len = 0;
+                out(b"^C\r\n");
+                out(PROMPT.as_bytes());
+            }
+            0x20..=0x7e => {
+                if let Some(slot) = self.line.buf.get_mut(self.line.len) {
+                    *slot = byte;
+                    self.line.len = self.line.len.saturating_add(1);
+                    out(&[byte]);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command<'a> {
+    Empty,
+    Help,
+    Freq,
+    Load,
+    Crash,
+    Pages,
+    Get(&'a str),
+    Set(&'a str, &'a str),
+    Reset,
+    Presets,
+    Save(&'a str, &'a str),
+    Recall(&'a str),
+    Amp,
+    SetAmp(&'a str, &'a str),
+    Calibrate,
+    Calibration,
+    Watch(&'a str),
+    Snapshot,
+    Bootloader,
+    Update,
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        let line = line.trim();
+        let (word, args) = match line.find(' ') {
+            Some(i) => (
+                line.get(..i).unwrap_or(""),
+                line.get(i..).unwrap_or("").trim(),
+            ),
+            None => (line, ""),
+        };
+
+        match (word, args) {
+            ("", _) => Command::Empty,
+            ("help", _) | ("?", _) => Command::Help,
+            ("freq?", "") => Command::Freq,
+            ("load?", "") => Command::Load,
+            ("crash?", "") => Command::Crash,
+            ("pages", "") => Command::Pages,
+            ("set", args) => {
+                let mut words = args.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(page), Some(value), None) => Command::Set(page, value),
+                    _ => Command::Unknown(word),
+                }
+            }
+            ("reset", "") => Command::Reset,
+            ("presets", "") => Command::Presets,
+            ("save", args) => {
+                let mut words = args.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(slot), name, None) => Command::Save(slot, name.unwrap_or("")),
+                    _ => Command::Unknown(word),
+                }
+            }
+            ("recall", args) if !args.is_empty() && !args.contains(' ') => Command::Recall(args),
+            ("amp", "") => Command::Amp,
+            ("amp", args) => {
+                let mut words = args.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(point), Some(code), None) => Command::SetAmp(point, code),
+                    _ => Command::Unknown(word),
+                }
+            }
+            ("cal", "") => Command::Calibrate,
+            ("cal?", "") => Command::Calibration,
+            ("watch", args) => Command::Watch(args),
+            ("snapshot", "") => Command::Snapshot,
+            ("bootloader", "") => Command::Bootloader,
+            ("update", "") => Command::Update,
+            (word, "") if word.ends_with('?') => Command::Get(word.trim_end_matches('?')),
+            (word, _) => Command::Unknown(word),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `keys` and returns the line Enter gave, if any.
+    fn type_line(editor: &mut Editor, keys: &[u8]) -> Option<Line> {
+        keys.iter()
+            .fold(None, |line, &b| editor.feed(b, |_| {}).or(line))
+    }
+
+    #[test]
+    fn editing_keys() {
+        let mut editor = Editor::new();
+        let line = type_line(&mut editor, b"frex\x7fq?\r");
+        assert_eq!(line.as_ref().map(Line::as_str), Some("freq?"));
+        let line = type_line(&mut editor, b"junk\x15help\r");
+        assert_eq!(line.as_ref().map(Line::as_str), Some("help"));
+        assert!(type_line(&mut editor, b"junk\x03").is_none());
+        let line = type_line(&mut editor, b"pa\x1b[Dges\r\n");
+        assert_eq!(line.as_ref().map(Line::as_str), Some("pages"));
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        let mut editor = Editor::new();
+        let line = type_line(&mut editor, &[b'a'; LINE + 10]);
+        assert!(line.is_none());
+        let line = type_line(&mut editor, b"\r");
+        assert_eq!(line.map(|l| l.as_str().len()), Some(LINE));
+    }
+
+    #[test]
+    fn commands_and_their_arguments() {
+        assert!(Command::parse("  set glide 50 ") == Command::Set("glide", "50"));
+        assert!(Command::parse("set glide") == Command::Unknown("set"));
+        assert!(Command::parse("save 3") == Command::Save("3", ""));
+        assert!(Command::parse("save 3 bass") == Command::Save("3", "bass"));
+        assert!(Command::parse("glide?") == Command::Get("glide"));
+        assert!(Command::parse("amp 2 40000") == Command::SetAmp("2", "40000"));
+        assert!(Command::parse("update") == Command::Update);
+        assert!(Command::parse("update now") == Command::Unknown("update"));
+        assert!(Command::parse("") == Command::Empty);
+    }
+}
@@ -0,0 +1,26 @@
+//! Build-time tuning: sample rates, buffer sizes and the CV scaling, in one
+//! place for builds on other hardware.
+//!
+//! Everything else derives from these, so changing a value here is enough;
+//! the comments say what has to hold for it to keep working.
+
+/// Phase accumulator rate, TIM3's update frequency. The CV is measured at
+/// half of it. Higher rates push aliasing up but leave the tick handler less
+/// time; at 28 MHz the handler has 140 cycles at the default.
+pub const TIM3_FREQ_HZ: u32 = 200_000;
+
+/// Samples averaged per pitch update. More samples mean less jitter and
+/// slower tracking; the pitch is published every `AVG_BUF_SIZE` measurements.
+pub const AVG_BUF_SIZE: usize = 32;
+
+/// Millivolts the fine tune page moves per encoder detent.
+pub const FINE_TUNE_STEP: i32 = 2;
+
+/// VREFINT reading scaled to millivolts at the CV input, for the divider in
+/// front of the ADC.
+pub const VREF_SCALE: f32 = 1_191.555_5;
+
+/// The input stage inverts and scales the CV by [`CV_GAIN`] around this
+/// pitch, in mV/oct.
+pub const CV_OFFSET_MV: f32 = 6000.0;
+pub const CV_GAIN: f32 = 2.0;
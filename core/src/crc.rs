@@ -0,0 +1,80 @@
+//! CRC-32 (IEEE 802.3), and the CRC-16 XMODEM blocks carry, both computed
+//! bitwise so they need no table in flash.
+
+const POLY: u32 = 0xedb8_8320;
+const POLY16: u16 = 0x1021;
+
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32(!0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (POLY & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRC-16/XMODEM: the CCITT polynomial, most significant bit first, from
+/// zero.
+pub struct Crc16(u16);
+
+impl Crc16 {
+    pub const fn new() -> Self {
+        Crc16(0)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= (byte as u16) << 8;
+            for _ in 0..8 {
+                let mask = (self.0 >> 15).wrapping_neg();
+                self.0 = (self.0 << 1) ^ (POLY16 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u16 {
+        self.0
+    }
+}
+
+impl Default for Crc16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+
+        let mut crc = Crc16::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0x31c3);
+    }
+}
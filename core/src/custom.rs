@@ -0,0 +1,16 @@
+//! Fork-specific behaviour. Stock firmware adds nothing here.
+//!
+//! Add menu pages to `PAGES` and read them with `Param::User(index)`; put
+//! behaviour in the [`Hooks`] implementation below.
+
+use crate::hooks::Hooks;
+use crate::params::Info;
+
+/// Extra encoder menu pages, shown after the built-in ones.
+pub const PAGES: [Info; 0] = [];
+
+pub struct Custom;
+
+impl Hooks for Custom {}
+
+pub static HOOKS: Custom = Custom;
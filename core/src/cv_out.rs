@@ -0,0 +1,61 @@
+//! Filtered PWM control voltage output: a 16-bit code turned into timer
+//! compare values whose average, once an RC filter smooths the pulses, is
+//! the code's fraction of the supply.
+//!
+//! A PWM period only has as many duty steps as the timer counts in it, a few
+//! hundred at the measurement rate. The compare value is dithered between the
+//! two steps either side of the code from period to period, so the filtered
+//! level keeps the full resolution.
+//!
+//! Runs in the measurement interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::dac;
+use crate::wave;
+
+/// Pitch at full scale for the `pitch` source, 8 octaves across the supply.
+pub const PITCH_FULL_SCALE_MV: f32 = 8000.0;
+
+/// Code for a pitch, clamped to the range of the output.
+pub fn pitch_code(pitch_mv: f32) -> u16 {
+    (pitch_mv / PITCH_FULL_SCALE_MV * 65535.0).clamp(0.0, 65535.0) as u16
+}
+
+/// Code for the oscillator's triangle at `phase`, which is an LFO in `lfo`
+/// range.
+pub fn triangle_code(phase: u32) -> u16 {
+    dac::from_u8(wave::triangle(phase))
+}
+
+/// First-order noise shaping of the compare value: what one period rounds
+/// off is carried into the next.
+pub struct Dither {
+    error: u32,
+}
+
+impl Dither {
+    pub const fn new() -> Self {
+        Dither { error: 0 }
+    }
+
+    /// Compare value for the next period, out of `top` timer counts a period.
+    pub fn compare(&mut self, code: u16, top: u32) -> u32 {
+        let exact = (code as u64)
+            .saturating_mul(top as u64)
+            .saturating_add(self.error as u64);
+        self.error = (exact & 0xffff) as u32;
+        (exact >> 16) as u32
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new()
+    }
+}
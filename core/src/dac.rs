@@ -0,0 +1,116 @@
+//! DAC output codes. Everything written to the DAC is a 16-bit full-scale
+//! code, whatever converter is fitted: the 8-bit R-2R ladder on PA0-PA7 takes
+//! the top byte, an external SPI DAC as many bits as it has.
+//!
+//! Runs once per tick at the highest priority, or once per block of samples
+//! streamed to the ladder by DMA.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Bytes in the longest SPI frame.
+pub const FRAME: usize = 4;
+
+/// Code for an 8-bit sample. Steps stay evenly spaced at every width, so
+/// the top of the range is one step short of full scale, as on the ladder.
+pub fn from_u8(sample: u8) -> u16 {
+    (sample as u16) << 8
+}
+
+/// BSRR value that sets and resets the ladder bits in one write, for a ladder
+/// on pins 0-7; shifting it left moves the ladder up the port.
+pub fn r2r_bsrr(code: u16) -> u32 {
+    let s = (code >> 8) as u32;
+    s | ((!s & 0xff) << 16)
+}
+
+/// Samples in each half of the ladder's DMA buffer with the `dac-dma`
+/// feature, 160 µs at the 200 kHz tick.
+pub const BLOCK: usize = 32;
+
+/// Which half of the DMA buffer is free to refill, from the transfers the
+/// DMA has left in this pass over the buffer, and how many samples it plays
+/// before it gets there.
+pub fn free_half(remaining: usize) -> (usize, usize) {
+    match remaining.checked_sub(BLOCK) {
+        Some(lead) if lead > 0 => (1, lead),
+        _ => (0, remaining),
+    }
+}
+
+/// Fills `block` with the BSRR words of a ladder from pin `lsb` up, one
+/// `code` for every sample: at `phase`, then a `step` further each.
+pub fn fill_r2r<F>(block: &mut [u32], lsb: u32, phase: u32, step: u32, mut code: F)
+where
+    F: FnMut(u32) -> u16,
+{
+    let mut phase = phase;
+    for word in block.iter_mut() {
+        *word = r2r_bsrr(code(phase)).checked_shl(lsb).unwrap_or(0);
+        phase = phase.wrapping_add(step);
+    }
+}
+
+/// SPI CR1 baud rate bits for the fastest clock from `pclk_hz` that stays at
+/// or below `max_hz`, down to the slowest divider of 256.
+pub fn spi_baud_bits(pclk_hz: u32, max_hz: u32) -> u32 {
+    let mut br = 0u32;
+    while br < 7 && pclk_hz.checked_shr(br.wrapping_add(1)).unwrap_or(0) > max_hz {
+        br = br.wrapping_add(1);
+    }
+    br
+}
+
+/// External DAC on SPI, sent one frame per sample with CS pulsed between
+/// frames.
+pub trait SpiDac {
+    /// Bytes per frame, at most [`FRAME`].
+    const LEN: usize;
+    /// Whether the chip samples data on the falling clock edge.
+    const CPHA: bool;
+    /// Frame sent once at power up, before any samples.
+    const SETUP: Option<[u8; FRAME]>;
+    /// Fastest SPI clock the chip takes.
+    const MAX_CLOCK_HZ: u32;
+
+    fn frame(code: u16) -> [u8; FRAME];
+}
+
+/// Microchip MCP4922, 12-bit, on channel A. LDAC is tied low, so the output
+/// updates when CS goes high.
+pub struct Mcp4922;
+
+impl SpiDac for Mcp4922 {
+    const LEN: usize = 2;
+    const CPHA: bool = false;
+    const SETUP: Option<[u8; FRAME]> = None;
+    const MAX_CLOCK_HZ: u32 = 20_000_000;
+
+    fn frame(code: u16) -> [u8; FRAME] {
+        // Channel A, unbuffered reference, 1x gain, output on
+        let [high, low] = (0x3000 | code >> 4).to_be_bytes();
+        [high, low, 0, 0]
+    }
+}
+
+/// TI DAC8568, 16-bit, on channel A with its internal 2.5 V reference, for
+/// 0-5 V out.
+pub struct Dac8568;
+
+impl SpiDac for Dac8568 {
+    const LEN: usize = 4;
+    const CPHA: bool = true;
+    // Internal reference on, in static mode
+    const SETUP: Option<[u8; FRAME]> = Some([0x08, 0x00, 0x00, 0x01]);
+    const MAX_CLOCK_HZ: u32 = 50_000_000;
+
+    fn frame(code: u16) -> [u8; FRAME] {
+        // Write and update channel A, the data sits between the address and
+        // the feature bits
+        (0x0300_0000 | (code as u32) << 4).to_be_bytes()
+    }
+}
@@ -0,0 +1,179 @@
+//! SSD1306 128x32 OLED over I2C with a built-in 5x7 text font.
+
+use core::fmt;
+
+use embedded_hal::blocking::i2c::Write;
+
+const ADDRESS: u8 = 0x3c;
+pub const WIDTH: usize = 128;
+/// Text rows, one 8 pixel page each.
+pub const ROWS: usize = 4;
+/// Advance per character: 5 glyph columns plus one blank.
+const CHAR_WIDTH: usize = 6;
+pub const COLUMNS: usize = WIDTH / CHAR_WIDTH;
+
+const CONTROL_COMMAND: u8 = 0x00;
+const CONTROL_DATA: u8 = 0x40;
+
+const INIT: [u8; 25] = [
+    0xae, // display off
+    0xd5, 0x80, // clock divide ratio
+    0xa8, 0x1f, // multiplex ratio: 32 rows
+    0xd3, 0x00, // no display offset
+    0x40, // start line 0
+    0x8d, 0x14, // enable charge pump
+    0x20, 0x00, // horizontal addressing
+    0xa1, // mirror columns
+    0xc8, // scan rows top to bottom
+    0xda, 0x02, // sequential COM pins for 128x32
+    0x81, 0x8f, // contrast
+    0xd9, 0xf1, // pre-charge period
+    0xdb, 0x40, // VCOMH deselect level
+    0xa4, // display RAM contents
+    0xa6, // not inverted
+    0xaf, // display on
+];
+
+pub struct Ssd1306<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C, E> Ssd1306<I2C>
+where
+    I2C: Write<Error = E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Ssd1306 { i2c }
+    }
+
+    pub fn init(&mut self) -> Result<(), E> {
+        for &cmd in INIT.iter() {
+            self.i2c.write(ADDRESS, &[CONTROL_COMMAND, cmd])?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders a line of text into the given row, padding with blanks.
+    pub fn draw_row(&mut self, row: u8, text: &str) -> Result<(), E> {
+        // Column range 0-127, single page
+        for &cmd in [0x21, 0, (WIDTH - 1) as u8, 0x22, row, row].iter() {
+            self.i2c.write(ADDRESS, &[CONTROL_COMMAND, cmd])?;
+        }
+
+        let mut buf = [0u8; WIDTH + 1];
+        buf[0] = CONTROL_DATA;
+        for (cell, c) in buf[1..].chunks_mut(CHAR_WIDTH).zip(text.bytes()) {
+            let glyph = glyph(c);
+            let n = cell.len().min(glyph.len());
+            cell[..n].copy_from_slice(&glyph[..n]);
+        }
+
+        self.i2c.write(ADDRESS, &buf)
+    }
+}
+
+/// Fixed capacity line of text for one display row.
+pub struct Line {
+    buf: [u8; COLUMNS],
+    len: usize,
+}
+
+impl Line {
+    pub fn new() -> Self {
+        Line {
+            buf: [b' '; COLUMNS],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII bytes are ever pushed
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for Line {
+    /// Truncates at the end of the row instead of failing.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.bytes() {
+            if self.len == COLUMNS {
+                break;
+            }
+            self.buf[self.len] = if c.is_ascii() { c } else { b'?' };
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+}
+
+fn glyph(c: u8) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    CHARS
+        .binary_search(&c)
+        .or_else(|_| CHARS.binary_search(&b'?'))
+        .map_or([0; 5], |i| GLYPHS[i])
+}
+
+/// Glyph characters, sorted so lookups can binary search.
+const CHARS: &[u8] = b" #%+-./0123456789:<=>?ABCDEFGHIJKLMNOPQRSTUVWXYZ_";
+
+/// 5x7 glyphs as columns, least significant bit at the top.
+const GLYPHS: [[u8; 5]; 49] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // '#'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // '+'
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // '0'
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x7e, 0x09, 0x09, 0x09, 0x7e], // 'A'
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // 'D'
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // 'G'
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // 'H'
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // 'J'
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // 'M'
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // 'N'
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // 'O'
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // 'Q'
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // 'T'
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // 'U'
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // 'V'
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+];
@@ -0,0 +1,171 @@
+//! Musical divisions of a clock, for expressing LFO rates against a tempo.
+//!
+//! Lengths are counted in MIDI clock ticks (24 per quarter note), so the same
+//! table serves tempo-based rates and rates derived from an incoming clock.
+
+/// MIDI clock resolution, ticks per quarter note.
+pub const PPQN: u32 = 24;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Division {
+    pub name: &'static str,
+    /// Length of one LFO cycle in clock ticks.
+    pub ticks: u32,
+}
+
+/// Longest first; dotted and triplet values sit next to their straight ones.
+pub const DIVISIONS: [Division; 12] = [
+    Division {
+        name: "1/1",
+        ticks: 96,
+    },
+    Division {
+        name: "1/2",
+        ticks: 48,
+    },
+    Division {
+        name: "1/4D",
+        ticks: 36,
+    },
+    Division {
+        name: "1/2T",
+        ticks: 32,
+    },
+    Division {
+        name: "1/4",
+        ticks: 24,
+    },
+    Division {
+        name: "1/8D",
+        ticks: 18,
+    },
+    Division {
+        name: "1/4T",
+        ticks: 16,
+    },
+    Division {
+        name: "1/8",
+        ticks: 12,
+    },
+    Division {
+        name: "1/8T",
+        ticks: 8,
+    },
+    Division {
+        name: "1/16",
+        ticks: 6,
+    },
+    Division {
+        name: "1/16T",
+        ticks: 4,
+    },
+    Division {
+        name: "1/32",
+        ticks: 3,
+    },
+];
+
+/// Division names in table order, for the menu.
+pub const NAMES: [&str; 12] = [
+    DIVISIONS[0].name,
+    DIVISIONS[1].name,
+    DIVISIONS[2].name,
+    DIVISIONS[3].name,
+    DIVISIONS[4].name,
+    DIVISIONS[5].name,
+    DIVISIONS[6].name,
+    DIVISIONS[7].name,
+    DIVISIONS[8].name,
+    DIVISIONS[9].name,
+    DIVISIONS[10].name,
+    DIVISIONS[11].name,
+];
+
+impl Division {
+    /// Rate at a tempo in quarter notes per minute.
+    pub fn hz(self, bpm: f32) -> f32 {
+        bpm / 60.0 * PPQN as f32 / self.ticks as f32
+    }
+
+    /// Rate from the measured interval between clock ticks.
+    pub fn hz_from_tick(self, tick_us: u32) -> f32 {
+        let cycle_us = tick_us.saturating_mul(self.ticks);
+        if cycle_us == 0 {
+            return 0.0;
+        }
+
+        1_000_000.0 / cycle_us as f32
+    }
+
+    /// Division closest to `hz` at `bpm`, with the rate relative to it (1.0 is
+    /// exact). `None` without a usable tempo or rate.
+    pub fn nearest(hz: f32, bpm: f32) -> Option<(Division, f32)> {
+        if !(hz > 0.0 && bpm > 0.0) {
+            return None;
+        }
+
+        let mut best = None;
+        let mut best_error = f32::INFINITY;
+        for &div in DIVISIONS.iter() {
+            let ratio = hz / div.hz(bpm);
+            // Compare in the log domain so faster and slower count the same
+            let error = if ratio >= 1.0 { ratio } else { 1.0 / ratio };
+            if error < best_error {
+                best_error = error;
+                best = Some((div, ratio));
+            }
+        }
+        best
+    }
+}
+
+/// Gap between clock ticks after which the clock counts as stopped, 24 ppqn at
+/// 20 BPM.
+const STOPPED_US: u32 = 125_000;
+
+/// Follows an incoming 24 ppqn clock, keeping the interval between ticks
+/// smoothed over about a beat so jitter doesn't wobble the rate.
+pub struct Follower {
+    last: Option<u32>,
+    tick_us: u32,
+}
+
+impl Follower {
+    pub const fn new() -> Self {
+        Follower {
+            last: None,
+            tick_us: 0,
+        }
+    }
+
+    /// Registers a clock tick at `now_us` and returns the smoothed tick
+    /// interval, re-derived on every tick.
+    pub fn tick(&mut self, now_us: u32) -> Option<u32> {
+        let last = self.last.replace(now_us)?;
+        let interval = now_us.wrapping_sub(last);
+        if interval > STOPPED_US {
+            self.tick_us = 0;
+            return None;
+        }
+
+        self.tick_us = if self.tick_us == 0 {
+            interval
+        } else {
+            let delta = interval as i64 - self.tick_us as i64;
+            (self.tick_us as i64 + delta / PPQN as i64) as u32
+        };
+        Some(self.tick_us)
+    }
+
+    /// Clock stopped: the next tick starts measuring afresh.
+    pub fn stop(&mut self) {
+        self.last = None;
+        self.tick_us = 0;
+    }
+}
+
+impl Default for Follower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
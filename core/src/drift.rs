@@ -0,0 +1,66 @@
+//! Analog-style pitch drift: a bounded random walk, pulled back towards the
+//! true pitch and smoothed, so the oscillator wanders the way a warm VCO does
+//! instead of sitting dead on.
+//!
+//! Updated from the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Largest move of the walk per update, as a fraction of its range, for the
+/// publish rate of about 3 kHz. The walk spreads over about half its range in
+/// a couple of seconds.
+const JUMP: f32 = 0.011;
+
+/// Time constant of the pull back towards the true pitch, which keeps the
+/// walk from sitting at either bound.
+const RETURN_US: f32 = 4_000_000.0;
+
+/// Time constant of the low-pass over the walk, which leaves only the slow
+/// movement.
+const SMOOTH_US: f32 = 500_000.0;
+
+pub struct Drift {
+    rng: u32,
+    walk: f32,
+    smooth: f32,
+}
+
+impl Drift {
+    pub const fn new() -> Self {
+        Drift {
+            // Any non-zero seed runs through the full 2^32 - 1 sequence
+            rng: 0x9e37_79b9,
+            walk: 0.0,
+            smooth: 0.0,
+        }
+    }
+
+    /// Advances the drift by one update of `step_us` and returns the offset
+    /// to add to the pitch, within `depth_mv` either way.
+    pub fn update(&mut self, depth_mv: f32, step_us: f32) -> f32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        // Uniform in -1..1
+        let white = (x as i32) as f32 / 2_147_483_648.0;
+
+        let pull = self.walk * (step_us / RETURN_US);
+        self.walk = (self.walk + white * JUMP - pull).clamp(-1.0, 1.0);
+        let k = (step_us / SMOOTH_US).clamp(0.0, 1.0);
+        self.smooth += (self.walk - self.smooth) * k;
+        self.smooth * depth_mv
+    }
+}
+
+impl Default for Drift {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,120 @@
+//! Rotary encoder decoding.
+
+/// Both phases high, the resting position of a detented encoder with pull-ups.
+const REST: u8 = 0b11;
+/// Valid transitions per detent for full-cycle encoders.
+const STEPS_PER_DETENT: i8 = 4;
+/// Contact bounce window: a phase toggling again sooner than this is ignored.
+const DEBOUNCE_US: u32 = 1_000;
+
+/// Quadrature transition table indexed by `(previous << 2) | current`, where
+/// each state is `(a << 1) | b`. Impossible transitions (both phases changed)
+/// count as zero, so a missed edge can't be mistaken for a step.
+const TRANSITIONS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// 4-state quadrature decoder fed with the phase levels and a cycle count
+/// timestamp on every edge of either phase.
+pub struct Quadrature {
+    debounce: u32,
+    state: u8,
+    acc: i8,
+    last_phase: u8,
+    last_edge: u32,
+}
+
+impl Quadrature {
+    pub const fn new(cycles_per_us: u32) -> Self {
+        Quadrature {
+            debounce: DEBOUNCE_US * cycles_per_us,
+            state: REST,
+            acc: 0,
+            last_phase: 0,
+            last_edge: 0,
+        }
+    }
+
+    /// Feeds the current phase levels sampled at cycle count `now` and returns
+    /// the number of detents completed, positive when B leads A.
+    pub fn update(&mut self, a: bool, b: bool, now: u32) -> i8 {
+        let state = ((a as u8) << 1) | b as u8;
+        let phase = self.state ^ state;
+
+        if phase == 0 {
+            // Bounce settled back to the accepted state
+            return 0;
+        }
+
+        // A real rotation alternates phases, the same phase toggling again within
+        // the window is contact bounce.
+        if phase == self.last_phase && now.wrapping_sub(self.last_edge) < self.debounce {
+            return 0;
+        }
+
+        if phase == 0b11 {
+            // Both phases changed at once: an edge was missed and the direction
+            // is unknown, so resynchronise instead of letting steps pile up.
+            self.acc = 0;
+        } else {
+            let index = ((self.state << 2) | state) as usize;
+            self.acc += TRANSITIONS[index];
+        }
+
+        self.state = state;
+        self.last_phase = phase;
+        self.last_edge = now;
+
+        if state != REST {
+            return 0;
+        }
+
+        // Back at rest: whole detents count, partial moves (bounce) are dropped.
+        let detents = self.acc / STEPS_PER_DETENT;
+        self.acc = 0;
+        detents
+    }
+}
+
+/// Detent interval at or above which every detent moves a single step.
+const ACCEL_SLOW_US: u32 = 40_000;
+/// Upper bound for the step multiplier on fast turns.
+const ACCEL_MAX: u32 = 16;
+
+/// Velocity-sensitive step scaling: slow turns move one step per detent,
+/// faster turns progressively more.
+pub struct Acceleration {
+    cycles_per_us: u32,
+    last: u32,
+    interval_us: u32,
+    dir: i8,
+}
+
+impl Acceleration {
+    pub const fn new(cycles_per_us: u32) -> Self {
+        Acceleration {
+            cycles_per_us,
+            last: 0,
+            interval_us: ACCEL_SLOW_US,
+            dir: 0,
+        }
+    }
+
+    /// Returns the number of steps to apply for `detents` reported at cycle
+    /// count `now`.
+    pub fn steps(&mut self, detents: i8, now: u32) -> i16 {
+        let dir = detents.signum();
+        let elapsed_us = now.wrapping_sub(self.last) / self.cycles_per_us;
+        self.last = now;
+
+        if dir != self.dir {
+            // Reversing starts slow again so corrections near the target stay fine.
+            self.dir = dir;
+            self.interval_us = ACCEL_SLOW_US;
+        } else {
+            // Smooth over detents so a single early edge doesn't jump the multiplier.
+            self.interval_us = (self.interval_us + elapsed_us.min(ACCEL_SLOW_US)) / 2;
+        }
+
+        let multiplier = (ACCEL_SLOW_US / self.interval_us.max(1)).clamp(1, ACCEL_MAX);
+        detents as i16 * multiplier as i16
+    }
+}
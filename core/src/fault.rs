@@ -0,0 +1,72 @@
+//! Latched fault flags, with a count of how often each one was raised.
+
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum Fault {
+    /// An ADC conversion failed.
+    Adc = 1 << 0,
+    /// The power-on self-test found a problem.
+    SelfTest = 1 << 1,
+    /// The supply sagged below the brown-out warning threshold.
+    Supply = 1 << 2,
+    /// A pitch CV input sat at a rail or on one code.
+    CvInput = 1 << 3,
+    /// The clocks came out different from the board's constants.
+    Clock = 1 << 4,
+}
+
+impl Fault {
+    fn index(self) -> usize {
+        (self as u8).trailing_zeros() as usize
+    }
+}
+
+const KINDS: usize = 5;
+
+/// Faults raised since boot, shared by reference between tasks.
+pub struct Faults {
+    latched: AtomicU8,
+    counts: [AtomicU32; KINDS],
+}
+
+impl Faults {
+    pub const fn new() -> Self {
+        Faults {
+            latched: AtomicU8::new(0),
+            counts: [
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+                AtomicU32::new(0),
+            ],
+        }
+    }
+
+    /// Latches and counts `fault`, returning whether it is new since boot.
+    pub fn raise(&self, fault: Fault) -> bool {
+        if let Some(count) = self.counts.get(fault.index()) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latched.fetch_or(fault as u8, Ordering::Relaxed) & fault as u8 == 0
+    }
+
+    pub fn any(&self) -> bool {
+        self.latched.load(Ordering::Relaxed) != 0
+    }
+
+    /// Times `fault` was raised since boot, wrapping.
+    pub fn count(&self, fault: Fault) -> u32 {
+        self.counts
+            .get(fault.index())
+            .map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,139 @@
+//! Glitch filter for the sync inputs.
+//!
+//! Runs inside the sync interrupt, on edges from whatever is patched in.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Shortest lockout, for ringing on the cable whatever the output does.
+pub const MIN_HOLDOFF_US: u32 = 5;
+
+/// Longest lockout, so a slow output still takes a fast clock.
+pub const MAX_HOLDOFF_US: u32 = 1000;
+
+/// Share of the output period an edge locks out, as a shift: a sixteenth,
+/// short enough that dividing the input clock by 8 still sees every edge.
+const HOLDOFF_SHIFT: u32 = 4;
+
+/// Lockout in cycles of `clock_hz` for an output stepping by `step` every
+/// tick of `tick_hz`: a share of its period, within [`MIN_HOLDOFF_US`] and
+/// [`MAX_HOLDOFF_US`]. A stopped output gets the longest.
+pub fn holdoff(step: u32, tick_hz: u32, clock_hz: u32) -> u32 {
+    let per_us = clock_hz / 1_000_000;
+    let min = MIN_HOLDOFF_US.saturating_mul(per_us);
+    let max = MAX_HOLDOFF_US.saturating_mul(per_us);
+    let period = ((clock_hz as u64) << 32)
+        .checked_div((step as u64).saturating_mul(tick_hz as u64))
+        .unwrap_or(u64::MAX);
+    (period >> HOLDOFF_SHIFT).clamp(min as u64, max as u64) as u32
+}
+
+/// Rejects edges that come too soon after the last accepted one, or whose
+/// level is already gone by the time the interrupt runs: ringing on long
+/// patch cables and spikes from neighbouring modules.
+pub struct GlitchFilter {
+    last: Option<u32>,
+    holdoff: u32,
+}
+
+impl GlitchFilter {
+    /// Accepts at most one edge per `holdoff` cycles.
+    pub const fn new(holdoff: u32) -> Self {
+        GlitchFilter {
+            last: None,
+            holdoff,
+        }
+    }
+
+    /// Changes the lockout from the next edge on, as the output period moves.
+    pub fn set_holdoff(&mut self, holdoff: u32) {
+        self.holdoff = holdoff;
+    }
+
+    /// `settled` tells whether the input still sits at the level the edge
+    /// went to.
+    pub fn accept(&mut self, now: u32, settled: bool) -> bool {
+        if !settled {
+            return false;
+        }
+        if let Some(last) = self.last {
+            if now.wrapping_sub(last) < self.holdoff {
+                return false;
+            }
+        }
+
+        self.last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_settled_edge_is_accepted() {
+        assert!(GlitchFilter::new(100).accept(0, true));
+        assert!(GlitchFilter::new(100).accept(u32::MAX, true));
+    }
+
+    #[test]
+    fn edge_gone_by_the_interrupt_is_rejected() {
+        let mut filter = GlitchFilter::new(100);
+        assert!(!filter.accept(0, false));
+        // And doesn't start the holdoff either
+        assert!(filter.accept(1, true));
+    }
+
+    #[test]
+    fn holdoff_boundary() {
+        let mut filter = GlitchFilter::new(100);
+        assert!(filter.accept(1000, true));
+        assert!(!filter.accept(1099, true));
+        assert!(filter.accept(1100, true));
+    }
+
+    #[test]
+    fn holdoff_across_the_cycle_counter_wrap() {
+        let mut filter = GlitchFilter::new(100);
+        assert!(filter.accept(u32::MAX - 49, true));
+        assert!(!filter.accept(49, true));
+        assert!(filter.accept(50, true));
+    }
+
+    #[test]
+    fn holdoff_is_a_sixteenth_of_the_period() {
+        // 1 kHz at 200 kHz ticks, a 30 000 cycle period at 30 MHz
+        let step = (1u64 << 32) / 200;
+        assert_eq!(holdoff(step as u32, 200_000, 30_000_000), 1875);
+    }
+
+    #[test]
+    fn holdoff_bounds() {
+        assert_eq!(holdoff(0, 200_000, 30_000_000), 30_000);
+        assert_eq!(holdoff(1, 200_000, 30_000_000), 30_000);
+        assert_eq!(holdoff(u32::MAX, 200_000, 30_000_000), 150);
+        assert_eq!(holdoff(1 << 31, 200_000, 72_000_000), 360);
+        assert_eq!(holdoff(1 << 20, 0, 30_000_000), 30_000);
+    }
+
+    #[test]
+    fn new_holdoff_applies_to_the_next_edge() {
+        let mut filter = GlitchFilter::new(100);
+        assert!(filter.accept(0, true));
+        filter.set_holdoff(10);
+        assert!(filter.accept(10, true));
+    }
+
+    #[test]
+    fn no_holdoff_passes_every_settled_edge() {
+        let mut filter = GlitchFilter::new(0);
+        for _ in 0..3 {
+            assert!(filter.accept(7, true));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Sanity checks on the raw pitch CV samples: a reading pinned at either rail
+//! or frozen on one code is a broken jack or a stuck converter rather than a
+//! CV, and the pitch shouldn't chase it.
+//!
+//! Runs inside the measurement interrupt, on every raw sample.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Full-scale ADC reading.
+const FULL: u16 = 4095;
+
+/// Codes from either rail that count as pinned to it.
+const RAIL_MARGIN: u16 = 8;
+
+/// Samples in a row at a rail before the input counts as broken, about 10 ms
+/// at the full measurement rate.
+const RAILED_SAMPLES: u16 = 1024;
+
+/// Samples in a row on exactly one code before the converter counts as
+/// stuck, about 40 ms. A live input always has a code or two of noise.
+const STUCK_SAMPLES: u16 = 4096;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Health {
+    Good,
+    /// At a rail, not for long enough to call it broken yet.
+    Suspect,
+    /// At a rail for [`RAILED_SAMPLES`] and more.
+    Saturated,
+    /// On one code for [`STUCK_SAMPLES`] and more.
+    Stuck,
+}
+
+impl Health {
+    /// Whether the sample shouldn't move the pitch.
+    pub fn is_bad(self) -> bool {
+        self != Health::Good
+    }
+
+    /// Whether the input is broken, rather than passing a rail.
+    pub fn is_fault(self) -> bool {
+        matches!(self, Health::Saturated | Health::Stuck)
+    }
+}
+
+/// Follows the samples of one input. Recovers by itself as soon as they move
+/// off the rail or off the stuck code.
+pub struct Monitor {
+    last: u16,
+    repeats: u16,
+    railed: u16,
+}
+
+impl Monitor {
+    pub const fn new() -> Self {
+        Monitor {
+            last: 0,
+            repeats: 0,
+            railed: 0,
+        }
+    }
+
+    /// Feeds one sample of the input.
+    pub fn check(&mut self, sample: u16) -> Health {
+        self.repeats = if sample == self.last {
+            self.repeats.saturating_add(1)
+        } else {
+            0
+        };
+        self.last = sample;
+
+        let at_rail = sample <= RAIL_MARGIN || sample >= FULL.saturating_sub(RAIL_MARGIN);
+        self.railed = if at_rail {
+            self.railed.saturating_add(1)
+        } else {
+            0
+        };
+
+        if self.railed >= RAILED_SAMPLES {
+            Health::Saturated
+        } else if at_rail {
+            Health::Suspect
+        } else if self.repeats >= STUCK_SAMPLES {
+            Health::Stuck
+        } else {
+            Health::Good
+        }
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `sample` `count` times and returns the last verdict.
+    fn feed(monitor: &mut Monitor, sample: u16, count: u16) -> Health {
+        let mut health = Health::Good;
+        for _ in 0..count {
+            health = monitor.check(sample);
+        }
+        health
+    }
+
+    #[test]
+    fn rail_margin_edges() {
+        let mut monitor = Monitor::new();
+        assert_eq!(monitor.check(RAIL_MARGIN), Health::Suspect);
+        assert_eq!(monitor.check(RAIL_MARGIN + 1), Health::Good);
+        assert_eq!(monitor.check(FULL - RAIL_MARGIN - 1), Health::Good);
+        assert_eq!(monitor.check(FULL - RAIL_MARGIN), Health::Suspect);
+        assert_eq!(monitor.check(FULL), Health::Suspect);
+    }
+
+    #[test]
+    fn railed_input_turns_into_a_fault_and_recovers() {
+        let mut monitor = Monitor::new();
+        assert_eq!(feed(&mut monitor, 0, RAILED_SAMPLES - 1), Health::Suspect);
+        assert_eq!(monitor.check(0), Health::Saturated);
+        assert!(monitor.check(FULL).is_fault());
+        assert_eq!(monitor.check(2048), Health::Good);
+    }
+
+    #[test]
+    fn frozen_code_turns_into_a_fault_and_recovers() {
+        let mut monitor = Monitor::new();
+        assert_eq!(feed(&mut monitor, 2048, STUCK_SAMPLES), Health::Good);
+        assert_eq!(monitor.check(2048), Health::Stuck);
+        assert_eq!(monitor.check(2049), Health::Good);
+    }
+
+    #[test]
+    fn counts_saturate_instead_of_wrapping() {
+        let mut monitor = Monitor::new();
+        assert_eq!(feed(&mut monitor, 0, u16::MAX), Health::Saturated);
+        assert_eq!(feed(&mut monitor, 0, 10), Health::Saturated);
+        let mut monitor = Monitor::new();
+        assert_eq!(feed(&mut monitor, 1000, u16::MAX), Health::Stuck);
+        assert_eq!(feed(&mut monitor, 1000, 10), Health::Stuck);
+    }
+}
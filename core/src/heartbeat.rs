@@ -0,0 +1,56 @@
+//! Heartbeats for the watchdog supervisor: every task that must keep running
+//! sets its flag, and the watchdog is only fed once all of them have, so a
+//! wedged interrupt or a runaway loop anywhere ends in a reset.
+//!
+//! Beats come from the tick at the highest priority.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy)]
+pub enum Beat {
+    Tick,
+    Measure,
+    /// The idle loop, which stops beating if any task hogs the CPU.
+    Idle,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DEAD: AtomicBool = AtomicBool::new(false);
+
+pub struct Heartbeats([AtomicBool; 3]);
+
+impl Heartbeats {
+    pub const fn new() -> Self {
+        Heartbeats([DEAD; 3])
+    }
+
+    /// A plain store, cheap enough for every tick.
+    pub fn beat(&self, beat: Beat) {
+        if let Some(flag) = self.0.get(beat as usize) {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether every task beat since the last check, starting the next round.
+    pub fn check(&self) -> bool {
+        // Every flag is taken, even after a missing one
+        let mut alive = true;
+        for flag in self.0.iter() {
+            alive &= flag.swap(false, Ordering::Relaxed);
+        }
+        alive
+    }
+}
+
+impl Default for Heartbeats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
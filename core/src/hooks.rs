@@ -0,0 +1,21 @@
+//! Extension points for forks building custom modules on this firmware.
+//!
+//! The core calls these at fixed points, and every method defaults to doing
+//! nothing. Implement them in `custom.rs` instead of patching the tasks, so a
+//! fork only carries that one file across rebases.
+
+use crate::params::Params;
+
+pub trait Hooks: Sync {
+    /// A new pitch was published, from the publish task at the lowest
+    /// priority, every 320 µs. Keep it short: the next reading waits behind
+    /// it, and one that comes in while another is already waiting is dropped.
+    fn on_pitch_update(&self, _pitch_mv: f32, _params: &Params) {}
+
+    /// Hard sync edge, from the sync interrupt.
+    fn on_sync(&self) {}
+
+    /// The main oscillator started a new cycle, from the tick interrupt at the
+    /// highest priority. Anything beyond a few instructions costs audio timing.
+    fn on_cycle_wrap(&self) {}
+}
@@ -0,0 +1,122 @@
+//! I2C follower interface, monome II style: a leader on the bus writes a
+//! register byte followed by a 16-bit value, or writes the register byte and
+//! reads the value back. Values are signed and big-endian.
+//!
+//! | Register | Value                          |
+//! |----------|--------------------------------|
+//! | `00`     | Pitch offset in mV, ±10000     |
+//! | `01`     | `dac` page, the DAC waveform   |
+//! | `02`     | `sync` page, the sync flavour  |
+//! | `03`     | `fine` page, fine tune in mV   |
+//! | `10`     | Pitch in mV, read only         |
+//!
+//! Runs inside the I2C event interrupt, on bytes from any controller on the
+//! bus.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// 7-bit bus address.
+pub const ADDRESS: u8 = 0x5d;
+
+/// Largest pitch offset either way.
+pub const MAX_OFFSET_MV: i16 = 10_000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Register {
+    PitchOffset,
+    Wave,
+    Sync,
+    FineTune,
+    Pitch,
+}
+
+impl Register {
+    fn from_u8(reg: u8) -> Option<Self> {
+        match reg {
+            0x00 => Some(Register::PitchOffset),
+            0x01 => Some(Register::Wave),
+            0x02 => Some(Register::Sync),
+            0x03 => Some(Register::FineTune),
+            0x10 => Some(Register::Pitch),
+            _ => None,
+        }
+    }
+
+    pub fn writable(self) -> bool {
+        self != Register::Pitch
+    }
+}
+
+/// Follower side of one transfer at a time.
+pub struct Responder {
+    /// Register selected by the last write, kept for reads after a stop.
+    reg: Option<Register>,
+    /// Bytes received since the address, register byte included.
+    received: usize,
+    value: [u8; 2],
+    /// Bytes sent since the address.
+    sent: usize,
+}
+
+impl Responder {
+    pub const fn new() -> Self {
+        Responder {
+            reg: None,
+            received: 0,
+            value: [0; 2],
+            sent: 0,
+        }
+    }
+
+    /// Our address was matched, for either direction.
+    pub fn start(&mut self) {
+        self.received = 0;
+        self.sent = 0;
+    }
+
+    pub fn receive(&mut self, byte: u8) {
+        match self.received {
+            0 => self.reg = Register::from_u8(byte),
+            n => {
+                if let Some(slot) = self.value.get_mut(n.wrapping_sub(1)) {
+                    *slot = byte;
+                }
+            }
+        }
+        self.received = self.received.saturating_add(1);
+    }
+
+    /// Next byte of a read of the selected register. `read` is called for the
+    /// first byte only, so both halves come from the same value. Past them,
+    /// and for unknown registers, the leader gets zeros.
+    pub fn transmit(&mut self, read: impl FnOnce(Register) -> i16) -> u8 {
+        if self.sent == 0 {
+            self.value = self.reg.map_or([0; 2], |reg| read(reg).to_be_bytes());
+        }
+        let byte = self.value.get(self.sent).copied().unwrap_or(0);
+        self.sent = self.sent.saturating_add(1);
+        byte
+    }
+
+    /// End of the transfer: a complete write to a writable register.
+    pub fn stop(&mut self) -> Option<(Register, i16)> {
+        let received = core::mem::replace(&mut self.received, 0);
+        match self.reg {
+            Some(reg) if received == 3 && reg.writable() => {
+                Some((reg, i16::from_be_bytes(self.value)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
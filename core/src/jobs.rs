@@ -0,0 +1,207 @@
+//! Long-running procedures advanced from idle in short slices.
+//!
+//! A job is a state machine that does a bounded amount of work per `step` and
+//! keeps its progress in itself, so sweeps and table rebuilds can run for
+//! seconds while every interrupt keeps its timing.
+
+use crate::params::{Param, Params};
+use crate::pitch::Override;
+
+pub enum Status {
+    Running,
+    Done,
+}
+
+pub trait Job {
+    /// Advances by one slice at cycle count `now`. Must return within a few
+    /// hundred microseconds.
+    fn step(&mut self, now: u32) -> Status;
+}
+
+/// Runs at most one job at a time.
+pub struct Runner<'a> {
+    job: Option<&'a mut dyn Job>,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new() -> Self {
+        Runner { job: None }
+    }
+
+    pub fn start(&mut self, job: &'a mut dyn Job) {
+        self.job = Some(job);
+    }
+
+    pub fn poll(&mut self, now: u32) {
+        if let Some(job) = self.job.as_mut() {
+            if let Status::Done = job.step(now) {
+                self.job = None;
+            }
+        }
+    }
+}
+
+impl<'a> Default for Runner<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `true` once the cycle counter has reached `deadline`, across wrap-around.
+pub fn reached(now: u32, deadline: u32) -> bool {
+    now.wrapping_sub(deadline) as i32 >= 0
+}
+
+const BURNIN_LOW_MV: i32 = 0;
+const BURNIN_HIGH_MV: i32 = 8000;
+const BURNIN_STEP_MV: i32 = 1000;
+
+/// Burn-in sweep: holds every octave across the range for a while, for
+/// exercising freshly built modules.
+pub struct Burnin<'a> {
+    pitch: &'a Override,
+    dwell: u32,
+    sweeps: u16,
+    mv: i32,
+    next: u32,
+    started: bool,
+}
+
+impl<'a> Burnin<'a> {
+    pub fn new(pitch: &'a Override, dwell: u32, sweeps: u16) -> Self {
+        Burnin {
+            pitch,
+            dwell,
+            sweeps,
+            mv: BURNIN_LOW_MV,
+            next: 0,
+            started: false,
+        }
+    }
+}
+
+impl<'a> Job for Burnin<'a> {
+    fn step(&mut self, now: u32) -> Status {
+        if self.started && !reached(now, self.next) {
+            return Status::Running;
+        }
+
+        if self.sweeps == 0 {
+            self.pitch.set(None);
+            return Status::Done;
+        }
+
+        self.pitch.set(Some(self.mv));
+        self.started = true;
+        self.next = now.wrapping_add(self.dwell);
+
+        self.mv += BURNIN_STEP_MV;
+        if self.mv > BURNIN_HIGH_MV {
+            self.mv = BURNIN_LOW_MV;
+            self.sweeps -= 1;
+        }
+
+        Status::Running
+    }
+}
+
+/// [`Param::TestSignal`] values.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Signal {
+    Off,
+    /// Rises from low to high at a constant rate in mV/oct, so exponentially
+    /// in frequency.
+    Sweep,
+    /// Holds every octave between low and high in turn.
+    Steps,
+    /// Sweeps in an eighth of the duration, then rests at low.
+    Chirp,
+}
+
+impl Signal {
+    fn from_param(value: i32) -> Self {
+        match value {
+            1 => Signal::Sweep,
+            2 => Signal::Steps,
+            3 => Signal::Chirp,
+            _ => Signal::Off,
+        }
+    }
+}
+
+const TEST_OCTAVE_MV: i32 = 1000;
+const CHIRP_FRACTION: u32 = 8;
+
+/// Test signal generator: drives the pitch from the signal picked on the
+/// menu, ignoring the CV, for testing downstream filters and VCAs.
+pub struct TestSignal<'a> {
+    pitch: &'a Override,
+    params: &'a Params,
+    low_mv: i32,
+    high_mv: i32,
+    duration: u32,
+    signal: Signal,
+    start: u32,
+}
+
+impl<'a> TestSignal<'a> {
+    /// Repeats every `duration` cycles between `low_mv` and `high_mv`.
+    pub fn new(
+        pitch: &'a Override,
+        params: &'a Params,
+        low_mv: i32,
+        high_mv: i32,
+        duration: u32,
+    ) -> Self {
+        TestSignal {
+            pitch,
+            params,
+            low_mv,
+            high_mv: high_mv.max(low_mv),
+            duration: duration.max(CHIRP_FRACTION),
+            signal: Signal::Off,
+            start: 0,
+        }
+    }
+
+    /// Ramp from low to high over `length` cycles, `elapsed` into it.
+    fn ramp(&self, elapsed: u32, length: u32) -> i32 {
+        let span = (self.high_mv - self.low_mv) as i64;
+        self.low_mv + (span * elapsed as i64 / length.max(1) as i64) as i32
+    }
+}
+
+impl<'a> Job for TestSignal<'a> {
+    fn step(&mut self, now: u32) -> Status {
+        let signal = Signal::from_param(self.params.get(Param::TestSignal));
+        if signal != self.signal {
+            self.signal = signal;
+            self.start = now;
+            if signal == Signal::Off {
+                self.pitch.set(None);
+            }
+        }
+
+        let elapsed = now.wrapping_sub(self.start) % self.duration;
+        let mv = match self.signal {
+            Signal::Off => return Status::Running,
+            Signal::Sweep => self.ramp(elapsed, self.duration),
+            Signal::Steps => {
+                let steps = ((self.high_mv - self.low_mv) / TEST_OCTAVE_MV + 1) as u32;
+                let step = elapsed / (self.duration / steps).max(1);
+                self.low_mv + step.min(steps - 1) as i32 * TEST_OCTAVE_MV
+            }
+            Signal::Chirp => {
+                let length = self.duration / CHIRP_FRACTION;
+                if elapsed < length {
+                    self.ramp(elapsed, length)
+                } else {
+                    self.low_mv
+                }
+            }
+        };
+        self.pitch.set(Some(mv));
+
+        Status::Running
+    }
+}
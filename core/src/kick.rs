@@ -0,0 +1,74 @@
+//! Kick drum mode: every trigger starts a pitch sweep that falls back
+//! exponentially from above the base pitch, the classic analog kick and tom.
+//!
+//! Fired from the sync and MIDI interrupts and swept from the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A trigger waiting for the next publish, shared by reference.
+pub struct Trigger {
+    pending: AtomicBool,
+}
+
+impl Trigger {
+    pub const fn new() -> Self {
+        Trigger {
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    pub fn fire(&self) {
+        self.pending.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a trigger came in since the last call.
+    pub fn take(&self) -> bool {
+        self.pending.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The sweep itself, owned by the publish task.
+pub struct Sweep {
+    offset_mv: f32,
+}
+
+impl Sweep {
+    pub const fn new() -> Self {
+        Sweep { offset_mv: 0.0 }
+    }
+
+    /// Advances the sweep by one publish of `step_us`, restarting it at
+    /// `depth_mv` on a trigger, and returns the offset to add to the pitch.
+    /// `decay_ms` is the time constant: the offset falls to about a third in
+    /// that time.
+    pub fn update(&mut self, triggered: bool, depth_mv: f32, decay_ms: f32, step_us: f32) -> f32 {
+        if triggered {
+            self.offset_mv = depth_mv;
+        }
+        let offset = self.offset_mv;
+        // One step of exp(-t / decay) to first order, as a publish is far
+        // shorter than any decay. Zero and NaN decays stop at once.
+        let k = (1.0 - step_us / (decay_ms * 1000.0)).max(0.0);
+        self.offset_mv *= k;
+        offset
+    }
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
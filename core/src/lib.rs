@@ -0,0 +1,70 @@
+//! Oscillator core of the Oxide DCO: pitch math, the phase accumulator and
+//! sync, the CV filtering, the parameter model and the MIDI, SysEx, console
+//! and I2C protocols, with no dependency on the STM32 it runs on.
+//!
+//! The firmware binary owns the peripherals and the RTFM tasks, and reaches
+//! its hardware through a few traits: [`storage::Flash`] for the settings
+//! pages, [`dac::SpiDac`] for external converters, the `embedded-hal` I2C and
+//! pin traits for the display and readout, and [`hooks::Hooks`] for forks.
+//! Everything here also builds for the host, for tests and the fuzz targets.
+//!
+//! A panic stops every interrupt until the reset, outputs and all. Modules
+//! that run inside the interrupt handlers and the publish task, and the ones
+//! that parse what the flash holds, deny arithmetic that can overflow,
+//! indexing and unwrapping at the top, and their headers say where they run.
+#![no_std]
+
+pub mod amp;
+pub mod arp;
+pub mod button;
+pub mod calibrate;
+pub mod capture;
+pub mod chord;
+pub mod cli;
+pub mod config;
+pub mod crc;
+pub mod custom;
+pub mod cv_out;
+pub mod dac;
+pub mod display;
+pub mod division;
+pub mod drift;
+pub mod encoder;
+pub mod fault;
+pub mod glitch;
+pub mod health;
+pub mod heartbeat;
+pub mod hooks;
+pub mod ii;
+pub mod jobs;
+pub mod kick;
+pub mod matrix;
+pub mod midi;
+pub mod noise;
+pub mod note;
+pub mod osc;
+pub mod outbox;
+pub mod params;
+pub mod pitch;
+pub mod pll;
+pub mod poly;
+pub mod post;
+pub mod preset;
+pub mod profile;
+pub mod recorder;
+pub mod sampling;
+pub mod segments;
+pub mod settings;
+pub mod sleep;
+pub mod source;
+pub mod storage;
+pub mod sysex;
+pub mod tap;
+pub mod trigger;
+pub mod vibrato;
+pub mod voice;
+pub mod watch;
+pub mod wave;
+pub mod wide;
+pub mod ws2812;
+pub mod xmodem;
@@ -0,0 +1,77 @@
+//! Modulation matrix for the spare CV inputs: every input is routed to one
+//! destination through an attenuverter, and what arrives at each destination
+//! is summed.
+//!
+//! Routed from the publish task, with amounts from the menu and sources from
+//! the spare CV inputs.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::params::{MOD_AMP, MOD_DETUNE, MOD_GLIDE, MOD_PITCH, MOD_PW, MOD_VIBRATO};
+
+/// Full scale of each destination, what a full-scale input at 100% adds.
+pub const PW_RANGE_PCT: f32 = 45.0;
+pub const GLIDE_RANGE_MS: f32 = 2000.0;
+pub const DETUNE_RANGE_CENTS: f32 = 100.0;
+pub const VIBRATO_RANGE_CENTS: f32 = 100.0;
+pub const PITCH_RANGE_MV: f32 = 1000.0;
+
+/// Full-scale ADC reading.
+const FULL: f32 = 4095.0;
+
+/// What the routed inputs add at every destination, as fractions of its full
+/// scale, -1 to 1 per input.
+pub struct Mods {
+    pub pw: f32,
+    pub glide: f32,
+    pub detune: f32,
+    pub vibrato: f32,
+    pub pitch: f32,
+    /// Gain on the amplitude level instead, from 0 to 1.
+    pub amp: f32,
+}
+
+impl Mods {
+    /// Nothing routed: no change anywhere.
+    pub const fn new() -> Self {
+        Mods {
+            pw: 0.0,
+            glide: 0.0,
+            detune: 0.0,
+            vibrato: 0.0,
+            pitch: 0.0,
+            amp: 1.0,
+        }
+    }
+
+    /// Routes an input reading to `dest` at `amount_pct`, negative to invert.
+    ///
+    /// The amplitude opens with a rising input at positive amounts and closes
+    /// at negative ones, like a VCA with its initial gain set to make room.
+    pub fn route(&mut self, dest: i32, amount_pct: i32, sample: u16) {
+        let v = (sample as f32 / FULL).min(1.0);
+        let amount = amount_pct.clamp(-100, 100) as f32 / 100.0;
+        let x = v * amount;
+        match dest {
+            MOD_PW => self.pw += x,
+            MOD_GLIDE => self.glide += x,
+            MOD_DETUNE => self.detune += x,
+            MOD_VIBRATO => self.vibrato += x,
+            MOD_PITCH => self.pitch += x,
+            MOD_AMP if amount >= 0.0 => self.amp *= 1.0 - amount + x,
+            MOD_AMP => self.amp *= 1.0 + x,
+            _ => {}
+        }
+    }
+}
+
+impl Default for Mods {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,472 @@
+//! MIDI input: a byte-at-a-time parser, and the note stack that turns note on
+//! and off messages into one monophonic pitch.
+//!
+//! Runs inside the serial receive interrupt, on whatever arrives at the
+//! jack.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicI16, AtomicU8, Ordering};
+
+use crate::amp::ENV_FULL;
+use crate::params::{VELOCITY_HARD, VELOCITY_LINEAR, VELOCITY_SOFT};
+
+/// MIDI serial rate.
+pub const BAUD: u32 = 31_250;
+
+/// Messages the DCO acts on.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Message {
+    /// Velocity from 1 to 127.
+    NoteOn {
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        note: u8,
+    },
+    /// 7-bit controller value.
+    ControlChange {
+        control: u8,
+        value: u8,
+    },
+    ProgramChange {
+        program: u8,
+    },
+    /// Bend from -8192 to 8191, centred at zero.
+    PitchBend {
+        bend: i16,
+    },
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+/// Splits the byte stream into messages, with running status. Real-time bytes
+/// may arrive in the middle of another message and don't disturb it.
+pub struct Parser {
+    /// Running status, `None` after system common messages and at power-up.
+    status: Option<u8>,
+    /// First data byte of a two-byte message.
+    first: Option<u8>,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            status: None,
+            first: None,
+        }
+    }
+
+    /// Feeds one received byte. Channel messages for other channels than
+    /// `channel` (0-based, `None` for omni) are dropped.
+    pub fn feed(&mut self, byte: u8, channel: Option<u8>) -> Option<Message> {
+        match byte {
+            0xf8 => return Some(Message::Clock),
+            0xfa => return Some(Message::Start),
+            0xfb => return Some(Message::Continue),
+            0xfc => return Some(Message::Stop),
+            0xf9..=0xff => return None,
+            // System common and SysEx: their data bytes are dropped
+            0xf0..=0xf7 => {
+                self.status = None;
+                self.first = None;
+                return None;
+            }
+            0x80..=0xef => {
+                self.status = Some(byte);
+                self.first = None;
+                return None;
+            }
+            _ => {}
+        }
+
+        let status = self.status?;
+        let kind = status & 0xf0;
+        let first = if data_bytes(kind) == 2 {
+            match self.first.take() {
+                Some(first) => first,
+                None => {
+                    self.first = Some(byte);
+                    return None;
+                }
+            }
+        } else {
+            byte
+        };
+        if matches!(channel, Some(channel) if channel != status & 0x0f) {
+            return None;
+        }
+
+        match kind {
+            0x90 if byte > 0 => Some(Message::NoteOn {
+                note: first,
+                velocity: byte,
+            }),
+            // Note on with zero velocity is a note off, for running status
+            0x80 | 0x90 => Some(Message::NoteOff { note: first }),
+            0xb0 => Some(Message::ControlChange {
+                control: first,
+                value: byte,
+            }),
+            0xc0 => Some(Message::ProgramChange { program: first }),
+            0xe0 => {
+                let raw = (byte as i16) << 7 | first as i16;
+                Some(Message::PitchBend {
+                    bend: raw.wrapping_sub(BEND_CENTER),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const BEND_CENTER: i16 = 0x2000;
+
+/// Data bytes following the status of a channel message.
+fn data_bytes(kind: u8) -> u8 {
+    match kind {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+/// Notes held at once; the oldest is forgotten beyond that.
+pub const HELD: usize = 8;
+
+/// Held notes with last-note priority: releasing the sounding note goes back
+/// to the most recent one still held.
+pub struct NoteStack {
+    notes: [u8; HELD],
+    len: usize,
+}
+
+impl NoteStack {
+    pub const fn new() -> Self {
+        NoteStack {
+            notes: [0; HELD],
+            len: 0,
+        }
+    }
+
+    pub fn press(&mut self, note: u8) {
+        self.release(note);
+        if self.len == HELD {
+            self.notes.rotate_left(1);
+            self.len = HELD.saturating_sub(1);
+        }
+        if let Some(slot) = self.notes.get_mut(self.len) {
+            *slot = note;
+            self.len = self.len.saturating_add(1);
+        }
+    }
+
+    pub fn release(&mut self, note: u8) {
+        let held = self.notes.get_mut(..self.len).unwrap_or(&mut []);
+        if let Some(i) = held.iter().position(|&n| n == note) {
+            if let Some(rest) = held.get_mut(i..) {
+                rest.rotate_left(1);
+            }
+            self.len = self.len.saturating_sub(1);
+        }
+    }
+
+    /// All notes off: nothing is held, the last note still sounds.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// The sounding note, `None` with no key held.
+    pub fn current(&self) -> Option<u8> {
+        self.len
+            .checked_sub(1)
+            .and_then(|last| self.notes.get(last))
+            .copied()
+    }
+
+    /// Every held note, oldest first.
+    pub fn held(&self) -> &[u8] {
+        self.notes.get(..self.len).unwrap_or(&[])
+    }
+}
+
+impl Default for NoteStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// First channel mode controller: all sound off, then reset all controllers,
+/// local control, all notes off and the omni and mono/poly switches, which
+/// also turn the notes off.
+pub const CC_MODE: u8 = 120;
+pub const CC_RESET_CONTROLLERS: u8 = 121;
+
+/// Controllers 0-31 are the MSBs of 14-bit values, their LSBs come 32
+/// numbers higher.
+const LSB_OFFSET: u8 = 32;
+
+/// Pairs the MSB and LSB halves of controllers into 14-bit values. Senders
+/// that only send the MSB get 7-bit steps.
+pub struct Controllers {
+    msb: [u8; LSB_OFFSET as usize],
+}
+
+impl Controllers {
+    pub const fn new() -> Self {
+        Controllers {
+            msb: [0; LSB_OFFSET as usize],
+        }
+    }
+
+    /// Returns the MSB controller number a change belongs to and its 14-bit
+    /// value, `None` for controllers without an LSB. A new MSB clears the
+    /// LSB, as the spec wants.
+    pub fn change(&mut self, control: u8, value: u8) -> Option<(u8, u16)> {
+        let (cc, lsb) = match control.checked_sub(LSB_OFFSET) {
+            None => (control, None),
+            Some(cc) => (cc, Some(value)),
+        };
+        let msb = self.msb.get_mut(cc as usize)?;
+        match lsb {
+            None => {
+                *msb = value;
+                Some((cc, (value as u16) << 7))
+            }
+            Some(lsb) => Some((cc, (*msb as u16) << 7 | lsb as u16)),
+        }
+    }
+}
+
+impl Default for Controllers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// MIDI-to-CV on the 8-bit DAC: six steps a semitone, so every note lands on
+/// a whole step, and 72 an octave. An output stage gain of 1.073 turns that
+/// into 1 V/oct from a 3.3 V full scale.
+const CV_STEPS_PER_SEMITONE: f32 = 6.0;
+/// Note at 0 V, C2. The DAC reaches 42 semitones up from it.
+const CV_LOWEST_NOTE: u8 = 36;
+
+/// DAC code for `note` bent by `bend_mv`, clamped to the DAC range.
+pub fn cv_code(note: u8, bend_mv: f32) -> u8 {
+    let semitones = note.saturating_sub(CV_LOWEST_NOTE) as f32 + bend_mv / (1000.0 / 12.0);
+    ((semitones * CV_STEPS_PER_SEMITONE).clamp(0.0, 255.0) + 0.5) as u8
+}
+
+/// Gain for a note's velocity through a [`Param::Velocity`] curve, on the
+/// scale of an envelope reading: `soft` needs a harder hit for the same
+/// level than `lin`, `hard` less, and `off` always gives full scale.
+///
+/// [`Param::Velocity`]: crate::params::Param::Velocity
+pub fn velocity_gain(velocity: u8, curve: i32) -> u16 {
+    let v = velocity.min(127) as f32 / 127.0;
+    let gain = match curve {
+        VELOCITY_LINEAR => v,
+        VELOCITY_SOFT => v * v,
+        VELOCITY_HARD => 1.0 - (1.0 - v) * (1.0 - v),
+        _ => 1.0,
+    };
+    (gain * ENV_FULL as f32 + 0.5) as u16
+}
+
+/// Last note played over MIDI, its velocity and the pitch bend, shared with
+/// the publish task. The note stays set after the key is released, so the
+/// pitch doesn't drop back to the CV while an envelope is still decaying.
+pub struct Playing {
+    note: AtomicU8,
+    velocity: AtomicU8,
+    bend: AtomicI16,
+}
+
+impl Playing {
+    const NONE: u8 = u8::MAX;
+
+    pub const fn new() -> Self {
+        Playing {
+            note: AtomicU8::new(Self::NONE),
+            velocity: AtomicU8::new(127),
+            bend: AtomicI16::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Option<u8> {
+        match self.note.load(Ordering::Relaxed) {
+            Self::NONE => None,
+            note => Some(note),
+        }
+    }
+
+    pub fn set(&self, note: Option<u8>) {
+        self.note
+            .store(note.unwrap_or(Self::NONE), Ordering::Relaxed);
+    }
+
+    /// Velocity of the last note on, 127 before the first.
+    pub fn velocity(&self) -> u8 {
+        self.velocity.load(Ordering::Relaxed)
+    }
+
+    pub fn set_velocity(&self, velocity: u8) {
+        self.velocity.store(velocity, Ordering::Relaxed);
+    }
+
+    /// Pitch bend from -8192 to 8191.
+    pub fn bend(&self) -> i16 {
+        self.bend.load(Ordering::Relaxed)
+    }
+
+    pub fn set_bend(&self, bend: i16) {
+        self.bend.store(bend, Ordering::Relaxed);
+    }
+
+    /// Bend in mV/oct for a range of `semitones` either way.
+    pub fn bend_mv(&self, semitones: i32) -> f32 {
+        self.bend() as f32 / BEND_CENTER as f32 * semitones as f32 * (1000.0 / 12.0)
+    }
+}
+
+impl Default for Playing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `bytes` and returns the last message they make.
+    fn feed(parser: &mut Parser, bytes: &[u8], channel: Option<u8>) -> Option<Message> {
+        bytes
+            .iter()
+            .fold(None, |last, &b| parser.feed(b, channel).or(last))
+    }
+
+    #[test]
+    fn running_status_and_zero_velocity_note_off() {
+        let mut parser = Parser::new();
+        assert!(
+            feed(&mut parser, &[0x90, 60, 100], None)
+                == Some(Message::NoteOn {
+                    note: 60,
+                    velocity: 100
+                })
+        );
+        assert!(
+            feed(&mut parser, &[62, 90], None)
+                == Some(Message::NoteOn {
+                    note: 62,
+                    velocity: 90
+                })
+        );
+        assert!(feed(&mut parser, &[60, 0], None) == Some(Message::NoteOff { note: 60 }));
+    }
+
+    #[test]
+    fn real_time_bytes_leave_a_message_whole() {
+        let mut parser = Parser::new();
+        assert!(parser.feed(0xb0, None).is_none());
+        assert!(parser.feed(7, None).is_none());
+        assert!(parser.feed(0xf8, None) == Some(Message::Clock));
+        assert!(
+            parser.feed(64, None)
+                == Some(Message::ControlChange {
+                    control: 7,
+                    value: 64
+                })
+        );
+    }
+
+    #[test]
+    fn sysex_drops_running_status() {
+        let mut parser = Parser::new();
+        feed(&mut parser, &[0x90, 60, 100], None);
+        assert!(feed(&mut parser, &[0xf0, 0x7d, 60, 100, 0xf7], None).is_none());
+        assert!(feed(&mut parser, &[60, 100], None).is_none());
+    }
+
+    #[test]
+    fn other_channels_are_dropped() {
+        let mut parser = Parser::new();
+        assert!(feed(&mut parser, &[0x91, 60, 100], Some(0)).is_none());
+        assert!(feed(&mut parser, &[0x91, 60, 100], Some(1)).is_some());
+        assert!(feed(&mut parser, &[0xc1, 5], None) == Some(Message::ProgramChange { program: 5 }));
+    }
+
+    #[test]
+    fn pitch_bend_is_centred() {
+        let mut parser = Parser::new();
+        assert!(feed(&mut parser, &[0xe0, 0, 0x40], None) == Some(Message::PitchBend { bend: 0 }));
+        assert!(feed(&mut parser, &[0, 0], None) == Some(Message::PitchBend { bend: -8192 }));
+        assert!(feed(&mut parser, &[0x7f, 0x7f], None) == Some(Message::PitchBend { bend: 8191 }));
+    }
+
+    #[test]
+    fn release_goes_back_to_the_last_held_note() {
+        let mut notes = NoteStack::new();
+        assert_eq!(notes.current(), None);
+        notes.press(60);
+        notes.press(64);
+        notes.press(67);
+        notes.release(67);
+        assert_eq!(notes.current(), Some(64));
+        notes.release(60);
+        assert_eq!(notes.current(), Some(64));
+        notes.press(64);
+        assert_eq!(notes.held(), [64]);
+    }
+
+    #[test]
+    fn overfull_stack_forgets_the_oldest() {
+        let mut notes = NoteStack::new();
+        for note in 0..HELD as u8 + 2 {
+            notes.press(note);
+        }
+        assert_eq!(notes.held().len(), HELD);
+        assert_eq!(notes.held().first(), Some(&2));
+        assert_eq!(notes.current(), Some(HELD as u8 + 1));
+    }
+
+    #[test]
+    fn controller_lsb_follows_its_msb() {
+        let mut controllers = Controllers::new();
+        assert_eq!(controllers.change(1, 0x40), Some((1, 0x40 << 7)));
+        assert_eq!(controllers.change(33, 0x10), Some((1, 0x40 << 7 | 0x10)));
+        assert_eq!(controllers.change(64, 127), None);
+    }
+
+    #[test]
+    fn velocity_curves_meet_at_the_ends() {
+        for curve in [VELOCITY_LINEAR, VELOCITY_SOFT, VELOCITY_HARD] {
+            assert_eq!(velocity_gain(0, curve), 0);
+            assert_eq!(velocity_gain(127, curve), ENV_FULL);
+        }
+        assert!(velocity_gain(64, VELOCITY_SOFT) < velocity_gain(64, VELOCITY_LINEAR));
+        assert!(velocity_gain(64, VELOCITY_HARD) > velocity_gain(64, VELOCITY_LINEAR));
+    }
+
+    #[test]
+    fn cv_codes_land_on_whole_steps() {
+        assert_eq!(cv_code(CV_LOWEST_NOTE, 0.0), 0);
+        assert_eq!(cv_code(CV_LOWEST_NOTE + 12, 0.0), 72);
+        assert_eq!(cv_code(0, 0.0), 0);
+        assert_eq!(cv_code(127, 0.0), 255);
+    }
+}
@@ -0,0 +1,77 @@
+//! Noise sources for the DAC and the square output.
+//!
+//! Runs inside the tick interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Voss-McCartney rows, each updated half as often as the one before, for
+/// pink noise down to about 200 kHz / 2^7.
+const ROWS: usize = 7;
+
+/// Xorshift LFSR plus the running sums pink noise needs.
+pub struct Noise {
+    state: u32,
+    counter: u32,
+    rows: [u8; ROWS],
+    sum: u16,
+}
+
+impl Noise {
+    pub const fn new() -> Self {
+        Noise {
+            // Any non-zero seed runs through the full 2^32 - 1 sequence
+            state: 0x1234_5678,
+            counter: 0,
+            rows: [0; ROWS],
+            sum: 0,
+        }
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// One white noise sample for the DAC.
+    pub fn white(&mut self) -> u8 {
+        (self.next() >> 24) as u8
+    }
+
+    /// One pink noise sample for the DAC, -3 dB per octave.
+    pub fn pink(&mut self) -> u8 {
+        let random = self.next();
+        self.counter = self.counter.wrapping_add(1);
+
+        // Replace the row picked by the counter's trailing zeros
+        let row = self.counter.trailing_zeros() as usize;
+        if let Some(r) = self.rows.get_mut(row) {
+            let new = (random >> 24) as u8;
+            self.sum = self.sum.wrapping_sub(*r as u16).wrapping_add(new as u16);
+            *r = new;
+        }
+
+        // Plus a white component, averaged over rows + 1 sources
+        let white = (random & 0xff) as u16;
+        (self.sum.wrapping_add(white) >> 3) as u8
+    }
+
+    /// One random bit for 1-bit noise.
+    pub fn bit(&mut self) -> bool {
+        self.next() & (1 << 31) != 0
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
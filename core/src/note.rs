@@ -0,0 +1,74 @@
+//! Equal-tempered note naming for frequencies, and the pitch of MIDI notes.
+
+use eurorack_oxide_utils::voct::{MvOct, Voltage};
+
+const A4_HZ: f32 = 440.0;
+const A4_MIDI: i32 = 69;
+
+const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Nearest equal-tempered note to a frequency.
+#[derive(Clone, Copy)]
+pub struct Note {
+    /// MIDI note number, 60 is C4.
+    pub midi: i32,
+    /// Deviation from the note, -50 to +50 cents.
+    pub cents: f32,
+}
+
+impl Note {
+    /// Returns `None` for frequencies that aren't positive and finite.
+    pub fn from_hz(hz: f32) -> Option<Self> {
+        if !(hz > 0.0 && hz < f32::INFINITY) {
+            return None;
+        }
+
+        let semitones = 12.0 * log2(hz / A4_HZ);
+        let nearest = round(semitones);
+
+        Some(Note {
+            midi: A4_MIDI + nearest,
+            cents: (semitones - nearest as f32) * 100.0,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        NAMES[self.midi.rem_euclid(12) as usize]
+    }
+
+    pub fn octave(self) -> i32 {
+        self.midi.div_euclid(12) - 1
+    }
+}
+
+/// Pitch in mV/oct that sounds MIDI note `midi`.
+pub fn mv(midi: i32) -> f32 {
+    let a4_mv = 1000.0 * log2(A4_HZ / MvOct(0.0).hz());
+    a4_mv + (midi - A4_MIDI) as f32 * (1000.0 / 12.0)
+}
+
+/// Rounds half away from zero; `f32::round` needs `std`.
+fn round(x: f32) -> i32 {
+    if x >= 0.0 {
+        (x + 0.5) as i32
+    } else {
+        (x - 0.5) as i32
+    }
+}
+
+/// Base 2 logarithm of a positive normal float, accurate to about 0.02 cent.
+pub fn log2(x: f32) -> f32 {
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    // Mantissa in [1, 2)
+    let m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+
+    // log2(m) = 2 / ln(2) * atanh(t) with t = (m - 1) / (m + 1) in [0, 1/3)
+    let t = (m - 1.0) / (m + 1.0);
+    let t2 = t * t;
+    let atanh = t * (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 / 7.0)));
+
+    exponent as f32 + 2.0 * core::f32::consts::LOG2_E * atanh
+}
@@ -0,0 +1,478 @@
+//! Square wave oscillator advanced from the tick interrupt.
+//!
+//! Stepped at the highest priority, and reset from the sync interrupt in
+//! between.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// What the tick handler has to do with the output pin.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edge {
+    None,
+    /// Restart after a sync, the output takes the level at the new phase.
+    Reset,
+    Toggle,
+}
+
+/// Phase accumulator shared between the tick, sync and publish tasks: a
+/// 32-bit phase advanced by a tuning word per tick, with the output high once
+/// it passes the pulse width threshold.
+///
+/// New tuning words and pulse widths only take effect at the start of a
+/// cycle, so a change never cuts a pulse short or produces a runt. A tuning
+/// word can instead be slewed to over a number of ticks, which changes the
+/// pitch mid-cycle but only ever by a little per tick.
+pub struct Oscillator {
+    phase: AtomicU32,
+    step: AtomicU32,
+    pending: AtomicU32,
+    /// Change of the tuning word per tick while it slews to `pending`, as
+    /// `i32` bits.
+    slew: AtomicU32,
+    /// Ticks left until the slew reaches `pending`, zero when not slewing
+    /// and [`HOLD`] while a new one is being set up.
+    slew_ticks: AtomicU32,
+    width: AtomicU32,
+    pending_width: AtomicU32,
+    sync: AtomicBool,
+    sync_phase: AtomicU32,
+    reverse: AtomicBool,
+    /// Running backwards after a reversing sync.
+    backwards: AtomicBool,
+    /// The last tick started the waveform over at phase zero.
+    zero: AtomicBool,
+    syncs: AtomicU32,
+}
+
+const HIGH: u32 = 1 << 31;
+
+/// `slew_ticks` while the publish task sets up a slew: the tick keeps the
+/// running word, and a new cycle doesn't take `pending` either, whatever
+/// half of the slew it would see.
+const HOLD: u32 = u32::MAX;
+
+/// Soft sync only acts this close to the end of a cycle, an eighth of a turn.
+const SOFT_WINDOW: u32 = 1 << 29;
+
+/// Duty cycle limits in percent, so the pulse never disappears.
+pub const MIN_DUTY: u32 = 5;
+pub const MAX_DUTY: u32 = 95;
+
+impl Oscillator {
+    pub const fn new() -> Self {
+        Oscillator {
+            phase: AtomicU32::new(0),
+            step: AtomicU32::new(0),
+            pending: AtomicU32::new(0),
+            slew: AtomicU32::new(0),
+            slew_ticks: AtomicU32::new(0),
+            width: AtomicU32::new(HIGH),
+            pending_width: AtomicU32::new(HIGH),
+            sync: AtomicBool::new(false),
+            sync_phase: AtomicU32::new(0),
+            reverse: AtomicBool::new(false),
+            backwards: AtomicBool::new(false),
+            zero: AtomicBool::new(false),
+            syncs: AtomicU32::new(0),
+        }
+    }
+
+    pub fn tick(&self) -> Edge {
+        if self.sync.swap(false, Ordering::Relaxed) {
+            let phase = self.sync_phase.load(Ordering::Relaxed);
+            self.phase.store(phase, Ordering::Relaxed);
+            self.zero.store(phase == 0, Ordering::Relaxed);
+            self.backwards.store(false, Ordering::Relaxed);
+            self.latch();
+            return Edge::Reset;
+        }
+        if self.reverse.swap(false, Ordering::Relaxed) {
+            self.backwards.fetch_xor(true, Ordering::Relaxed);
+        }
+        let slew_ticks = self.slew_ticks.load(Ordering::Relaxed);
+        if slew_ticks > 0 && slew_ticks != HOLD {
+            // The last tick lands on the word exactly, whatever the rounding
+            let step = if slew_ticks == 1 {
+                self.pending.load(Ordering::Relaxed)
+            } else {
+                self.step
+                    .load(Ordering::Relaxed)
+                    .wrapping_add(self.slew.load(Ordering::Relaxed))
+            };
+            self.step.store(step, Ordering::Relaxed);
+            self.slew_ticks
+                .store(slew_ticks.saturating_sub(1), Ordering::Relaxed);
+        }
+
+        let step = self.step.load(Ordering::Relaxed);
+        let was_high = self.is_high();
+        let old = self.phase.load(Ordering::Relaxed);
+        let backwards = self.backwards.load(Ordering::Relaxed);
+        let new = if backwards {
+            old.wrapping_sub(step)
+        } else {
+            old.wrapping_add(step)
+        };
+        self.phase.store(new, Ordering::Relaxed);
+
+        let wrapped = (new < old) != backwards;
+        if wrapped {
+            // Wrapped: a new cycle starts
+            self.latch();
+        }
+        self.zero.store(wrapped, Ordering::Relaxed);
+
+        if self.is_high() != was_high {
+            Edge::Toggle
+        } else {
+            Edge::None
+        }
+    }
+
+    /// Hard sync: restarts the cycle on the next tick.
+    pub fn reset(&self) {
+        self.reset_to(0);
+    }
+
+    /// Hard sync to `phase` instead of the start of the cycle.
+    pub fn reset_to(&self, phase: u32) {
+        self.sync_phase.store(phase, Ordering::Relaxed);
+        self.sync.store(true, Ordering::Relaxed);
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reversing sync: flips the direction the phase runs in on the next
+    /// tick, as on triangle-core oscillators. A hard reset runs forwards again.
+    pub fn reverse(&self) {
+        self.reverse.store(true, Ordering::Relaxed);
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Soft sync: like [`reset_to`](Self::reset_to), but only when the cycle
+    /// is about to wrap anyway, so the edge pulls the oscillator in gently
+    /// instead of cutting cycles short.
+    pub fn soft_reset_to(&self, phase: u32) {
+        if self.phase().wrapping_neg() <= SOFT_WINDOW {
+            self.reset_to(phase);
+        }
+    }
+
+    /// Number of hard sync resets, wrapping.
+    pub fn syncs(&self) -> u32 {
+        self.syncs.load(Ordering::Relaxed)
+    }
+
+    /// Whether the last tick crossed phase zero, in either direction, or a
+    /// sync restarted the cycle there. Unlike a wrap of the output, a sync to
+    /// another phase doesn't count.
+    pub fn at_zero(&self) -> bool {
+        self.zero.load(Ordering::Relaxed)
+    }
+
+    /// Position in the cycle, a full turn is 2^32.
+    pub fn phase(&self) -> u32 {
+        self.phase.load(Ordering::Relaxed)
+    }
+
+    /// Output level, high from the pulse width threshold to the end of the
+    /// cycle.
+    pub fn is_high(&self) -> bool {
+        self.phase.load(Ordering::Relaxed) >= self.width.load(Ordering::Relaxed)
+    }
+
+    /// Tuning word of the running cycle.
+    pub fn step(&self) -> u32 {
+        self.step.load(Ordering::Relaxed)
+    }
+
+    /// Tuning word from the next cycle on, or right away when the oscillator
+    /// is stopped and no cycle would ever end.
+    pub fn set_step(&self, step: u32) {
+        self.slew_ticks.store(0, Ordering::Relaxed);
+        self.pending.store(step, Ordering::Relaxed);
+        if self.step.load(Ordering::Relaxed) == 0 {
+            self.step.store(step, Ordering::Relaxed);
+        }
+    }
+
+    /// Tuning word reached in a straight line over the next `ticks` ticks,
+    /// from the running one, so a pitch published every few hundred µs
+    /// glides from one update to the next instead of stepping. Zero ticks, or
+    /// a stopped oscillator, is [`set_step`](Self::set_step).
+    pub fn slew_step(&self, step: u32, ticks: u32) {
+        // Held first, so the tick never pairs the new slew with old ticks,
+        // and the running word can't move while it is read
+        self.slew_ticks.store(HOLD, Ordering::Relaxed);
+        let current = self.step.load(Ordering::Relaxed);
+        if ticks == 0 || current == 0 {
+            self.set_step(step);
+            return;
+        }
+        let delta = (step as i64).wrapping_sub(current as i64);
+        let slew = delta
+            .checked_div(ticks as i64)
+            .unwrap_or(0)
+            .clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        self.pending.store(step, Ordering::Relaxed);
+        self.slew.store(slew as u32, Ordering::Relaxed);
+        self.slew_ticks
+            .store(ticks.min(HOLD.saturating_sub(1)), Ordering::Relaxed);
+    }
+
+    /// Duty cycle in percent from the next cycle on, clamped to
+    /// [`MIN_DUTY`]..=[`MAX_DUTY`]. The output is low for the first part of
+    /// the cycle.
+    pub fn set_duty(&self, percent: u32) {
+        let high = percent.clamp(MIN_DUTY, MAX_DUTY);
+        let low = 100u32.saturating_sub(high);
+        self.pending_width
+            .store((u32::MAX / 100).saturating_mul(low), Ordering::Relaxed);
+    }
+
+    fn latch(&self) {
+        // A slew gets there by itself, and one being set up will
+        if self.slew_ticks.load(Ordering::Relaxed) == 0 {
+            self.step
+                .store(self.pending.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.width.store(
+            self.pending_width.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sub-octave levels derived from the main output's toggles, so they stay
+/// phase-locked to it and start low with it after a sync.
+#[derive(Default)]
+pub struct Sub {
+    toggles: u8,
+}
+
+impl Sub {
+    pub const fn new() -> Self {
+        Sub { toggles: 0 }
+    }
+
+    /// Advances with the tick result and returns the levels one and two octaves
+    /// down, or `None` when nothing changed.
+    pub fn update(&mut self, edge: Edge) -> Option<(bool, bool)> {
+        match edge {
+            Edge::None => return None,
+            Edge::Reset => self.toggles = 0,
+            Edge::Toggle => self.toggles = self.toggles.wrapping_add(1),
+        }
+
+        Some((self.toggles & 0b10 != 0, self.toggles & 0b100 != 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_phase(phase: u32) -> Oscillator {
+        let osc = Oscillator::new();
+        osc.reset_to(phase);
+        osc.tick();
+        osc
+    }
+
+    #[test]
+    fn stopped_oscillator_never_moves() {
+        let osc = Oscillator::new();
+        for _ in 0..1000 {
+            assert!(osc.tick() == Edge::None);
+        }
+        assert_eq!(osc.phase(), 0);
+        assert!(!osc.at_zero());
+    }
+
+    #[test]
+    fn stopped_oscillator_takes_a_step_right_away() {
+        let osc = Oscillator::new();
+        osc.set_step(1000);
+        assert_eq!(osc.step(), 1000);
+    }
+
+    #[test]
+    fn half_turn_toggles_every_tick() {
+        let osc = Oscillator::new();
+        osc.set_step(HIGH);
+        assert!(osc.tick() == Edge::Toggle);
+        assert!(osc.is_high() && !osc.at_zero());
+        assert!(osc.tick() == Edge::Toggle);
+        assert!(!osc.is_high() && osc.at_zero());
+    }
+
+    #[test]
+    fn largest_step_wraps_every_tick_but_the_first() {
+        let osc = Oscillator::new();
+        osc.set_step(u32::MAX);
+        osc.tick();
+        assert!(!osc.at_zero());
+        for _ in 0..10 {
+            osc.tick();
+            assert!(osc.at_zero());
+        }
+    }
+
+    #[test]
+    fn step_changes_wait_for_the_wrap() {
+        let osc = Oscillator::new();
+        osc.set_step(1 << 30);
+        osc.set_step(1 << 29);
+        for _ in 0..3 {
+            osc.tick();
+            assert_eq!(osc.step(), 1 << 30);
+        }
+        osc.tick();
+        assert!(osc.at_zero());
+        assert_eq!(osc.step(), 1 << 29);
+    }
+
+    #[test]
+    fn slew_lands_on_the_word_mid_cycle() {
+        let osc = Oscillator::new();
+        osc.set_step(1000);
+        osc.slew_step(1004, 4);
+        let steps = [1000, 1001, 1002, 1003, 1004, 1004];
+        for expected in steps.iter().skip(1) {
+            osc.tick();
+            assert_eq!(osc.step(), *expected);
+        }
+    }
+
+    #[test]
+    fn wrap_while_a_slew_is_set_up_keeps_the_running_word() {
+        let osc = Oscillator::new();
+        osc.set_step(1 << 30);
+        osc.slew_step(1 << 29, 100);
+        osc.tick();
+        let running = osc.step();
+        // The publish task interrupted after the hold and the new target, the
+        // next tick wrapping before the rest of the slew is stored
+        osc.slew_ticks.store(HOLD, Ordering::Relaxed);
+        osc.pending.store(1 << 28, Ordering::Relaxed);
+        for _ in 0..4 {
+            osc.tick();
+            assert_eq!(osc.step(), running);
+        }
+        assert!(osc.at_zero());
+        osc.slew_step(1 << 28, 2);
+        osc.tick();
+        osc.tick();
+        assert_eq!(osc.step(), 1 << 28);
+        osc.tick();
+        assert_eq!(osc.step(), 1 << 28);
+    }
+
+    #[test]
+    fn slew_per_tick_is_clamped_not_truncated() {
+        let osc = Oscillator::new();
+        osc.set_step(1);
+        osc.slew_step(u32::MAX, 1);
+        assert_eq!(osc.slew.load(Ordering::Relaxed), i32::MAX as u32);
+        let osc = Oscillator::new();
+        osc.set_step(u32::MAX);
+        osc.slew_step(1, 1);
+        assert_eq!(osc.slew.load(Ordering::Relaxed), i32::MIN as u32);
+        osc.tick();
+        assert_eq!(osc.step(), 1);
+    }
+
+    #[test]
+    fn hard_sync_restarts_low_from_any_phase() {
+        for phase in [1, HIGH - 1, HIGH, u32::MAX] {
+            let osc = at_phase(phase);
+            osc.set_step(12345);
+            osc.reset();
+            assert!(osc.tick() == Edge::Reset);
+            assert_eq!(osc.phase(), 0);
+            assert!(!osc.is_high());
+            assert!(osc.at_zero());
+        }
+    }
+
+    #[test]
+    fn sync_to_another_phase_is_not_a_zero_crossing() {
+        let osc = at_phase(HIGH);
+        assert!(osc.is_high());
+        assert!(!osc.at_zero());
+    }
+
+    #[test]
+    fn soft_sync_window_edges() {
+        let osc = at_phase(SOFT_WINDOW.wrapping_neg());
+        let syncs = osc.syncs();
+        osc.soft_reset_to(0);
+        assert_eq!(osc.syncs(), syncs.wrapping_add(1));
+
+        let osc = at_phase(SOFT_WINDOW.wrapping_neg().wrapping_sub(1));
+        let syncs = osc.syncs();
+        osc.soft_reset_to(0);
+        assert_eq!(osc.syncs(), syncs);
+    }
+
+    #[test]
+    fn reversing_at_zero_wraps_backwards() {
+        let osc = Oscillator::new();
+        osc.set_step(1 << 30);
+        osc.reverse();
+        osc.tick();
+        assert_eq!(osc.phase(), 3 << 30);
+        assert!(osc.at_zero());
+        assert!(osc.is_high());
+    }
+
+    #[test]
+    fn hard_sync_runs_forwards_again() {
+        let osc = Oscillator::new();
+        osc.set_step(1 << 28);
+        osc.reverse();
+        osc.tick();
+        osc.reset();
+        osc.tick();
+        osc.tick();
+        assert_eq!(osc.phase(), 1 << 28);
+    }
+
+    #[test]
+    fn duty_is_clamped() {
+        let osc = Oscillator::new();
+        osc.set_step(1 << 24);
+        osc.set_duty(0);
+        // Latched on the reset, then high for the last 5% of each cycle
+        osc.reset();
+        osc.tick();
+        let mut high = 0u32;
+        for _ in 0..256 {
+            osc.tick();
+            high = high.wrapping_add(osc.is_high() as u32);
+        }
+        assert_eq!(high, 12);
+    }
+
+    #[test]
+    fn sub_octaves_start_low_after_a_sync() {
+        let mut sub = Sub::new();
+        for _ in 0..3 {
+            sub.update(Edge::Toggle);
+        }
+        assert!(sub.update(Edge::None).is_none());
+        assert_eq!(sub.update(Edge::Reset), Some((false, false)));
+    }
+}
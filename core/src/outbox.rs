@@ -0,0 +1,78 @@
+//! Bytes queued for the USART3 transmitter: SysEx replies and console
+//! output, sent one per interrupt.
+//!
+//! Filled from the serial interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::fmt;
+
+/// Outgoing bytes waiting for the transmitter, dropped when full.
+pub struct Outbox {
+    buf: [u8; OUTBOX],
+    head: usize,
+    len: usize,
+}
+
+/// Room for a wavetable reply and an acknowledgement, or a screenful of
+/// console output.
+const OUTBOX: usize = 1024;
+
+impl Outbox {
+    pub const fn new() -> Self {
+        Outbox {
+            buf: [0; OUTBOX],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        if self.len == OUTBOX {
+            return;
+        }
+        let tail = self.head.wrapping_add(self.len) % OUTBOX;
+        if let Some(slot) = self.buf.get_mut(tail) {
+            *slot = byte;
+            self.len = self.len.saturating_add(1);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf.get(self.head).copied();
+        self.head = self.head.wrapping_add(1) % OUTBOX;
+        self.len = self.len.saturating_sub(1);
+        byte
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for Outbox {
+    /// Drops what doesn't fit, and sends newlines as CR LF for terminals.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            if b == b'\n' {
+                self.push(b'\r');
+            }
+            self.push(b);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1035 @@
+//! User parameters edited from the encoder menu.
+//!
+//! Every task reads parameters through [`Params`] instead of owning its own
+//! atomics, and the menu only has to know the table below.
+//!
+//! Looked up by the tick and sync interrupts and the publish task on every
+//! run, with whatever page index the menu or the flash holds.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+use crate::arp;
+use crate::chord;
+use crate::config::FINE_TUNE_STEP;
+use crate::custom;
+use crate::division;
+use crate::pll;
+use crate::preset;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Param {
+    FineTune,
+    Octave,
+    Glide,
+    Detune,
+    FineMode,
+    TestSignal,
+    Dac,
+    Bank,
+    Morph,
+    PulseWidth,
+    Square,
+    Ring,
+    Range,
+    Rate,
+    Division,
+    LfoSync,
+    Sync,
+    SyncEdge,
+    Pll,
+    Clock,
+    Source,
+    Channel,
+    BendRange,
+    Preset,
+    Sleep,
+    CvOut,
+    Velocity,
+    Kick,
+    KickDecay,
+    Arp,
+    ArpClock,
+    ArpIntervals,
+    Chord,
+    Drift,
+    Vibrato,
+    VibratoRate,
+    VibratoDelay,
+    Track,
+    Mod1,
+    ModAmount1,
+    Mod2,
+    ModAmount2,
+    Tuner,
+    Reference,
+    Smooth,
+    Poly,
+    /// Page defined in `custom::PAGES`.
+    User(u8),
+}
+
+const BUILTIN: usize = 46;
+
+/// Pages on the menu, built-in first.
+pub const COUNT: usize = BUILTIN + custom::PAGES.len();
+
+const ALL: [Param; BUILTIN] = [
+    Param::FineTune,
+    Param::Octave,
+    Param::Glide,
+    Param::Detune,
+    Param::FineMode,
+    Param::TestSignal,
+    Param::Dac,
+    Param::Bank,
+    Param::Morph,
+    Param::PulseWidth,
+    Param::Square,
+    Param::Ring,
+    Param::Range,
+    Param::Rate,
+    Param::Division,
+    Param::LfoSync,
+    Param::Sync,
+    Param::SyncEdge,
+    Param::Pll,
+    Param::Clock,
+    Param::Source,
+    Param::Channel,
+    Param::BendRange,
+    Param::Preset,
+    Param::Sleep,
+    Param::CvOut,
+    Param::Velocity,
+    Param::Kick,
+    Param::KickDecay,
+    Param::Arp,
+    Param::ArpClock,
+    Param::ArpIntervals,
+    Param::Chord,
+    Param::Drift,
+    Param::Vibrato,
+    Param::VibratoRate,
+    Param::VibratoDelay,
+    Param::Track,
+    Param::Mod1,
+    Param::ModAmount1,
+    Param::Mod2,
+    Param::ModAmount2,
+    Param::Tuner,
+    Param::Reference,
+    Param::Smooth,
+    Param::Poly,
+];
+
+/// [`Param::FineMode`] values.
+pub const FINE_LATCHED: i32 = 0;
+pub const FINE_MOMENTARY: i32 = 1;
+
+/// [`Param::Dac`] values.
+pub const DAC_AMPLITUDE: i32 = 0;
+pub const DAC_WAVETABLE: i32 = 1;
+pub const DAC_SAW: i32 = 2;
+pub const DAC_SINE: i32 = 3;
+pub const DAC_TRIANGLE: i32 = 4;
+pub const DAC_MORPH: i32 = 5;
+pub const DAC_WHITE: i32 = 6;
+pub const DAC_PINK: i32 = 7;
+pub const DAC_MIDI_CV: i32 = 8;
+
+/// [`Param::Square`] values.
+pub const SQUARE_PULSE: i32 = 0;
+pub const SQUARE_NOISE: i32 = 1;
+
+/// [`Param::Ring`] values.
+pub const RING_OFF: i32 = 0;
+pub const RING_XOR: i32 = 1;
+
+/// [`Param::Range`] values.
+pub const RANGE_VCO: i32 = 0;
+pub const RANGE_LFO: i32 = 1;
+
+/// [`Param::Rate`] values.
+pub const RATE_CV: i32 = 0;
+pub const RATE_TAP: i32 = 1;
+pub const RATE_MIDI: i32 = 2;
+
+/// [`Param::LfoSync`] values.
+pub const LFO_SYNC_0: i32 = 0;
+pub const LFO_SYNC_90: i32 = 1;
+pub const LFO_SYNC_FREE: i32 = 2;
+
+/// [`Param::Sync`] values.
+pub const SYNC_HARD: i32 = 0;
+pub const SYNC_SOFT: i32 = 1;
+pub const SYNC_REVERSE: i32 = 2;
+
+/// [`Param::SyncEdge`] values.
+pub const SYNC_EDGE_RISING: i32 = 0;
+pub const SYNC_EDGE_FALLING: i32 = 1;
+pub const SYNC_EDGE_BOTH: i32 = 2;
+
+/// [`Param::Pll`] value for no lock, the others pick from [`pll::RATIOS`].
+pub const PLL_OFF: i32 = 0;
+
+/// [`Param::Clock`] value for a normal voice, the others pick from
+/// [`pll::CLOCK_RATIOS`].
+pub const CLOCK_OFF: i32 = 0;
+
+/// [`Param::Source`] values.
+pub const SOURCE_CV: i32 = 0;
+pub const SOURCE_MIDI: i32 = 1;
+pub const SOURCE_SUM: i32 = 2;
+pub const SOURCE_LAST: i32 = 3;
+
+/// [`Param::Channel`] value for every channel, the others are channels 1-16.
+pub const CHANNEL_OMNI: i32 = 0;
+
+/// [`Param::Preset`] value before any preset is recalled, the others are
+/// presets 1 to [`preset::SLOTS`].
+pub const PRESET_NONE: i32 = 0;
+
+/// [`Param::Sleep`] values.
+pub const SLEEP_OFF: i32 = 0;
+pub const SLEEP_AUTO: i32 = 1;
+
+/// [`Param::CvOut`] values.
+pub const CV_OUT_OFF: i32 = 0;
+pub const CV_OUT_PITCH: i32 = 1;
+pub const CV_OUT_TRIANGLE: i32 = 2;
+
+/// [`Param::Velocity`] values.
+pub const VELOCITY_OFF: i32 = 0;
+pub const VELOCITY_LINEAR: i32 = 1;
+pub const VELOCITY_SOFT: i32 = 2;
+pub const VELOCITY_HARD: i32 = 3;
+
+/// [`Param::Arp`] values.
+pub const ARP_OFF: i32 = 0;
+pub const ARP_UP: i32 = 1;
+pub const ARP_DOWN: i32 = 2;
+pub const ARP_UP_DOWN: i32 = 3;
+pub const ARP_RANDOM: i32 = 4;
+
+/// [`Param::ArpClock`] values.
+pub const ARP_CLOCK_SYNC: i32 = 0;
+pub const ARP_CLOCK_TAP: i32 = 1;
+pub const ARP_CLOCK_MIDI: i32 = 2;
+
+/// [`Param::Chord`] off, the shapes in `chord::SHAPES` follow.
+pub const CHORD_OFF: i32 = 0;
+
+/// [`Param::Track`] values.
+pub const TRACK_OFF: i32 = 0;
+pub const TRACK_GATE: i32 = 1;
+pub const TRACK_SYNC: i32 = 2;
+
+/// [`Param::Mod1`] and [`Param::Mod2`] destinations.
+pub const MOD_OFF: i32 = 0;
+pub const MOD_PW: i32 = 1;
+pub const MOD_GLIDE: i32 = 2;
+pub const MOD_AMP: i32 = 3;
+pub const MOD_DETUNE: i32 = 4;
+pub const MOD_VIBRATO: i32 = 5;
+pub const MOD_PITCH: i32 = 6;
+
+/// [`Param::Tuner`] values.
+pub const TUNER_OFF: i32 = 0;
+pub const TUNER_ON: i32 = 1;
+
+/// [`Param::Smooth`] values.
+pub const SMOOTH_OFF: i32 = 0;
+pub const SMOOTH_ON: i32 = 1;
+
+/// [`Param::Poly`] values.
+pub const POLY_OFF: i32 = 0;
+pub const POLY_ON: i32 = 1;
+
+const MOD_LABELS: [&str; 7] = ["off", "pw", "glide", "amp", "detune", "vib", "pitch"];
+
+const PLL_LABELS: [&str; 7] = [
+    "off",
+    pll::RATIOS[0].name,
+    pll::RATIOS[1].name,
+    pll::RATIOS[2].name,
+    pll::RATIOS[3].name,
+    pll::RATIOS[4].name,
+    pll::RATIOS[5].name,
+];
+
+const CLOCK_LABELS: [&str; 9] = [
+    "off",
+    pll::CLOCK_RATIOS[0].name,
+    pll::CLOCK_RATIOS[1].name,
+    pll::CLOCK_RATIOS[2].name,
+    pll::CLOCK_RATIOS[3].name,
+    pll::CLOCK_RATIOS[4].name,
+    pll::CLOCK_RATIOS[5].name,
+    pll::CLOCK_RATIOS[6].name,
+    pll::CLOCK_RATIOS[7].name,
+];
+
+const CHORD_LABELS: [&str; 8] = [
+    "off",
+    chord::NAMES[0],
+    chord::NAMES[1],
+    chord::NAMES[2],
+    chord::NAMES[3],
+    chord::NAMES[4],
+    chord::NAMES[5],
+    chord::NAMES[6],
+];
+
+const CHANNEL_LABELS: [&str; 17] = [
+    "omni", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16",
+];
+
+const PRESET_LABELS: [&str; preset::SLOTS as usize + 1] =
+    ["none", "1", "2", "3", "4", "5", "6", "7", "8"];
+
+pub struct Info {
+    pub name: &'static str,
+    pub min: i32,
+    pub max: i32,
+    pub default: i32,
+    /// Change per encoder detent.
+    pub step: i32,
+    /// Whether fast encoder turns multiply the step.
+    pub accelerate: bool,
+    /// Names shown instead of the number, indexed from `min`.
+    pub labels: &'static [&'static str],
+}
+
+impl Info {
+    pub fn label(&self, value: i32) -> Option<&'static str> {
+        let index = value.checked_sub(self.min)?;
+        self.labels.get(index as usize).copied()
+    }
+
+    /// Reads a value typed as a label or a number, unclamped.
+    pub fn parse(&self, text: &str) -> Option<i32> {
+        match self.labels.iter().position(|&l| l == text) {
+            Some(index) => self.min.checked_add(index as i32),
+            None => text.parse().ok(),
+        }
+    }
+
+    /// Maps a 14-bit MIDI controller value across the range.
+    pub fn scale(&self, value: u16) -> i32 {
+        let span = self.max.saturating_sub(self.min) as f32;
+        self.min
+            .saturating_add((span * value.min(0x3fff) as f32 / 16383.0) as i32)
+    }
+}
+
+const INFO: [Info; BUILTIN] = [
+    // Millivolts added to the pitch CV
+    Info {
+        name: "fine",
+        min: -1000,
+        max: 1000,
+        default: 0,
+        step: FINE_TUNE_STEP,
+        accelerate: true,
+        labels: &[],
+    },
+    Info {
+        name: "octave",
+        min: -3,
+        max: 3,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &[],
+    },
+    // Milliseconds per octave, zero jumps
+    Info {
+        name: "glide",
+        min: 0,
+        max: 2000,
+        default: 0,
+        step: 5,
+        accelerate: true,
+        labels: &[],
+    },
+    // Cents between the main and the second oscillator
+    Info {
+        name: "detune",
+        min: -100,
+        max: 100,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &[],
+    },
+    // Latched keeps fine tune across power cycles, momentary starts at zero
+    Info {
+        name: "fmode",
+        min: FINE_LATCHED,
+        max: FINE_MOMENTARY,
+        default: FINE_LATCHED,
+        step: 1,
+        accelerate: false,
+        labels: &["latch", "moment"],
+    },
+    // Test signal generator, overrides the CV while not off
+    Info {
+        name: "test",
+        min: 0,
+        max: 3,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "sweep", "steps", "chirp"],
+    },
+    // What the GPIOA DAC outputs
+    Info {
+        name: "dac",
+        min: DAC_AMPLITUDE,
+        max: DAC_MIDI_CV,
+        default: DAC_AMPLITUDE,
+        step: 1,
+        accelerate: false,
+        labels: &[
+            "amp", "wave", "saw", "sine", "tri", "morph", "white", "pink", "cv",
+        ],
+    },
+    // Wavetable played in wave mode
+    Info {
+        name: "bank",
+        min: 0,
+        max: 3,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &["1", "2", "3", "4"],
+    },
+    // Position between adjacent shapes in morph mode
+    Info {
+        name: "morph",
+        min: 0,
+        max: crate::wave::MORPH_MAX,
+        default: 0,
+        step: 2,
+        accelerate: true,
+        labels: &[],
+    },
+    // Duty cycle of the square output in percent, before the CV
+    Info {
+        name: "pw",
+        min: crate::osc::MIN_DUTY as i32,
+        max: crate::osc::MAX_DUTY as i32,
+        default: 50,
+        step: 1,
+        accelerate: false,
+        labels: &[],
+    },
+    // Square output: the pulse, or 1-bit noise clocked at the pitch
+    Info {
+        name: "sq",
+        min: SQUARE_PULSE,
+        max: SQUARE_NOISE,
+        default: SQUARE_PULSE,
+        step: 1,
+        accelerate: false,
+        labels: &["pulse", "noise"],
+    },
+    // Ring-mod output on PB10
+    Info {
+        name: "ring",
+        min: RING_OFF,
+        max: RING_XOR,
+        default: RING_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "xor"],
+    },
+    // Audio or sub-audio pitch tracking
+    Info {
+        name: "range",
+        min: RANGE_VCO,
+        max: RANGE_LFO,
+        default: RANGE_VCO,
+        step: 1,
+        accelerate: false,
+        labels: &["vco", "lfo"],
+    },
+    // What sets the LFO rate: the CV, taps at the sync input or MIDI clock
+    Info {
+        name: "rate",
+        min: RATE_CV,
+        max: RATE_MIDI,
+        default: RATE_CV,
+        step: 1,
+        accelerate: false,
+        labels: &["cv", "tap", "midi"],
+    },
+    // LFO cycle length against the MIDI clock
+    Info {
+        name: "div",
+        min: 0,
+        max: division::NAMES.len() as i32 - 1,
+        default: 4,
+        step: 1,
+        accelerate: false,
+        labels: &division::NAMES,
+    },
+    // What a sync edge does to the LFO
+    Info {
+        name: "lsync",
+        min: LFO_SYNC_0,
+        max: LFO_SYNC_FREE,
+        default: LFO_SYNC_0,
+        step: 1,
+        accelerate: false,
+        labels: &["0", "90", "free"],
+    },
+    // Sync flavour
+    Info {
+        name: "sync",
+        min: SYNC_HARD,
+        max: SYNC_REVERSE,
+        default: SYNC_HARD,
+        step: 1,
+        accelerate: false,
+        labels: &["hard", "soft", "rev"],
+    },
+    // Which sync input edges count
+    Info {
+        name: "sedge",
+        min: SYNC_EDGE_RISING,
+        max: SYNC_EDGE_BOTH,
+        default: SYNC_EDGE_RISING,
+        step: 1,
+        accelerate: false,
+        labels: &["rise", "fall", "both"],
+    },
+    // Phase lock to the sync input at a ratio
+    Info {
+        name: "pll",
+        min: PLL_OFF,
+        max: pll::RATIOS.len() as i32,
+        default: PLL_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &PLL_LABELS,
+    },
+    // Clock divider/multiplier utility mode
+    Info {
+        name: "clock",
+        min: CLOCK_OFF,
+        max: pll::CLOCK_RATIOS.len() as i32,
+        default: CLOCK_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &CLOCK_LABELS,
+    },
+    // Pitch from the CV, the last MIDI note, the CV transposed by it, or
+    // whichever of the CV and MIDI moved last
+    Info {
+        name: "src",
+        min: SOURCE_CV,
+        max: SOURCE_LAST,
+        default: SOURCE_CV,
+        step: 1,
+        accelerate: false,
+        labels: &["cv", "midi", "sum", "last"],
+    },
+    // MIDI channel filter
+    Info {
+        name: "chan",
+        min: CHANNEL_OMNI,
+        max: 16,
+        default: CHANNEL_OMNI,
+        step: 1,
+        accelerate: false,
+        labels: &CHANNEL_LABELS,
+    },
+    // MIDI pitch bend range either way, in semitones
+    Info {
+        name: "bend",
+        min: 0,
+        max: 24,
+        default: 2,
+        step: 1,
+        accelerate: false,
+        labels: &[],
+    },
+    // Preset recalled as soon as it is picked
+    Info {
+        name: "preset",
+        min: PRESET_NONE,
+        max: preset::SLOTS as i32,
+        default: PRESET_NONE,
+        step: 1,
+        accelerate: false,
+        labels: &PRESET_LABELS,
+    },
+    // Auto-sleep once the CV and the controls sit still
+    Info {
+        name: "sleep",
+        min: SLEEP_OFF,
+        max: SLEEP_AUTO,
+        default: SLEEP_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "auto"],
+    },
+    // What the filtered PWM output on PB10 carries
+    Info {
+        name: "cvout",
+        min: CV_OUT_OFF,
+        max: CV_OUT_TRIANGLE,
+        default: CV_OUT_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "pitch", "tri"],
+    },
+    // How MIDI velocity scales the amplitude DAC
+    Info {
+        name: "vel",
+        min: VELOCITY_OFF,
+        max: VELOCITY_HARD,
+        default: VELOCITY_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "lin", "soft", "hard"],
+    },
+    // Millivolts above the pitch a trigger sweeps down from, zero is off
+    Info {
+        name: "kick",
+        min: 0,
+        max: 6000,
+        default: 0,
+        step: 100,
+        accelerate: true,
+        labels: &[],
+    },
+    // Time constant of the kick sweep in milliseconds
+    Info {
+        name: "kdecay",
+        min: 5,
+        max: 1000,
+        default: 60,
+        step: 5,
+        accelerate: true,
+        labels: &[],
+    },
+    // Arpeggiator pattern over the held notes or the `aint` intervals
+    Info {
+        name: "arp",
+        min: ARP_OFF,
+        max: ARP_RANDOM,
+        default: ARP_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "up", "down", "updn", "rand"],
+    },
+    // Arpeggiator clock: every sync edge, the tapped tempo, or MIDI clock
+    // at `div`
+    Info {
+        name: "aclk",
+        min: ARP_CLOCK_SYNC,
+        max: ARP_CLOCK_MIDI,
+        default: ARP_CLOCK_SYNC,
+        step: 1,
+        accelerate: false,
+        labels: &["sync", "tap", "midi"],
+    },
+    // Intervals above the pitch the arpeggiator plays with no note held
+    Info {
+        name: "aint",
+        min: 0,
+        max: arp::NAMES.len() as i32 - 1,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &arp::NAMES,
+    },
+    // Notes stacked above the pitch on the DAC
+    Info {
+        name: "chord",
+        min: CHORD_OFF,
+        max: chord::NAMES.len() as i32,
+        default: CHORD_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &CHORD_LABELS,
+    },
+    // Depth of the random pitch drift in cents either way, zero is off
+    Info {
+        name: "drift",
+        min: 0,
+        max: 50,
+        default: 0,
+        step: 1,
+        accelerate: false,
+        labels: &[],
+    },
+    // Vibrato depth in cents either way, zero is off
+    Info {
+        name: "vib",
+        min: 0,
+        max: 100,
+        default: 0,
+        step: 1,
+        accelerate: true,
+        labels: &[],
+    },
+    // Vibrato rate in tenths of a hertz
+    Info {
+        name: "vrate",
+        min: 1,
+        max: 200,
+        default: 55,
+        step: 1,
+        accelerate: true,
+        labels: &[],
+    },
+    // Milliseconds after a new note before the vibrato fades in, over as long
+    // again
+    Info {
+        name: "vdelay",
+        min: 0,
+        max: 2000,
+        default: 0,
+        step: 10,
+        accelerate: true,
+        labels: &[],
+    },
+    // Track and hold on the pitch CV: always tracking, tracking while the
+    // sync input is high, or sampling on every sync edge
+    Info {
+        name: "th",
+        min: TRACK_OFF,
+        max: TRACK_SYNC,
+        default: TRACK_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "gate", "sync"],
+    },
+    // Destination of the first modulation input on PC4
+    Info {
+        name: "mod1",
+        min: MOD_OFF,
+        max: MOD_PITCH,
+        default: MOD_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &MOD_LABELS,
+    },
+    // Its attenuverter in percent of the destination's range, negative
+    // inverts
+    Info {
+        name: "mamt1",
+        min: -100,
+        max: 100,
+        default: 100,
+        step: 1,
+        accelerate: true,
+        labels: &[],
+    },
+    // Destination of the second modulation input on PC5
+    Info {
+        name: "mod2",
+        min: MOD_OFF,
+        max: MOD_PITCH,
+        default: MOD_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &MOD_LABELS,
+    },
+    Info {
+        name: "mamt2",
+        min: -100,
+        max: 100,
+        default: 100,
+        step: 1,
+        accelerate: true,
+        labels: &[],
+    },
+    // Tuner reference: the oscillator plays `ref` exactly, whatever the CV,
+    // MIDI and the modulation say
+    Info {
+        name: "tuner",
+        min: TUNER_OFF,
+        max: TUNER_ON,
+        default: TUNER_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "on"],
+    },
+    // Reference frequency in tenths of a Hz, A440 by default
+    Info {
+        name: "ref",
+        min: 200,
+        max: 20000,
+        default: 4400,
+        step: 1,
+        accelerate: true,
+        labels: &[],
+    },
+    // Pitch updates slewed across the interval to the next one instead of
+    // stepping at the next cycle
+    Info {
+        name: "smooth",
+        min: SMOOTH_OFF,
+        max: SMOOTH_ON,
+        default: SMOOTH_ON,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "on"],
+    },
+    // Four voices with a MIDI note each, on the square, detune and
+    // sub-octave pins and mixed on the DAC
+    Info {
+        name: "poly",
+        min: POLY_OFF,
+        max: POLY_ON,
+        default: POLY_OFF,
+        step: 1,
+        accelerate: false,
+        labels: &["off", "on"],
+    },
+];
+
+impl Param {
+    pub fn info(self) -> &'static Info {
+        let [fine_tune, ..] = &INFO;
+        match self {
+            Param::User(n) => custom::PAGES.get(n as usize),
+            p => INFO.get(p.index()),
+        }
+        .unwrap_or(fine_tune)
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Param::FineTune => 0,
+            Param::Octave => 1,
+            Param::Glide => 2,
+            Param::Detune => 3,
+            Param::FineMode => 4,
+            Param::TestSignal => 5,
+            Param::Dac => 6,
+            Param::Bank => 7,
+            Param::Morph => 8,
+            Param::PulseWidth => 9,
+            Param::Square => 10,
+            Param::Ring => 11,
+            Param::Range => 12,
+            Param::Rate => 13,
+            Param::Division => 14,
+            Param::LfoSync => 15,
+            Param::Sync => 16,
+            Param::SyncEdge => 17,
+            Param::Pll => 18,
+            Param::Clock => 19,
+            Param::Source => 20,
+            Param::Channel => 21,
+            Param::BendRange => 22,
+            Param::Preset => 23,
+            Param::Sleep => 24,
+            Param::CvOut => 25,
+            Param::Velocity => 26,
+            Param::Kick => 27,
+            Param::KickDecay => 28,
+            Param::Arp => 29,
+            Param::ArpClock => 30,
+            Param::ArpIntervals => 31,
+            Param::Chord => 32,
+            Param::Drift => 33,
+            Param::Vibrato => 34,
+            Param::VibratoRate => 35,
+            Param::VibratoDelay => 36,
+            Param::Track => 37,
+            Param::Mod1 => 38,
+            Param::ModAmount1 => 39,
+            Param::Mod2 => 40,
+            Param::ModAmount2 => 41,
+            Param::Tuner => 42,
+            Param::Reference => 43,
+            Param::Smooth => 44,
+            Param::Poly => 45,
+            Param::User(n) => BUILTIN.saturating_add(n as usize),
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        ALL.get(index)
+            .copied()
+            .unwrap_or(Param::User(index.saturating_sub(BUILTIN) as u8))
+    }
+}
+
+/// Every page, built-in and custom.
+pub fn all() -> impl Iterator<Item = Param> {
+    (0..COUNT).map(Param::from_index)
+}
+
+/// The page shown as `name` on the menu.
+pub fn by_name(name: &str) -> Option<Param> {
+    all().find(|p| p.info().name == name)
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicI32 = AtomicI32::new(0);
+
+/// Current parameter values and the menu page, shared by reference.
+pub struct Params {
+    values: [AtomicI32; COUNT],
+    page: AtomicU8,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        let params = Params {
+            values: [ZERO; COUNT],
+            page: AtomicU8::new(0),
+        };
+        params.reset();
+        params
+    }
+
+    /// Restores every parameter to its default.
+    pub fn reset(&self) {
+        for p in all() {
+            self.set(p, p.info().default);
+        }
+    }
+
+    /// Read from the realtime tasks, so it must not panic.
+    pub fn get(&self, p: Param) -> i32 {
+        self.values
+            .get(p.index())
+            .map_or(0, |v| v.load(Ordering::Relaxed))
+    }
+
+    /// Applies the power-up behaviour of the loaded values: momentary fine
+    /// tune starts from zero, and the test signal always starts off.
+    pub fn power_up(&self) {
+        if self.get(Param::FineMode) == FINE_MOMENTARY {
+            self.set(Param::FineTune, 0);
+        }
+        self.set(Param::TestSignal, 0);
+    }
+
+    /// Sets `p`, clamped to its range.
+    pub fn set(&self, p: Param, value: i32) {
+        let info = p.info();
+        if let Some(v) = self.values.get(p.index()) {
+            v.store(value.max(info.min).min(info.max), Ordering::Relaxed);
+        }
+    }
+
+    /// Moves `p` by `steps` multiples of its step size.
+    pub fn nudge(&self, p: Param, steps: i32) {
+        self.set(
+            p,
+            self.get(p)
+                .saturating_add(steps.saturating_mul(p.info().step)),
+        );
+    }
+
+    /// The parameter the encoder currently edits.
+    pub fn page(&self) -> Param {
+        Param::from_index(self.page.load(Ordering::Relaxed) as usize % COUNT)
+    }
+
+    pub fn next_page(&self) {
+        let next = (self.page.load(Ordering::Relaxed) as usize).saturating_add(1) % COUNT;
+        self.page.store(next as u8, Ordering::Relaxed);
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_page_is_consistent() {
+        for p in all() {
+            let info = p.info();
+            assert!(
+                info.min <= info.default && info.default <= info.max,
+                "{}",
+                info.name
+            );
+            assert!(info.step > 0, "{}", info.name);
+            if !info.labels.is_empty() {
+                let values = info.max.saturating_sub(info.min).saturating_add(1);
+                assert_eq!(info.labels.len() as i32, values, "{}", info.name);
+            }
+            assert!(by_name(info.name) == Some(p), "{} isn't unique", info.name);
+            assert!(Param::from_index(p.index()) == p, "{}", info.name);
+        }
+    }
+
+    #[test]
+    fn set_clamps_to_the_range() {
+        let params = Params::new();
+        params.set(Param::Octave, 10);
+        assert_eq!(params.get(Param::Octave), 3);
+        params.nudge(Param::Octave, -100);
+        assert_eq!(params.get(Param::Octave), -3);
+        params.nudge(Param::FineTune, i32::MAX);
+        assert_eq!(params.get(Param::FineTune), 1000);
+        params.reset();
+        assert_eq!(params.get(Param::FineTune), 0);
+    }
+
+    #[test]
+    fn values_read_as_labels_or_numbers() {
+        let info = Param::Dac.info();
+        assert_eq!(info.parse("sine"), Some(DAC_SINE));
+        assert_eq!(info.parse("3"), Some(3));
+        assert_eq!(info.parse("nope"), None);
+        assert_eq!(info.label(DAC_MIDI_CV), Some("cv"));
+        assert_eq!(info.label(DAC_MIDI_CV + 1), None);
+        assert_eq!(Param::FineTune.info().label(0), None);
+    }
+
+    #[test]
+    fn controllers_span_the_range() {
+        let info = Param::FineTune.info();
+        assert_eq!(info.scale(0), -1000);
+        assert_eq!(info.scale(0x3fff), 1000);
+        assert_eq!(info.scale(u16::MAX), 1000);
+    }
+
+    #[test]
+    fn power_up_clears_momentary_fine_tune_and_the_test_signal() {
+        let params = Params::new();
+        params.set(Param::FineTune, 100);
+        params.set(Param::TestSignal, 1);
+        params.power_up();
+        assert_eq!(params.get(Param::FineTune), 100);
+        assert_eq!(params.get(Param::TestSignal), 0);
+        params.set(Param::FineMode, FINE_MOMENTARY);
+        params.power_up();
+        assert_eq!(params.get(Param::FineTune), 0);
+    }
+
+    #[test]
+    fn pages_wrap_around() {
+        let params = Params::new();
+        assert!(params.page() == Param::FineTune);
+        for _ in 0..COUNT {
+            params.next_page();
+        }
+        assert!(params.page() == Param::FineTune);
+    }
+}
@@ -0,0 +1,257 @@
+//! CV measurement to pitch conversion.
+//!
+//! Runs in the publish task, on any CV the input reads.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use crate::config::{CV_GAIN, CV_OFFSET_MV, VREF_SCALE};
+
+/// Range handed to the exponential converter, in mV/oct.
+pub const MIN_PITCH_MV: f32 = -2000.0;
+pub const MAX_PITCH_MV: f32 = 10000.0;
+
+pub fn avg(buf: &[u16]) -> u32 {
+    let sum = buf
+        .iter()
+        .fold(0u32, |acc, &sample| acc.saturating_add(sample as u32));
+
+    sum.checked_div(buf.len() as u32).unwrap_or(0)
+}
+
+/// Converts an averaged sample to millivolts at the input. Scaling by VREFINT
+/// keeps the reading independent of the supply.
+pub fn cv_mv(avg: u32, vref: u16) -> f32 {
+    if vref == 0 {
+        return 0.0;
+    }
+
+    avg as f32 * VREF_SCALE / vref as f32
+}
+
+/// Pitch in mV/oct for a CV reading plus a user offset, clamped to the
+/// converter's range. NaN readings end up at the bottom of the range.
+pub fn pitch_mv(cv_mv: f32, offset_mv: i32) -> f32 {
+    let pitch = CV_OFFSET_MV - CV_GAIN * cv_mv + offset_mv as f32;
+    if pitch.is_nan() {
+        return MIN_PITCH_MV;
+    }
+
+    pitch.clamp(MIN_PITCH_MV, MAX_PITCH_MV)
+}
+
+/// Phase accumulator increment per tick for a frequency. Out of range and NaN
+/// inputs saturate.
+pub fn tuning_word(hz: f32, tick_hz: u32) -> u32 {
+    (hz * (4_294_967_296.0 / tick_hz as f32)) as u32
+}
+
+/// LFO range: the VCO frequency ten octaves down, clamped to
+/// [`LFO_MIN_HZ`]..=[`LFO_MAX_HZ`].
+pub const LFO_MIN_HZ: f32 = 0.05;
+pub const LFO_MAX_HZ: f32 = 50.0;
+const LFO_DIVIDER: f32 = 1024.0;
+
+/// Frequency in LFO range for a VCO frequency. At the bottom of the range a
+/// cycle is 20 s, still a tuning word of over a thousand, so the 32-bit
+/// accumulator keeps its resolution and never needs a longer period.
+pub fn lfo_hz(hz: f32) -> f32 {
+    (hz / LFO_DIVIDER).clamp(LFO_MIN_HZ, LFO_MAX_HZ)
+}
+
+/// Pitch detuned by `cents`.
+pub fn detune_mv(pitch_mv: f32, cents: i32) -> f32 {
+    pitch_mv + cents as f32 * (1000.0 / 1200.0)
+}
+
+/// Portamento: moves the published pitch towards its target at a limited rate
+/// instead of jumping.
+pub struct Glide(f32);
+
+impl Glide {
+    pub const fn new() -> Self {
+        // Nothing published yet, the first update jumps
+        Glide(f32::NAN)
+    }
+
+    /// Steps towards `target` by at most `max_step` mV; zero jumps straight
+    /// there.
+    pub fn update(&mut self, target: f32, max_step: f32) -> f32 {
+        let delta = target - self.0;
+
+        self.0 = if max_step > 0.0 && !delta.is_nan() {
+            self.0 + delta.clamp(-max_step, max_step)
+        } else {
+            target
+        };
+        self.0
+    }
+}
+
+impl Default for Glide {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Input past the halfway point between two semitones, as a fraction of a
+/// semitone, before [`Semitones`] moves to the next, so noise at a boundary
+/// doesn't trill between them.
+const SEMITONE_HYSTERESIS: f32 = 0.1;
+
+/// Rounds a pitch CV to whole semitones, for transposing in tune.
+pub struct Semitones(i32);
+
+impl Semitones {
+    pub const fn new() -> Self {
+        Semitones(0)
+    }
+
+    /// The semitone nearest `mv`, unless it is still within the hysteresis of
+    /// the last one, in mV/oct. NaN keeps the last one.
+    pub fn update(&mut self, mv: f32) -> i32 {
+        let semitones = mv * (12.0 / 1000.0);
+        let last = self.0 as f32;
+        let limit = 0.5 + SEMITONE_HYSTERESIS;
+        if semitones > last + limit || semitones < last - limit {
+            // Saturates, and rounds half away from zero
+            self.0 = if semitones < 0.0 {
+                (semitones - 0.5) as i32
+            } else {
+                (semitones + 0.5) as i32
+            };
+        }
+        self.0.saturating_mul(1000).wrapping_div(12)
+    }
+}
+
+impl Default for Semitones {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pitch forced by a background procedure instead of the CV, in mV/oct.
+pub struct Override(AtomicI32);
+
+impl Override {
+    const NONE: i32 = i32::MIN;
+
+    pub const fn new() -> Self {
+        Override(AtomicI32::new(Self::NONE))
+    }
+
+    pub fn get(&self) -> Option<i32> {
+        match self.0.load(Ordering::Relaxed) {
+            Self::NONE => None,
+            mv => Some(mv),
+        }
+    }
+
+    pub fn set(&self, mv: Option<i32>) {
+        self.0.store(mv.unwrap_or(Self::NONE), Ordering::Relaxed);
+    }
+}
+
+impl Default for Override {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TIM3_FREQ_HZ;
+    use crate::osc::Oscillator;
+    use eurorack_oxide_utils::voct::{MvOct, Voltage};
+
+    /// VREFINT readings across the datasheet's spread and the supply range.
+    const VREFS: [u16; 4] = [1400, 1489, 1550, 1650];
+
+    /// Largest frequency error of a tuning word, relative: about 0.05 cents,
+    /// the truncation of the shortest word, at the bottom of the range.
+    const MAX_ERROR: f64 = 3e-5;
+
+    fn word(mv: f32) -> u32 {
+        tuning_word(MvOct(mv).hz(), TIM3_FREQ_HZ)
+    }
+
+    #[test]
+    fn higher_cv_never_shortens_the_period() {
+        // The input stage inverts, so the word falls as the code rises
+        for vref in VREFS {
+            for offset_mv in [-1000, 0, 1000] {
+                let mut last = u32::MAX;
+                for code in 0..=4095 {
+                    let w = word(pitch_mv(cv_mv(code, vref), offset_mv));
+                    assert!(
+                        w <= last,
+                        "code {} vref {} offset {}",
+                        code,
+                        vref,
+                        offset_mv
+                    );
+                    last = w;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn words_stay_in_range_across_the_cv() {
+        // Every reading the ADC can give, at any VREFINT and offset
+        for vref in (1..=4095).step_by(7).chain(VREFS) {
+            for code in (0..=4095).step_by(3) {
+                for offset_mv in [i32::MIN, -10_000, 0, 10_000, i32::MAX] {
+                    let mv = pitch_mv(cv_mv(code, vref), offset_mv);
+                    assert!((MIN_PITCH_MV..=MAX_PITCH_MV).contains(&mv));
+                    let w = word(mv);
+                    // Below Nyquist, so the accumulator wraps at most once a
+                    // tick, and never stopped
+                    assert!(w > 0 && w < 1 << 31, "code {} vref {}", code, vref);
+                }
+            }
+        }
+        assert_eq!(cv_mv(4095, 0), 0.0);
+        assert_eq!(tuning_word(f32::NAN, TIM3_FREQ_HZ), 0);
+        assert_eq!(tuning_word(-1.0, TIM3_FREQ_HZ), 0);
+        assert_eq!(tuning_word(f32::INFINITY, TIM3_FREQ_HZ), u32::MAX);
+        assert_eq!(pitch_mv(f32::NAN, 0), MIN_PITCH_MV);
+    }
+
+    #[test]
+    fn words_round_trip_to_the_frequency() {
+        for mv in (MIN_PITCH_MV as i32..=MAX_PITCH_MV as i32).step_by(5) {
+            let hz = MvOct(mv as f32).hz() as f64;
+            let back = word(mv as f32) as f64 * TIM3_FREQ_HZ as f64 / 4_294_967_296.0;
+            let error = (back / hz - 1.0).abs();
+            assert!(error < MAX_ERROR, "{} mV off by {}", mv, error);
+        }
+    }
+
+    #[test]
+    fn accumulator_runs_at_the_word_frequency() {
+        // One second of ticks, counting cycles
+        for mv in [MIN_PITCH_MV, 0.0, 4321.0, MAX_PITCH_MV] {
+            let osc = Oscillator::new();
+            osc.set_step(word(mv));
+            osc.reset();
+            osc.tick();
+            let cycles = (0..TIM3_FREQ_HZ).filter(|_| {
+                osc.tick();
+                osc.at_zero()
+            });
+            let cycles = cycles.count() as f64;
+            let hz = MvOct(mv).hz() as f64;
+            // Within the one cycle that can be cut off at either end
+            assert!((cycles - hz).abs() <= 1.0 + hz * MAX_ERROR, "{} mV", mv);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Phase-locked loop to the signal at the sync input.
+//!
+//! Edges are recorded from the tick interrupt and the loop runs in the
+//! publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Output frequency relative to the input, `num / den`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Ratio {
+    pub name: &'static str,
+    pub num: u32,
+    pub den: u32,
+}
+
+pub const RATIOS: [Ratio; 6] = [
+    Ratio {
+        name: "/4",
+        num: 1,
+        den: 4,
+    },
+    Ratio {
+        name: "/2",
+        num: 1,
+        den: 2,
+    },
+    Ratio {
+        name: "x1",
+        num: 1,
+        den: 1,
+    },
+    Ratio {
+        name: "x2",
+        num: 2,
+        den: 1,
+    },
+    Ratio {
+        name: "x3",
+        num: 3,
+        den: 1,
+    },
+    Ratio {
+        name: "x4",
+        num: 4,
+        den: 1,
+    },
+];
+
+/// Clock divider and multiplier factors.
+pub const CLOCK_RATIOS: [Ratio; 8] = [
+    Ratio {
+        name: "/8",
+        num: 1,
+        den: 8,
+    },
+    Ratio {
+        name: "/4",
+        num: 1,
+        den: 4,
+    },
+    Ratio {
+        name: "/3",
+        num: 1,
+        den: 3,
+    },
+    Ratio {
+        name: "/2",
+        num: 1,
+        den: 2,
+    },
+    Ratio {
+        name: "x2",
+        num: 2,
+        den: 1,
+    },
+    Ratio {
+        name: "x3",
+        num: 3,
+        den: 1,
+    },
+    Ratio {
+        name: "x4",
+        num: 4,
+        den: 1,
+    },
+    Ratio {
+        name: "x8",
+        num: 8,
+        den: 1,
+    },
+];
+
+impl Ratio {
+    /// `hz` scaled by the ratio.
+    pub fn of(self, hz: f32) -> f32 {
+        hz * self.num as f32 / self.den.max(1) as f32
+    }
+}
+
+/// Fraction of the phase error corrected per input cycle. Has to stay well
+/// under 1 / the largest ratio for the loop to settle.
+const GAIN: f32 = 0.1;
+
+/// Oscillator phase at the last input edge.
+pub struct Pll {
+    edge_phase: AtomicU32,
+}
+
+impl Pll {
+    pub const fn new() -> Self {
+        Pll {
+            edge_phase: AtomicU32::new(0),
+        }
+    }
+
+    /// Records the oscillator phase at an input edge.
+    pub fn edge(&self, phase: u32) {
+        self.edge_phase.store(phase, Ordering::Relaxed);
+    }
+
+    /// Output frequency for an input at `input_hz`: the ratio, nudged to pull
+    /// the output cycle start onto the input edges.
+    pub fn hz(&self, input_hz: f32, ratio: Ratio) -> f32 {
+        let den = ratio.den.max(1);
+        // With N input edges per output cycle, any of the N phases at k / N
+        // turns is locked
+        let error = self.edge_phase.load(Ordering::Relaxed).wrapping_mul(den) as i32;
+        let turns = error as f32 / (4_294_967_296.0 * den as f32);
+
+        ratio.of(input_hz) * (1.0 - GAIN * turns)
+    }
+}
+
+impl Default for Pll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
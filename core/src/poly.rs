@@ -0,0 +1,245 @@
+//! Paraphonic mode: four oscillators of their own, each playing a note handed
+//! out from the MIDI input. A new note takes the voice that has been free the
+//! longest, or steals the oldest sounding note when all voices are busy.
+//!
+//! Notes are handed out in the serial interrupt, the tuning words follow in
+//! the publish task and the voices are stepped and mixed in the tick.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use eurorack_oxide_utils::voct::{MvOct, Voltage};
+
+use crate::osc::Oscillator;
+use crate::pitch;
+
+pub const VOICES: usize = 4;
+
+/// DAC sample of a voice with no note, the middle of the range.
+const SILENT: u32 = 128;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    note: Option<u8>,
+    /// Allocation clock at the last note on or off.
+    since: u32,
+}
+
+pub struct Allocator {
+    slots: [Slot; VOICES],
+    clock: u32,
+}
+
+impl Allocator {
+    pub const fn new() -> Self {
+        Allocator {
+            slots: [Slot {
+                note: None,
+                since: 0,
+            }; VOICES],
+            clock: 0,
+        }
+    }
+
+    /// Assigns `note` to a voice and returns it. A note that is already
+    /// sounding keeps its voice.
+    pub fn note_on(&mut self, note: u8) -> usize {
+        self.clock = self.clock.wrapping_add(1);
+
+        let voice = self
+            .find(note)
+            .or_else(|| self.oldest(|slot| slot.note.is_none()))
+            .or_else(|| self.oldest(|_| true))
+            .unwrap_or(0);
+
+        if let Some(slot) = self.slots.get_mut(voice) {
+            *slot = Slot {
+                note: Some(note),
+                since: self.clock,
+            };
+        }
+        voice
+    }
+
+    /// Frees the voice playing `note`, if any, and returns it.
+    pub fn note_off(&mut self, note: u8) -> Option<usize> {
+        self.clock = self.clock.wrapping_add(1);
+
+        let voice = self.find(note)?;
+        let slot = self.slots.get_mut(voice)?;
+        *slot = Slot {
+            note: None,
+            since: self.clock,
+        };
+        Some(voice)
+    }
+
+    /// Note currently assigned to `voice`.
+    pub fn note(&self, voice: usize) -> Option<u8> {
+        self.slots.get(voice).and_then(|slot| slot.note)
+    }
+
+    pub fn all_off(&mut self) {
+        for slot in self.slots.iter_mut() {
+            slot.note = None;
+        }
+    }
+
+    fn find(&self, note: u8) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.note == Some(note))
+    }
+
+    fn oldest(&self, filter: impl Fn(&Slot) -> bool) -> Option<usize> {
+        self.slots
+            .iter()
+            .enumerate()
+            // Ties go to the lowest voice
+            .rev()
+            .filter(|(_, slot)| filter(slot))
+            .max_by_key(|(_, slot)| self.clock.wrapping_sub(slot.since))
+            .map(|(voice, _)| voice)
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The voices' oscillators and the notes they were handed, shared by
+/// reference between the serial interrupt, the publish task and the tick.
+pub struct Voices {
+    pub oscs: [Oscillator; VOICES],
+    notes: [AtomicU8; VOICES],
+}
+
+impl Voices {
+    const NONE: u8 = u8::MAX;
+
+    pub const fn new() -> Self {
+        Voices {
+            oscs: [
+                Oscillator::new(),
+                Oscillator::new(),
+                Oscillator::new(),
+                Oscillator::new(),
+            ],
+            notes: [
+                AtomicU8::new(Self::NONE),
+                AtomicU8::new(Self::NONE),
+                AtomicU8::new(Self::NONE),
+                AtomicU8::new(Self::NONE),
+            ],
+        }
+    }
+
+    /// Note `voice` plays, `None` while it is free.
+    pub fn note(&self, voice: usize) -> Option<u8> {
+        match self.notes.get(voice)?.load(Ordering::Relaxed) {
+            Self::NONE => None,
+            note => Some(note),
+        }
+    }
+
+    pub fn set_note(&self, voice: usize, note: Option<u8>) {
+        if let Some(slot) = self.notes.get(voice) {
+            slot.store(note.unwrap_or(Self::NONE), Ordering::Relaxed);
+        }
+    }
+
+    /// Tunes `voice` to `mv` from its next cycle on, or with `None` stops it
+    /// at the end of the one it is in.
+    pub fn tune(&self, voice: usize, mv: Option<f32>, tick_hz: u32) {
+        let step = mv.map_or(0, |mv| pitch::tuning_word(MvOct(mv).hz(), tick_hz));
+        if let Some(osc) = self.oscs.get(voice) {
+            osc.set_step(step);
+        }
+    }
+
+    /// Every voice's `wave` sample `ahead` ticks from now, mixed at a fixed
+    /// level each, so the mix doesn't jump as notes come and go. Stopped
+    /// voices sit in the middle.
+    pub fn mix<F>(&self, ahead: u32, wave: F) -> u8
+    where
+        F: Fn(u32, u32) -> u8,
+    {
+        let sum = self.oscs.iter().fold(0u32, |sum, osc| {
+            let step = osc.step();
+            let sample = if step == 0 {
+                SILENT
+            } else {
+                wave(osc.phase().wrapping_add(step.wrapping_mul(ahead)), step) as u32
+            };
+            sum.saturating_add(sample)
+        });
+        sum.checked_div(VOICES as u32).unwrap_or(SILENT) as u8
+    }
+}
+
+impl Default for Voices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_take_the_voice_free_the_longest() {
+        let mut allocator = Allocator::new();
+        assert_eq!(allocator.note_on(60), 0);
+        assert_eq!(allocator.note_on(64), 1);
+        assert_eq!(allocator.note_off(60), Some(0));
+        assert_eq!(allocator.note_on(67), 2);
+        assert_eq!(allocator.note_on(71), 3);
+        assert_eq!(allocator.note_on(72), 0);
+    }
+
+    #[test]
+    fn fifth_note_steals_the_oldest() {
+        let mut allocator = Allocator::new();
+        for note in 60..64 {
+            allocator.note_on(note);
+        }
+        assert_eq!(allocator.note_on(70), 0);
+        assert_eq!(allocator.note(0), Some(70));
+        assert_eq!(allocator.note_on(71), 1);
+    }
+
+    #[test]
+    fn repeated_note_keeps_its_voice() {
+        let mut allocator = Allocator::new();
+        allocator.note_on(60);
+        allocator.note_on(62);
+        assert_eq!(allocator.note_on(60), 0);
+        assert_eq!(allocator.note_off(61), None);
+    }
+
+    #[test]
+    fn silent_voices_mix_to_the_middle() {
+        let voices = Voices::new();
+        assert_eq!(voices.mix(0, |_, _| 255), 128);
+        voices.oscs[0].set_step(1 << 20);
+        voices.oscs[1].set_step(1 << 20);
+        assert_eq!(voices.mix(0, |_, _| 0), 64);
+    }
+
+    #[test]
+    fn freed_voice_has_no_note() {
+        let voices = Voices::new();
+        voices.set_note(2, Some(60));
+        assert_eq!(voices.note(2), Some(60));
+        voices.set_note(2, None);
+        assert_eq!(voices.note(2), None);
+        assert_eq!(voices.note(VOICES), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! Power-on self-test: checks run from `init` before any task starts, and the
+//! failures they find, blinked as codes on the tuning LED.
+//!
+//! Failures only get reported, the module still starts: a unit with a dead
+//! display or a bad settings page is better off playing than stuck in a loop.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+#[derive(Clone, Copy)]
+pub enum Check {
+    /// The internal reference reads where it should, so the ADC and the
+    /// supply are fine.
+    Vref,
+    /// Every DAC port pin follows what is written to it.
+    Dac,
+    /// The square output follows what is written to it.
+    Output,
+    /// The saved settings pass their CRC.
+    Settings,
+}
+
+pub const CHECKS: [Check; 4] = [Check::Vref, Check::Dac, Check::Output, Check::Settings];
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Vref => "vref",
+            Check::Dac => "dac",
+            Check::Output => "output",
+            Check::Settings => "settings",
+        }
+    }
+
+    /// Blinks in the check's LED code.
+    pub fn blinks(self) -> u8 {
+        (self as u8).saturating_add(1)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// VREFINT is 1.16-1.24 V, read as 12 bits of a 3.0-3.6 V supply.
+const VREF_MIN: u16 = 1320;
+const VREF_MAX: u16 = 1692;
+
+pub fn vref_ok(sample: u16) -> bool {
+    (VREF_MIN..=VREF_MAX).contains(&sample)
+}
+
+/// Drives walking ones over the low `bits` pins, then all of them low,
+/// reading each pattern back. A pin that is stuck, or shorted to a
+/// neighbour, reads wrong.
+pub fn walk(bits: u32, mut drive: impl FnMut(u32), mut read: impl FnMut() -> u32) -> bool {
+    let mask = 1u32
+        .checked_shl(bits)
+        .map_or(u32::MAX, |b| b.wrapping_sub(1));
+    let mut ok = true;
+    for pattern in (0..bits).filter_map(|i| 1u32.checked_shl(i)) {
+        drive(pattern);
+        ok &= read() & mask == pattern;
+    }
+    drive(0);
+    ok &= read() & mask == 0;
+    ok
+}
+
+/// Checks that failed, in [`CHECKS`] order.
+#[derive(Clone, Copy)]
+pub struct Failures(u8);
+
+impl Failures {
+    pub const fn new() -> Self {
+        Failures(0)
+    }
+
+    pub fn record(&mut self, check: Check, ok: bool) {
+        if !ok {
+            self.0 |= check.bit();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Check> {
+        CHECKS
+            .iter()
+            .copied()
+            .filter(move |check| self.0 & check.bit() != 0)
+    }
+}
+
+impl Default for Failures {
+    fn default() -> Self {
+        Self::new()
+    }
+}
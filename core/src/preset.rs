@@ -0,0 +1,149 @@
+//! Presets: named snapshots of the pages that shape the sound, kept in the
+//! settings storage and recalled from the menu, MIDI program changes or the
+//! console.
+//!
+//! Stored presets are read back from flash, which may hold anything.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::params::{Param, Params};
+use crate::storage::{self, Flash, Storage};
+
+/// Presets are numbered from 1, as on the menu.
+pub const SLOTS: u8 = 8;
+
+/// Longest name.
+pub const NAME: usize = 8;
+
+/// Pages a preset holds. Pages added at the end miss from older presets and
+/// keep their current values when those are recalled.
+pub const PAGES: [Param; 6] = [
+    Param::Dac,
+    Param::Octave,
+    Param::Glide,
+    Param::Sync,
+    Param::PulseWidth,
+    Param::Detune,
+];
+
+/// Storage key of preset 1, the others follow.
+const KEY: u8 = 0x10;
+
+/// Name length, the name padded to [`NAME`], the page count, then every
+/// value as a little-endian `i16`.
+const LEN: usize = 1 + NAME + 1 + 2 * PAGES.len();
+
+#[derive(Clone, Copy)]
+pub struct Preset {
+    name: [u8; NAME],
+    name_len: usize,
+    values: [i32; PAGES.len()],
+    /// Pages the preset was saved with.
+    count: usize,
+}
+
+impl Preset {
+    /// Takes the current values of [`PAGES`]. The name is cut to [`NAME`]
+    /// characters and may only hold printable ASCII.
+    pub fn capture(name: &str, params: &Params) -> Self {
+        let mut preset = Preset {
+            name: [0; NAME],
+            name_len: 0,
+            values: [0; PAGES.len()],
+            count: PAGES.len(),
+        };
+        let name = name.bytes().filter(u8::is_ascii_graphic);
+        for (slot, b) in preset.name.iter_mut().zip(name) {
+            *slot = b;
+            preset.name_len = preset.name_len.saturating_add(1);
+        }
+        for (value, &p) in preset.values.iter_mut().zip(PAGES.iter()) {
+            *value = params.get(p);
+        }
+        preset
+    }
+
+    pub fn name(&self) -> &str {
+        let bytes = self.name.get(..self.name_len).unwrap_or(&[]);
+        core::str::from_utf8(bytes).unwrap_or("")
+    }
+
+    pub fn apply(&self, params: &Params) {
+        for (&p, &value) in PAGES.iter().zip(self.values.iter()).take(self.count) {
+            params.set(p, value);
+        }
+    }
+
+    fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0; LEN];
+        let (head, values) = bytes.split_at_mut(NAME.saturating_add(2));
+        if let [len, name @ .., count] = head {
+            *len = self.name_len as u8;
+            name.copy_from_slice(&self.name);
+            *count = PAGES.len() as u8;
+        }
+        for (pair, &value) in values.chunks_exact_mut(2).zip(self.values.iter()) {
+            pair.copy_from_slice(&(value as i16).to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&name_len, rest) = bytes.split_first()?;
+        let name_len = name_len as usize;
+        let name = rest.get(..NAME)?;
+        if !name.get(..name_len)?.iter().all(u8::is_ascii_graphic) {
+            return None;
+        }
+        let (&count, values) = rest.get(NAME..)?.split_first()?;
+
+        let mut preset = Preset {
+            name: [0; NAME],
+            name_len,
+            values: [0; PAGES.len()],
+            count: 0,
+        };
+        preset.name.copy_from_slice(name);
+        let stored = values.chunks_exact(2).take(count as usize);
+        for (value, pair) in preset.values.iter_mut().zip(stored) {
+            if let &[low, high] = pair {
+                *value = i16::from_le_bytes([low, high]) as i32;
+                preset.count = preset.count.saturating_add(1);
+            }
+        }
+        Some(preset)
+    }
+}
+
+/// Reads a preset number as typed, `None` outside 1 to [`SLOTS`].
+pub fn parse_slot(text: &str) -> Option<u8> {
+    text.parse().ok().filter(|slot| key(*slot).is_some())
+}
+
+fn key(slot: u8) -> Option<u8> {
+    if slot == 0 || slot > SLOTS {
+        return None;
+    }
+    KEY.checked_add(slot.checked_sub(1)?)
+}
+
+/// The preset saved as `slot`, `None` for empty slots.
+pub fn load<F: Flash>(storage: &Storage<F>, slot: u8) -> Option<Preset> {
+    let mut buf = [0; storage::MAX_LEN];
+    let len = storage.read(key(slot)?, &mut buf)?;
+    Preset::decode(buf.get(..len)?)
+}
+
+pub fn save<F: Flash>(
+    storage: &mut Storage<F>,
+    slot: u8,
+    preset: &Preset,
+) -> Result<(), storage::Error> {
+    let key = key(slot).ok_or(storage::Error::BadKey)?;
+    storage.write(key, &preset.encode())
+}
@@ -0,0 +1,184 @@
+//! Execution time of the interrupt handlers and the CPU load they add up to,
+//! from the DWT cycle counter.
+//!
+//! Times are exclusive: a handler preempted by a higher priority one isn't
+//! charged for it, so the load is the plain sum of what every handler took.
+//!
+//! Runs inside the tick, and every other handler.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Clone, Copy)]
+pub enum Task {
+    Tick,
+    Measure,
+    HardSync,
+    Encoder,
+    DacRefill,
+}
+
+pub const TASKS: [Task; 5] = [
+    Task::Tick,
+    Task::Measure,
+    Task::HardSync,
+    Task::Encoder,
+    Task::DacRefill,
+];
+
+impl Task {
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Tick => "tick",
+            Task::Measure => "measure",
+            Task::HardSync => "hard_sync",
+            Task::Encoder => "encoder",
+            Task::DacRefill => "dac_refill",
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+
+/// Running figures for one handler. Only the handler itself writes them.
+struct Stats {
+    min: AtomicU32,
+    max: AtomicU32,
+    total: AtomicU32,
+    count: AtomicU32,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const STATS: Stats = Stats {
+    min: AtomicU32::new(u32::MAX),
+    max: ZERO,
+    total: ZERO,
+    count: ZERO,
+};
+
+/// Cycles one handler took over a report window.
+#[derive(Clone, Copy, Default)]
+pub struct Summary {
+    pub min: u32,
+    pub max: u32,
+    pub avg: u32,
+    pub count: u32,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Report {
+    /// In [`TASKS`] order.
+    pub tasks: [Summary; 5],
+    /// Share of the window spent in the profiled handlers, in tenths of a
+    /// percent. The rest is idle.
+    pub load_permille: u32,
+}
+
+pub struct Profiler {
+    stats: [Stats; 5],
+    /// Exclusive cycles of every handler since boot, wrapping.
+    busy: AtomicU32,
+    window_start: AtomicU32,
+    window_busy: AtomicU32,
+}
+
+impl Profiler {
+    pub const fn new() -> Self {
+        Profiler {
+            stats: [STATS; 5],
+            busy: ZERO,
+            window_start: ZERO,
+            window_busy: ZERO,
+        }
+    }
+
+    /// Starts timing `task` until the span is dropped, reading the cycle
+    /// counter through `clock`.
+    pub fn span(&self, task: Task, clock: fn() -> u32) -> Span<'_> {
+        Span {
+            profiler: self,
+            task,
+            clock,
+            start: clock(),
+            busy: self.busy.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, task: Task, cycles: u32) {
+        self.busy.fetch_add(cycles, Ordering::Relaxed);
+        if let Some(s) = self.stats.get(task as usize) {
+            if cycles < s.min.load(Ordering::Relaxed) {
+                s.min.store(cycles, Ordering::Relaxed);
+            }
+            if cycles > s.max.load(Ordering::Relaxed) {
+                s.max.store(cycles, Ordering::Relaxed);
+            }
+            s.total.fetch_add(cycles, Ordering::Relaxed);
+            s.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Figures since the last report, at cycle count `now`, starting the next
+    /// window. Windows must stay under a minute, where the counter wraps.
+    pub fn report(&self, now: u32) -> Report {
+        let mut report = Report::default();
+        for (summary, s) in report.tasks.iter_mut().zip(self.stats.iter()) {
+            let count = s.count.swap(0, Ordering::Relaxed);
+            let total = s.total.swap(0, Ordering::Relaxed);
+            *summary = Summary {
+                min: s.min.swap(u32::MAX, Ordering::Relaxed),
+                max: s.max.swap(0, Ordering::Relaxed),
+                avg: total.checked_div(count).unwrap_or(0),
+                count,
+            };
+            if count == 0 {
+                summary.min = 0;
+            }
+        }
+
+        let busy = self.busy.load(Ordering::Relaxed);
+        let elapsed = now.wrapping_sub(self.window_start.swap(now, Ordering::Relaxed));
+        let spent = busy.wrapping_sub(self.window_busy.swap(busy, Ordering::Relaxed));
+        report.load_permille = ((spent as u64).saturating_mul(1000))
+            .checked_div(elapsed as u64)
+            .unwrap_or(0) as u32;
+        report
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Times a handler from [`Profiler::span`] until dropped, so early returns
+/// are counted too.
+pub struct Span<'a> {
+    profiler: &'a Profiler,
+    task: Task,
+    clock: fn() -> u32,
+    start: u32,
+    /// Busy count at the start, to take out the handlers that preempt this one.
+    busy: u32,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        let elapsed = (self.clock)().wrapping_sub(self.start);
+        let preempted = self
+            .profiler
+            .busy
+            .load(Ordering::Relaxed)
+            .wrapping_sub(self.busy);
+        self.profiler
+            .record(self.task, elapsed.saturating_sub(preempted));
+    }
+}
@@ -0,0 +1,106 @@
+//! Input event recording for offline replay.
+//!
+//! Events are timestamped with the cycle counter and buffered in RAM until the
+//! drain task streams them to the host, where they can be fed back into the
+//! oscillator core to reproduce a glitch deterministically.
+//!
+//! Recorded from the sync, serial and encoder interrupts and the publish
+//! task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+pub const CAPACITY: usize = 256;
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum Kind {
+    AdcAverage = 0,
+    SyncEdge = 1,
+    EncoderSteps = 2,
+    /// A byte from the MIDI input, before parsing.
+    MidiByte = 3,
+}
+
+impl Kind {
+    /// The kind logged as `kind as u8`, for reading a recording back.
+    pub fn from_u8(kind: u8) -> Option<Kind> {
+        match kind {
+            0 => Some(Kind::AdcAverage),
+            1 => Some(Kind::SyncEdge),
+            2 => Some(Kind::EncoderSteps),
+            3 => Some(Kind::MidiByte),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Event {
+    pub time: u32,
+    pub kind: Kind,
+    pub value: i32,
+}
+
+/// Fixed-size FIFO of input events. New events are dropped (and counted) while
+/// it is full, so a gap in the replay is always detectable.
+pub struct Recorder {
+    events: [Event; CAPACITY],
+    head: usize,
+    len: usize,
+    dropped: u32,
+}
+
+impl Recorder {
+    pub const fn new() -> Self {
+        Recorder {
+            events: [Event {
+                time: 0,
+                kind: Kind::AdcAverage,
+                value: 0,
+            }; CAPACITY],
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn record(&mut self, time: u32, kind: Kind, value: i32) {
+        if self.len == CAPACITY {
+            self.dropped = self.dropped.wrapping_add(1);
+            return;
+        }
+
+        let tail = self.head.wrapping_add(self.len) % CAPACITY;
+        if let Some(slot) = self.events.get_mut(tail) {
+            *slot = Event { time, kind, value };
+            self.len = self.len.saturating_add(1);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = *self.events.get(self.head)?;
+        self.head = self.head.wrapping_add(1) % CAPACITY;
+        self.len = self.len.saturating_sub(1);
+        Some(event)
+    }
+
+    /// Returns the number of events dropped since the last call.
+    pub fn take_dropped(&mut self) -> u32 {
+        core::mem::replace(&mut self.dropped, 0)
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,115 @@
+//! Timing of the pitch CV conversions against the tick, for the `adc-sync`
+//! feature.
+//!
+//! Every output edge comes out of the tick: the oscillator's phase only
+//! moves there, and the pins follow it in the tick handler or by DMA on the
+//! TIM3 update. The last stretch before the next update is the quiet part,
+//! so a conversion started from a TIM3 compare is placed to close its
+//! sample and hold right before it, whatever the clocks.
+
+/// Sampling time of the CV conversions, 13.5 ADC clocks, in half clocks,
+/// and its SMPx code. The tick is only 70 ADC clocks at 14 MHz, 60 at 12,
+/// so the ADC's usual 239.5 would always reach back over the tick's writes.
+/// With the margin the window takes the end of the 5 µs tick, 1.1 µs or the
+/// last 31 of 140 cycles at 28 MHz, 1.3 µs or 93 of 360 at 72 MHz. The tick
+/// handler has to have written its pins by then, which a `profile` max of
+/// the tick below about 95 cycles at 28 MHz shows, entry and exit on top.
+/// It wants the CV input stage to drive the pin from under 11 kΩ.
+const SAMPLE_HALF_CLOCKS: u64 = 27;
+pub const SAMPLE_TIME_BITS: u32 = 0b010;
+
+/// ADC clocks between the end of sampling and the next update: one for the
+/// trigger's resynchronization to the ADC clock, one to spare.
+const MARGIN_CLOCKS: u64 = 2;
+
+/// TIM3 compare count, out of `period` counts per tick at `timer_hz`, that
+/// starts a conversion so its sampling at `adc_hz` ends just before a later
+/// update. Between 1 and `period - 1`, so the compare fires every tick.
+pub fn trigger_count(period: u32, timer_hz: u32, adc_hz: u32) -> u32 {
+    if period < 2 || adc_hz == 0 {
+        return 1;
+    }
+    let period = period as u64;
+    let per_clock = timer_hz as u64;
+    let adc_hz = adc_hz as u64;
+    // Timer counts, rounded up
+    let counts = |half_clocks: u64| {
+        half_clocks
+            .saturating_mul(per_clock)
+            .saturating_add(adc_hz.saturating_mul(2).saturating_sub(1))
+            .checked_div(adc_hz.saturating_mul(2))
+            .unwrap_or(0)
+    };
+    let sample = counts(SAMPLE_HALF_CLOCKS).checked_rem(period).unwrap_or(0);
+    let margin = counts(MARGIN_CLOCKS.saturating_mul(2)).min(period.saturating_sub(1));
+    // Where sampling has to end within a tick, then back from it by the
+    // sampling time, wrapped into the tick
+    let end = period.saturating_sub(margin);
+    let count = end
+        .saturating_add(period)
+        .saturating_sub(sample)
+        .checked_rem(period)
+        .unwrap_or(0);
+    count.clamp(1, period.saturating_sub(1)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_ends_the_margin_before_the_update() {
+        // 28 MHz: 27 counts of sampling and 4 of margin in a 140 count tick
+        assert_eq!(trigger_count(140, 28_000_000, 14_000_000), 109);
+        // `clock-72mhz`: 81 and 12 in 360
+        assert_eq!(trigger_count(360, 72_000_000, 12_000_000), 267);
+    }
+
+    #[test]
+    fn uneven_counts_round_up() {
+        // 31.5 counts of sampling and 4.67 of margin, so 32 and 5
+        assert_eq!(trigger_count(140, 28_000_000, 12_000_000), 103);
+    }
+
+    #[test]
+    fn sampling_longer_than_a_tick_wraps_into_the_one_before() {
+        // 27 counts of sampling in a 20 count tick start 7 before the end of
+        // the margin, in the tick before
+        assert_eq!(trigger_count(20, 28_000_000, 14_000_000), 9);
+    }
+
+    #[test]
+    fn compare_at_the_wrap_moves_inside_the_tick() {
+        // Sampling would start right on the update, the compare can't fire
+        // there
+        assert_eq!(trigger_count(31, 28_000_000, 14_000_000), 1);
+        assert_eq!(trigger_count(32, 28_000_000, 14_000_000), 1);
+        // Or right before it
+        assert_eq!(trigger_count(30, 28_000_000, 14_000_000), 29);
+    }
+
+    #[test]
+    fn degenerate_periods_and_clocks() {
+        assert_eq!(trigger_count(0, 28_000_000, 14_000_000), 1);
+        assert_eq!(trigger_count(1, 28_000_000, 14_000_000), 1);
+        assert_eq!(trigger_count(2, 28_000_000, 14_000_000), 1);
+        assert_eq!(trigger_count(140, 28_000_000, 0), 1);
+        assert_eq!(
+            trigger_count(u32::MAX, 28_000_000, 14_000_000),
+            u32::MAX - 31
+        );
+        // A margin longer than the tick leaves one count before the update,
+        // and sampling 13.5 ticks long starts half a tick before it
+        assert_eq!(trigger_count(u32::MAX, u32::MAX, 1), 1 << 31);
+    }
+
+    #[test]
+    fn compare_always_fires_within_the_tick() {
+        for period in 2..2000 {
+            for &(timer_hz, adc_hz) in &[(28_000_000, 14_000_000), (72_000_000, 12_000_000)] {
+                let count = trigger_count(period, timer_hz, adc_hz);
+                assert!(count >= 1 && count < period, "{} of {}", count, period);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! 4-digit multiplexed 7-segment readout behind two chained 74HC595s.
+//!
+//! The register nearest the MCU drives the segments (active high, bit 0 is
+//! segment a through bit 6 for g, bit 7 the decimal point), the second one
+//! selects the digit (active low, bit 0 is the leftmost digit).
+
+use embedded_hal::digital::v2::OutputPin;
+
+pub const DIGITS: usize = 4;
+
+const DP: u8 = 1 << 7;
+const DASH: u8 = 1 << 6;
+
+/// Segment patterns for one refresh, leftmost digit first.
+pub type Frame = [u8; DIGITS];
+
+const DIGIT_SEGMENTS: [u8; 10] = [0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f];
+
+/// Bit-banged shift register chain.
+pub struct Hc595<DATA, CLOCK, LATCH> {
+    data: DATA,
+    clock: CLOCK,
+    latch: LATCH,
+}
+
+impl<DATA, CLOCK, LATCH, E> Hc595<DATA, CLOCK, LATCH>
+where
+    DATA: OutputPin<Error = E>,
+    CLOCK: OutputPin<Error = E>,
+    LATCH: OutputPin<Error = E>,
+{
+    pub fn new(data: DATA, clock: CLOCK, latch: LATCH) -> Self {
+        Hc595 { data, clock, latch }
+    }
+
+    /// Lights `segments` on digit `digit` only.
+    pub fn show(&mut self, digit: usize, segments: u8) -> Result<(), E> {
+        let select = !(1u8 << (digit % DIGITS));
+        // The far register gets its byte first
+        let word = (select as u16) << 8 | segments as u16;
+
+        self.latch.set_low()?;
+        for bit in (0..16).rev() {
+            self.clock.set_low()?;
+            if word & (1 << bit) != 0 {
+                self.data.set_high()?;
+            } else {
+                self.data.set_low()?;
+            }
+            self.clock.set_high()?;
+        }
+        self.latch.set_high()
+    }
+}
+
+/// Frequency with as many decimals as fit, switching to kHz from 10 kHz.
+pub fn hz(hz: f32) -> Frame {
+    if !(0.0..100_000.0).contains(&hz) {
+        return [DASH; DIGITS];
+    }
+
+    let (scaled, point) = if hz < 10.0 {
+        (hz * 1000.0, Some(0))
+    } else if hz < 100.0 {
+        (hz * 100.0, Some(1))
+    } else if hz < 1000.0 {
+        (hz * 10.0, Some(2))
+    } else if hz < 10_000.0 {
+        (hz, None)
+    } else {
+        (hz / 10.0, Some(1))
+    };
+
+    let mut value = ((scaled + 0.5) as u32).min(9999);
+    let mut frame = [0; DIGITS];
+    for (i, segments) in frame.iter_mut().enumerate().rev() {
+        *segments = DIGIT_SEGMENTS[(value % 10) as usize];
+        if point == Some(i) {
+            *segments |= DP;
+        }
+        value /= 10;
+    }
+    frame
+}
+
+/// Note name and octave, a sharp shown as the decimal point after the letter.
+pub fn note(name: &str, octave: i32) -> Frame {
+    let mut frame = [0; DIGITS];
+    let mut bytes = name.bytes();
+
+    frame[0] = match bytes.next() {
+        Some(b'A') => 0x77,
+        Some(b'B') => 0x7c,
+        Some(b'C') => 0x39,
+        Some(b'D') => 0x5e,
+        Some(b'E') => 0x79,
+        Some(b'F') => 0x71,
+        Some(b'G') => 0x3d,
+        _ => DASH,
+    };
+    if bytes.next() == Some(b'#') {
+        frame[0] |= DP;
+    }
+
+    if octave < 0 {
+        frame[2] = DASH;
+    }
+    frame[3] = DIGIT_SEGMENTS[(octave.unsigned_abs() % 10) as usize];
+    frame
+}
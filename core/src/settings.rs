@@ -0,0 +1,120 @@
+//! Settings that survive a power cycle: every page value in one versioned
+//! record with its own CRC.
+//!
+//! A record that fails its CRC, or comes from another layout, is ignored and
+//! the pages keep their compile-time defaults until the next save replaces
+//! it. Adding or removing a page changes the layout. Loaded values are still
+//! clamped to their page ranges, so even a record that passes both checks
+//! can't drive the oscillator anywhere the menu couldn't.
+//!
+//! Stored settings are read back from flash, which may hold anything.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::crc::Crc32;
+use crate::params::{self, Params};
+use crate::storage::{self, Flash, Storage};
+
+/// Layout version, bumped whenever the meaning of the values changes.
+pub const VERSION: u8 = 1;
+
+/// Storage key of the settings record.
+const KEY: u8 = 0x01;
+
+const CRC: usize = 4;
+
+/// Version, page count, every value as a little-endian `i32` in menu order,
+/// then a CRC-32 over all of it.
+const LEN: usize = 2 + 4 * params::COUNT + CRC;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Nothing saved yet.
+    Missing,
+    Corrupt,
+    /// Saved by firmware with another layout.
+    OldLayout,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Settings {
+    values: [i32; params::COUNT],
+}
+
+impl Settings {
+    pub fn capture(params: &Params) -> Self {
+        let mut settings = Settings {
+            values: [0; params::COUNT],
+        };
+        for (value, p) in settings.values.iter_mut().zip(params::all()) {
+            *value = params.get(p);
+        }
+        settings
+    }
+
+    pub fn apply(&self, params: &Params) {
+        for (&value, p) in self.values.iter().zip(params::all()) {
+            params.set(p, value);
+        }
+    }
+
+    fn encode(&self) -> [u8; LEN] {
+        let mut bytes = [0; LEN];
+        let (body, crc) = bytes.split_at_mut(LEN.saturating_sub(CRC));
+        if let [version, count, values @ ..] = body {
+            *version = VERSION;
+            *count = params::COUNT as u8;
+            for (chunk, value) in values.chunks_exact_mut(4).zip(self.values.iter()) {
+                chunk.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        let mut sum = Crc32::new();
+        sum.update(body);
+        crc.copy_from_slice(&sum.finish().to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let split = bytes.len().checked_sub(CRC).ok_or(Error::Corrupt)?;
+        let body = bytes.get(..split).ok_or(Error::Corrupt)?;
+        let mut sum = Crc32::new();
+        sum.update(body);
+        if bytes.get(split..) != Some(&sum.finish().to_le_bytes()[..]) {
+            return Err(Error::Corrupt);
+        }
+
+        match *body {
+            [VERSION, count, ref values @ ..]
+                if count as usize == params::COUNT
+                    && values.len() == LEN.saturating_sub(2 + CRC) =>
+            {
+                let mut settings = Settings {
+                    values: [0; params::COUNT],
+                };
+                for (value, chunk) in settings.values.iter_mut().zip(values.chunks_exact(4)) {
+                    if let &[a, b, c, d] = chunk {
+                        *value = i32::from_le_bytes([a, b, c, d]);
+                    }
+                }
+                Ok(settings)
+            }
+            _ => Err(Error::OldLayout),
+        }
+    }
+}
+
+pub fn load<F: Flash>(storage: &Storage<F>) -> Result<Settings, Error> {
+    let mut buf = [0; storage::MAX_LEN];
+    let len = storage.read(KEY, &mut buf).ok_or(Error::Missing)?;
+    Settings::decode(buf.get(..len).unwrap_or(&[]))
+}
+
+/// Saves `settings`, leaving the flash alone if they haven't changed.
+pub fn save<F: Flash>(storage: &mut Storage<F>, settings: &Settings) -> Result<(), storage::Error> {
+    storage.write(KEY, &settings.encode())
+}
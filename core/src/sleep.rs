@@ -0,0 +1,42 @@
+//! Auto-sleep: notices when the CV, the pitch and the controls have all sat
+//! still for a long time, so the module can stop refreshing the DAC and slow
+//! its measurement down until something moves again.
+//!
+//! Polled from the UI task.
+
+/// Change in mV that counts as movement, above the CV noise.
+const STILL_MV: i32 = 20;
+
+pub struct AutoSleep {
+    /// Polls without movement before going to sleep.
+    after: u32,
+    quiet: u32,
+    // Readings the movement is measured from
+    cv_mv: i32,
+    pitch_mv: i32,
+}
+
+impl AutoSleep {
+    pub const fn new(after: u32) -> Self {
+        AutoSleep {
+            after,
+            quiet: 0,
+            cv_mv: 0,
+            pitch_mv: 0,
+        }
+    }
+
+    /// One poll, with `touched` set for any control or page change. Returns
+    /// whether it is time to sleep; the first movement wakes it up again.
+    pub fn update(&mut self, cv_mv: i32, pitch_mv: i32, touched: bool) -> bool {
+        let moved = |from: i32, to: i32| to.saturating_sub(from).saturating_abs() > STILL_MV;
+        if touched || moved(self.cv_mv, cv_mv) || moved(self.pitch_mv, pitch_mv) {
+            self.cv_mv = cv_mv;
+            self.pitch_mv = pitch_mv;
+            self.quiet = 0;
+        } else {
+            self.quiet = self.quiet.saturating_add(1);
+        }
+        self.quiet >= self.after
+    }
+}
@@ -0,0 +1,70 @@
+//! Pitch source arbitration: which of the CV and the MIDI note sets the pitch
+//! when both are live, as the `src` page says.
+//!
+//! Runs in the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::params::{SOURCE_LAST, SOURCE_MIDI, SOURCE_SUM};
+use crate::trigger::NoteChange;
+
+/// What sets the pitch for one publish.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    Cv,
+    /// The note alone, the CV ignored.
+    Midi(u8),
+    /// The CV transposed by the note.
+    Sum(u8),
+}
+
+/// Follows both inputs on every publish, so `last` knows which one moved most
+/// recently whatever `src` was set to before.
+pub struct Arbiter {
+    cv: NoteChange,
+    note: Option<u8>,
+    midi_last: bool,
+}
+
+impl Arbiter {
+    pub const fn new() -> Self {
+        Arbiter {
+            cv: NoteChange::new(),
+            note: None,
+            midi_last: false,
+        }
+    }
+
+    /// Picks the source for `src` from the CV's pitch, before any offsets,
+    /// and the MIDI note playing, if any.
+    pub fn select(&mut self, src: i32, cv_pitch_mv: f32, note: Option<u8>) -> Source {
+        // The CV has to settle on a new semitone to count, so noise on it
+        // doesn't take the pitch back from MIDI
+        let cv_moved = self.cv.update(cv_pitch_mv);
+        let note_moved = note.is_some() && note != self.note;
+        self.note = note;
+        if note_moved {
+            self.midi_last = true;
+        } else if cv_moved {
+            self.midi_last = false;
+        }
+
+        match (src, note) {
+            (SOURCE_MIDI, Some(note)) => Source::Midi(note),
+            (SOURCE_SUM, Some(note)) => Source::Sum(note),
+            (SOURCE_LAST, Some(note)) if self.midi_last => Source::Midi(note),
+            _ => Source::Cv,
+        }
+    }
+}
+
+impl Default for Arbiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,480 @@
+//! Settings storage: EEPROM-style key-value records in two flash pages, for
+//! everything that has to survive a power cycle.
+//!
+//! Records are appended to the active page, so a value rewritten a hundred
+//! times costs one erase instead of a hundred. When the page is full the
+//! latest record of every key moves to the other page, which becomes the
+//! active one, and the old page is erased. Each page wears evenly, and every
+//! step of the move can lose power without losing a value.
+//!
+//! A page starts with its state half-word. Each record is a header
+//! half-word, key in the high byte and length in bytes in the low one, the
+//! data padded to whole half-words, and a CRC-32 over key, length and data.
+//! The first erased header ends the page.
+//!
+//! Flash contents are parsed at boot, whatever they hold.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::crc::Crc32;
+
+pub const PAGES: usize = 2;
+pub const PAGE: usize = 1024;
+pub const ERASED: u16 = 0xffff;
+
+/// Page states. Flash bits only program from one to zero, so each state can
+/// be written over the previous one.
+const RECEIVING: u16 = 0xeeee;
+const VALID: u16 = 0x0000;
+
+const HEADER: usize = 2;
+const CRC: usize = 4;
+
+/// Key 0xff could make an erased header.
+pub const MAX_KEY: u8 = 0xfe;
+pub const MAX_LEN: usize = 0xff;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Error {
+    /// The flash controller reported an error.
+    Flash,
+    BadKey,
+    TooLong,
+    /// The latest records of all keys don't fit in one page.
+    Full,
+}
+
+/// Half-word access to the two storage pages, with offsets in bytes.
+pub trait Flash {
+    /// Reads past the pages return [`ERASED`].
+    fn read(&self, page: usize, offset: usize) -> u16;
+    fn erase(&mut self, page: usize) -> Result<(), Error>;
+    /// Programs an erased half-word, or zeroes a programmed one.
+    fn program(&mut self, page: usize, offset: usize, value: u16) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    offset: usize,
+    key: u8,
+    len: usize,
+}
+
+impl Record {
+    fn size(&self) -> usize {
+        record_size(self.len)
+    }
+
+    fn data_offset(&self) -> usize {
+        self.offset.saturating_add(HEADER)
+    }
+}
+
+fn record_size(len: usize) -> usize {
+    HEADER
+        .saturating_add(len.saturating_add(1) & !1)
+        .saturating_add(CRC)
+}
+
+/// Records of one page in order, valid or not, and where they end.
+struct Records<'a, F> {
+    flash: &'a F,
+    page: usize,
+    offset: usize,
+}
+
+impl<F: Flash> Iterator for Records<'_, F> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        let header = self.flash.read(self.page, self.offset);
+        if header == ERASED {
+            return None;
+        }
+        let [len, key] = header.to_le_bytes();
+        let record = Record {
+            offset: self.offset,
+            key,
+            len: len as usize,
+        };
+        let end = self.offset.saturating_add(record.size());
+        if end > PAGE {
+            return None;
+        }
+        self.offset = end;
+        Some(record)
+    }
+}
+
+pub struct Storage<F> {
+    flash: F,
+    active: usize,
+    /// Offset of the first free header in the active page.
+    end: usize,
+}
+
+impl<F: Flash> Storage<F> {
+    /// Finds the active page, finishing or undoing a move that lost power,
+    /// and formats blank or corrupt storage.
+    pub fn mount(mut flash: F) -> Result<Self, Error> {
+        let states = (flash.read(0, 0), flash.read(1, 0));
+        let active = match states {
+            (VALID, ERASED) => 0,
+            (ERASED, VALID) => 1,
+            // Copy interrupted, the old page is still whole
+            (VALID, RECEIVING) => {
+                flash.erase(1)?;
+                0
+            }
+            (RECEIVING, VALID) => {
+                flash.erase(0)?;
+                1
+            }
+            // Copy done and the old page erased, only the state is missing
+            (RECEIVING, ERASED) => {
+                flash.program(0, 0, VALID)?;
+                0
+            }
+            (ERASED, RECEIVING) => {
+                flash.program(1, 0, VALID)?;
+                1
+            }
+            _ => {
+                flash.erase(0)?;
+                flash.erase(1)?;
+                flash.program(0, 0, VALID)?;
+                0
+            }
+        };
+
+        let mut storage = Storage {
+            flash,
+            active,
+            end: HEADER,
+        };
+        storage.end = storage.scan_end(active);
+        Ok(storage)
+    }
+
+    /// Copies the latest value of `key` into `buf`, returning its length.
+    /// Bytes that don't fit in `buf` are dropped.
+    pub fn read(&self, key: u8, buf: &mut [u8]) -> Option<usize> {
+        let record = self.latest(self.active, key)?;
+        for (i, slot) in buf.iter_mut().enumerate().take(record.len) {
+            *slot = self.data_byte(self.active, &record, i);
+        }
+        Some(record.len)
+    }
+
+    /// Stores `data` under `key`. Writing the value already stored doesn't
+    /// touch the flash.
+    pub fn write(&mut self, key: u8, data: &[u8]) -> Result<(), Error> {
+        if key > MAX_KEY {
+            return Err(Error::BadKey);
+        }
+        if data.len() > MAX_LEN || record_size(data.len()) > PAGE.saturating_sub(HEADER) {
+            return Err(Error::TooLong);
+        }
+        if let Some(record) = self.latest(self.active, key) {
+            let same = record.len == data.len()
+                && data
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &b)| self.data_byte(self.active, &record, i) == b);
+            if same {
+                return Ok(());
+            }
+        }
+
+        let fits = self.end.saturating_add(record_size(data.len())) <= PAGE;
+        // A failed write leaves a broken record behind, the move drops it
+        if fits && self.append(self.active, self.end, key, data).is_ok() {
+            self.end = self.end.saturating_add(record_size(data.len()));
+            return Ok(());
+        }
+        self.move_page(key, data)
+    }
+
+    /// Moves to the other page: the new record first, then the latest of
+    /// every other key.
+    fn move_page(&mut self, key: u8, data: &[u8]) -> Result<(), Error> {
+        let old = self.active;
+        let new = (old.wrapping_add(1)) % PAGES;
+
+        self.flash.erase(new)?;
+        self.flash.program(new, 0, RECEIVING)?;
+        self.append(new, HEADER, key, data)?;
+        let mut end = HEADER.saturating_add(record_size(data.len()));
+
+        let records = Records {
+            flash: &self.flash,
+            page: old,
+            offset: HEADER,
+        };
+        // Collected first, `append` needs the flash mutably
+        let mut keys = [false; MAX_KEY as usize + 1];
+        for record in records {
+            if let Some(seen) = keys.get_mut(record.key as usize) {
+                *seen = record.key != key && record.key <= MAX_KEY;
+            }
+        }
+        for k in 0..=MAX_KEY {
+            if !keys.get(k as usize).copied().unwrap_or(false) {
+                continue;
+            }
+            let record = match self.latest(old, k) {
+                Some(record) => record,
+                None => continue,
+            };
+            let size = record.size();
+            if end.saturating_add(size) > PAGE {
+                return Err(Error::Full);
+            }
+            let mut buf = [0; MAX_LEN];
+            for (i, slot) in buf.iter_mut().enumerate().take(record.len) {
+                *slot = self.data_byte(old, &record, i);
+            }
+            self.append(new, end, k, buf.get(..record.len).unwrap_or(&[]))?;
+            end = end.saturating_add(size);
+        }
+
+        self.flash.erase(old)?;
+        self.flash.program(new, 0, VALID)?;
+        self.active = new;
+        self.end = end;
+        Ok(())
+    }
+
+    fn append(&mut self, page: usize, offset: usize, key: u8, data: &[u8]) -> Result<(), Error> {
+        let len = data.len() as u8;
+        let mut crc = Crc32::new();
+        crc.update(&[key, len]);
+        crc.update(data);
+        let crc = crc.finish().to_le_bytes();
+
+        self.flash
+            .program(page, offset, u16::from_le_bytes([len, key]))?;
+        let mut at = offset.saturating_add(HEADER);
+        for pair in data.chunks(2) {
+            let half_word = match *pair {
+                [low, high] => u16::from_le_bytes([low, high]),
+                [low] => u16::from_le_bytes([low, 0xff]),
+                _ => ERASED,
+            };
+            self.flash.program(page, at, half_word)?;
+            at = at.saturating_add(2);
+        }
+        for pair in crc.chunks_exact(2) {
+            if let [low, high] = *pair {
+                self.flash
+                    .program(page, at, u16::from_le_bytes([low, high]))?;
+            }
+            at = at.saturating_add(2);
+        }
+        Ok(())
+    }
+
+    fn records(&self, page: usize) -> Records<'_, F> {
+        Records {
+            flash: &self.flash,
+            page,
+            offset: HEADER,
+        }
+    }
+
+    fn scan_end(&self, page: usize) -> usize {
+        self.records(page)
+            .last()
+            .map_or(HEADER, |r| r.offset.saturating_add(r.size()))
+    }
+
+    /// The last record of `key` whose CRC checks out.
+    fn latest(&self, page: usize, key: u8) -> Option<Record> {
+        self.records(page)
+            .filter(|r| r.key == key && self.valid(page, r))
+            .last()
+    }
+
+    fn valid(&self, page: usize, record: &Record) -> bool {
+        let mut crc = Crc32::new();
+        crc.update(&[record.key, record.len as u8]);
+        for i in 0..record.len {
+            crc.update(&[self.data_byte(page, record, i)]);
+        }
+        let at = record
+            .offset
+            .saturating_add(record.size())
+            .saturating_sub(CRC);
+        let low = self.flash.read(page, at) as u32;
+        let high = self.flash.read(page, at.saturating_add(2)) as u32;
+        crc.finish() == high << 16 | low
+    }
+
+    fn data_byte(&self, page: usize, record: &Record, i: usize) -> u8 {
+        let at = record.data_offset().saturating_add(i & !1);
+        let [low, high] = self.flash.read(page, at).to_le_bytes();
+        if i & 1 == 0 {
+            low
+        } else {
+            high
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flash in RAM that loses power after `budget` erases and writes.
+    struct Ram {
+        pages: [[u16; PAGE / 2]; PAGES],
+        budget: usize,
+    }
+
+    impl Ram {
+        fn new() -> Self {
+            Ram {
+                pages: [[ERASED; PAGE / 2]; PAGES],
+                budget: usize::MAX,
+            }
+        }
+
+        fn spend(&mut self) -> Result<(), Error> {
+            self.budget = self.budget.checked_sub(1).ok_or(Error::Flash)?;
+            Ok(())
+        }
+    }
+
+    impl Flash for Ram {
+        fn read(&self, page: usize, offset: usize) -> u16 {
+            self.pages
+                .get(page)
+                .and_then(|p| p.get(offset >> 1))
+                .copied()
+                .unwrap_or(ERASED)
+        }
+
+        fn erase(&mut self, page: usize) -> Result<(), Error> {
+            self.spend()?;
+            self.pages.get_mut(page).ok_or(Error::Flash)?.fill(ERASED);
+            Ok(())
+        }
+
+        fn program(&mut self, page: usize, offset: usize, value: u16) -> Result<(), Error> {
+            self.spend()?;
+            let slot = self
+                .pages
+                .get_mut(page)
+                .and_then(|p| p.get_mut(offset >> 1))
+                .ok_or(Error::Flash)?;
+            // Bits only ever program from one to zero
+            *slot &= value;
+            Ok(())
+        }
+    }
+
+    fn read(storage: &Storage<Ram>, key: u8) -> Option<[u8; 2]> {
+        let mut buf = [0; 2];
+        storage.read(key, &mut buf).filter(|&len| len == 2)?;
+        Some(buf)
+    }
+
+    #[test]
+    fn values_survive_a_remount() -> Result<(), Error> {
+        let mut storage = Storage::mount(Ram::new())?;
+        assert_eq!(read(&storage, 1), None);
+        storage.write(1, &[1, 2])?;
+        storage.write(2, &[3, 4])?;
+        storage.write(1, &[5, 6])?;
+
+        let storage = Storage::mount(storage.flash)?;
+        assert_eq!(read(&storage, 1), Some([5, 6]));
+        assert_eq!(read(&storage, 2), Some([3, 4]));
+        Ok(())
+    }
+
+    #[test]
+    fn rewriting_the_same_value_leaves_the_flash_alone() -> Result<(), Error> {
+        let mut storage = Storage::mount(Ram::new())?;
+        storage.write(1, &[1, 2])?;
+        storage.flash.budget = 0;
+        storage.write(1, &[1, 2])?;
+        assert!(storage.write(1, &[1, 3]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn full_page_moves_the_latest_values() -> Result<(), Error> {
+        let mut storage = Storage::mount(Ram::new())?;
+        storage.write(2, &[9, 9])?;
+        for n in 0..=u8::MAX {
+            storage.write(1, &[n, 0])?;
+        }
+        assert_eq!(read(&storage, 1), Some([u8::MAX, 0]));
+        assert_eq!(read(&storage, 2), Some([9, 9]));
+
+        let storage = Storage::mount(storage.flash)?;
+        assert_eq!(read(&storage, 1), Some([u8::MAX, 0]));
+        assert_eq!(read(&storage, 2), Some([9, 9]));
+        Ok(())
+    }
+
+    #[test]
+    fn power_loss_during_a_move_keeps_every_value() -> Result<(), Error> {
+        // With key 2's record these fill the page, so the next write moves it
+        let records = (PAGE - HEADER) / record_size(2) - 1;
+        for cut in 0..64 {
+            let mut storage = Storage::mount(Ram::new())?;
+            storage.write(2, &[9, 9])?;
+            for n in 0..records as u8 {
+                storage.write(1, &[n, 0])?;
+            }
+            let old = [records as u8 - 1, 0];
+            assert_eq!(read(&storage, 1), Some(old));
+
+            // This one moves the page, and loses power `cut` steps in
+            storage.flash.budget = cut;
+            let written = storage.write(1, &[7, 7]).is_ok();
+            let mut flash = storage.flash;
+            flash.budget = usize::MAX;
+
+            let storage = Storage::mount(flash)?;
+            let value = read(&storage, 1);
+            if written {
+                assert_eq!(value, Some([7, 7]), "cut {}", cut);
+            } else {
+                assert!(value == Some(old) || value == Some([7, 7]), "cut {}", cut);
+            }
+            assert_eq!(read(&storage, 2), Some([9, 9]), "cut {}", cut);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_storage_is_formatted() -> Result<(), Error> {
+        let mut ram = Ram::new();
+        ram.pages[0][0] = 0x1234;
+        ram.pages[1][0] = 0x5678;
+        let mut storage = Storage::mount(ram)?;
+        assert_eq!(read(&storage, 1), None);
+        storage.write(1, &[1, 2])?;
+        assert_eq!(read(&storage, 1), Some([1, 2]));
+        Ok(())
+    }
+
+    #[test]
+    fn bad_keys_and_lengths_are_refused() -> Result<(), Error> {
+        let mut storage = Storage::mount(Ram::new())?;
+        assert!(matches!(storage.write(0xff, &[1]), Err(Error::BadKey)));
+        assert!(matches!(
+            storage.write(1, &[0; MAX_LEN + 1]),
+            Err(Error::TooLong)
+        ));
+        Ok(())
+    }
+}
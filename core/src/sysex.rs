@@ -0,0 +1,237 @@
+//! SysEx configuration protocol, for backing up and restoring settings and
+//! for editor apps.
+//!
+//! Every message is `F0 7D 4F 44 <command> <payload> F7`: the non-commercial
+//! manufacturer ID, then "OD". Payloads only carry 7-bit bytes, so values
+//! are split up:
+//!
+//! | Command | Payload                  | Meaning                         |
+//! |---------|--------------------------|---------------------------------|
+//! | `01`    | page                     | Read a parameter                |
+//! | `02`    | page, value              | Parameter value, read or write  |
+//! | `03`    |                          | Read every parameter            |
+//! | `10`    | bank                     | Read a wavetable                |
+//! | `11`    | bank, 512 nibbles        | Wavetable, read or write        |
+//! | `20`    |                          | Reboot into the bootloader      |
+//! | `30`    | point                    | Read an amplitude breakpoint    |
+//! | `31`    | point, value             | Breakpoint, read or write       |
+//! | `7F`    | command, status          | Reply to a write                |
+//!
+//! Values are `i32`s in five 7-bit groups, least significant first. Wavetable
+//! samples are sent as two nibbles each, high first.
+//!
+//! Runs inside the serial receive interrupt, on messages of any length.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+const HEADER: [u8; 4] = [0xf0, 0x7d, 0x4f, 0x44];
+const END: u8 = 0xf7;
+
+pub const GET_PARAM: u8 = 0x01;
+pub const PARAM: u8 = 0x02;
+pub const DUMP: u8 = 0x03;
+pub const GET_WAVE: u8 = 0x10;
+pub const WAVE: u8 = 0x11;
+pub const BOOTLOADER: u8 = 0x20;
+pub const GET_AMP: u8 = 0x30;
+pub const AMP: u8 = 0x31;
+pub const ACK: u8 = 0x7f;
+
+/// Reply status for [`ACK`].
+pub const OK: u8 = 0;
+pub const BAD_REQUEST: u8 = 1;
+pub const WRITE_FAILED: u8 = 2;
+
+/// Wavetable length, as in `wavetable::SAMPLES`.
+pub const SAMPLES: usize = 256;
+const VALUE_BYTES: usize = 5;
+
+/// Longest message body after the header: a wavetable write.
+const MAX_BODY: usize = 2 + 2 * SAMPLES;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Request {
+    GetParam {
+        page: u8,
+    },
+    SetParam {
+        page: u8,
+        value: i32,
+    },
+    Dump,
+    GetWave {
+        bank: u8,
+    },
+    /// The samples are read with [`Receiver::samples`].
+    SetWave {
+        bank: u8,
+    },
+    Bootloader,
+    GetAmp {
+        point: u8,
+    },
+    /// The code is clamped to the DAC range.
+    SetAmp {
+        point: u8,
+        code: u16,
+    },
+    /// A message with our header that doesn't decode.
+    Bad {
+        command: u8,
+    },
+}
+
+/// Collects one SysEx message at a time. Messages for other manufacturers
+/// and ones too long to be ours are skipped.
+pub struct Receiver {
+    body: [u8; MAX_BODY],
+    /// Bytes seen since `F0`, header included; `None` outside a message or
+    /// once it turned out to be somebody else's.
+    len: Option<usize>,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Receiver {
+            body: [0; MAX_BODY],
+            len: None,
+        }
+    }
+
+    /// Feeds one received byte, returning a request at the end of a message.
+    pub fn feed(&mut self, byte: u8) -> Option<Request> {
+        match byte {
+            // Real-time bytes may come in the middle of a message
+            0xf8..=0xff => None,
+            0xf0 => {
+                self.len = Some(1);
+                None
+            }
+            END => {
+                let len = self.len.take()?;
+                let body = self.body.get(..len.checked_sub(HEADER.len())?)?;
+                Some(decode(body))
+            }
+            // Any other status byte ends the message unfinished
+            0x80..=0xef | 0xf1..=0xf6 => {
+                self.len = None;
+                None
+            }
+            data => {
+                let len = self.len?;
+                match len.checked_sub(HEADER.len()) {
+                    None if HEADER.get(len) == Some(&data) => {
+                        self.len = Some(len.saturating_add(1));
+                    }
+                    None => self.len = None,
+                    Some(i) => match self.body.get_mut(i) {
+                        Some(slot) => {
+                            *slot = data;
+                            self.len = Some(len.saturating_add(1));
+                        }
+                        None => self.len = None,
+                    },
+                }
+                None
+            }
+        }
+    }
+
+    /// Samples of the last [`Request::SetWave`], until the next message
+    /// starts coming in.
+    pub fn samples(&self) -> [u8; SAMPLES] {
+        let mut samples = [0; SAMPLES];
+        let nibbles = self.body.get(2..).unwrap_or(&[]);
+        for (sample, pair) in samples.iter_mut().zip(nibbles.chunks_exact(2)) {
+            if let &[high, low] = pair {
+                *sample = (high & 0x0f) << 4 | (low & 0x0f);
+            }
+        }
+        samples
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn decode(body: &[u8]) -> Request {
+    let (&command, payload) = match body.split_first() {
+        Some(split) => split,
+        None => return Request::Bad { command: 0 },
+    };
+
+    match (command, payload) {
+        (GET_PARAM, &[page]) => Request::GetParam { page },
+        (PARAM, &[page, ref value @ ..]) if value.len() == VALUE_BYTES => Request::SetParam {
+            page,
+            value: decode_value(value),
+        },
+        (DUMP, &[]) => Request::Dump,
+        (GET_WAVE, &[bank]) => Request::GetWave { bank },
+        (WAVE, &[bank, ref nibbles @ ..]) if nibbles.len() == 2 * SAMPLES => {
+            Request::SetWave { bank }
+        }
+        (BOOTLOADER, &[]) => Request::Bootloader,
+        (GET_AMP, &[point]) => Request::GetAmp { point },
+        (AMP, &[point, ref value @ ..]) if value.len() == VALUE_BYTES => Request::SetAmp {
+            point,
+            code: decode_value(value).clamp(0, u16::MAX as i32) as u16,
+        },
+        _ => Request::Bad { command },
+    }
+}
+
+fn decode_value(bytes: &[u8]) -> i32 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u32, |acc, &b| acc << 7 | (b & 0x7f) as u32) as i32
+}
+
+/// Writes a reply through `out`, one byte at a time.
+pub fn param(page: u8, value: i32, out: impl FnMut(u8)) {
+    value_message(PARAM, page, value, out);
+}
+
+pub fn amp(point: u8, code: u16, out: impl FnMut(u8)) {
+    value_message(AMP, point, code as i32, out);
+}
+
+fn value_message(command: u8, index: u8, value: i32, mut out: impl FnMut(u8)) {
+    HEADER.iter().for_each(|&b| out(b));
+    out(command);
+    out(index & 0x7f);
+    let mut bits = value as u32;
+    for _ in 0..VALUE_BYTES {
+        out((bits & 0x7f) as u8);
+        bits >>= 7;
+    }
+    out(END);
+}
+
+pub fn wave(bank: u8, samples: impl Iterator<Item = u8>, mut out: impl FnMut(u8)) {
+    HEADER.iter().for_each(|&b| out(b));
+    out(WAVE);
+    out(bank & 0x7f);
+    for sample in samples {
+        out(sample >> 4);
+        out(sample & 0x0f);
+    }
+    out(END);
+}
+
+pub fn ack(command: u8, status: u8, mut out: impl FnMut(u8)) {
+    HEADER.iter().for_each(|&b| out(b));
+    out(ACK);
+    out(command & 0x7f);
+    out(status);
+    out(END);
+}
@@ -0,0 +1,74 @@
+//! Tap tempo from edges at the sync input.
+//!
+//! Runs inside the sync interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Intervals averaged into the tempo.
+pub const TAPS: usize = 4;
+
+/// Averages the intervals between the last few taps, in cycle counts.
+pub struct Tap {
+    last: Option<u32>,
+    intervals: [u32; TAPS],
+    len: usize,
+    next: usize,
+    timeout: u32,
+}
+
+impl Tap {
+    /// Gaps longer than `timeout` cycles start a new tempo instead of counting
+    /// as an interval.
+    pub const fn new(timeout: u32) -> Self {
+        Tap {
+            last: None,
+            intervals: [0; TAPS],
+            len: 0,
+            next: 0,
+            timeout,
+        }
+    }
+
+    /// Registers a tap at cycle count `now` and returns the averaged interval,
+    /// or `None` until there are two taps to measure.
+    pub fn tap(&mut self, now: u32) -> Option<u32> {
+        let last = self.last.replace(now)?;
+        let interval = now.wrapping_sub(last);
+        if interval > self.timeout {
+            self.len = 0;
+            return None;
+        }
+
+        // A tempo change of more than double or half drops the old taps
+        if let Some(avg) = self.average() {
+            if interval > avg.saturating_mul(2) || interval < avg / 2 {
+                self.len = 0;
+            }
+        }
+        if self.len == 0 {
+            self.next = 0;
+        }
+
+        if let Some(slot) = self.intervals.get_mut(self.next) {
+            *slot = interval;
+        }
+        self.next = self.next.wrapping_add(1) % TAPS;
+        self.len = self.len.saturating_add(1).min(TAPS);
+
+        self.average()
+    }
+
+    fn average(&self) -> Option<u32> {
+        let taps = self.intervals.get(..self.len)?;
+        let sum = taps
+            .iter()
+            .fold(0u64, |acc, &i| acc.saturating_add(i as u64));
+
+        sum.checked_div(self.len as u64).map(|avg| avg as u32)
+    }
+}
@@ -0,0 +1,62 @@
+//! Note-change detection on the pitch CV, for firing envelopes from
+//! sequencers that only send CV.
+//!
+//! Runs in the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+const SEMITONE_MV: f32 = 1000.0 / 12.0;
+/// Distance from the current note, in semitones, before the CV counts as a
+/// new one. Over half a semitone so noise at a boundary can't retrigger.
+const HYSTERESIS: f32 = 0.7;
+
+/// Reports a new note whenever the CV settles on a different semitone.
+pub struct NoteChange {
+    note: Option<i32>,
+}
+
+impl NoteChange {
+    pub const fn new() -> Self {
+        NoteChange { note: None }
+    }
+
+    /// Feeds the averaged pitch and returns `true` on a new note. The first
+    /// reading only sets the reference.
+    pub fn update(&mut self, pitch_mv: f32) -> bool {
+        let semitones = pitch_mv / SEMITONE_MV;
+        if semitones.is_nan() {
+            return false;
+        }
+
+        match self.note {
+            Some(note) if (-HYSTERESIS..=HYSTERESIS).contains(&(semitones - note as f32)) => false,
+            Some(_) => {
+                self.note = Some(round(semitones));
+                true
+            }
+            None => {
+                self.note = Some(round(semitones));
+                false
+            }
+        }
+    }
+}
+
+impl Default for NoteChange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn round(x: f32) -> i32 {
+    if x >= 0.0 {
+        (x + 0.5) as i32
+    } else {
+        (x - 0.5) as i32
+    }
+}
@@ -0,0 +1,69 @@
+//! Built-in vibrato: a sine LFO on the pitch, faded in after every new note.
+//!
+//! Updated from the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::wave;
+
+const PHASE_PER_HZ_US: f32 = 4_294_967_296.0 / 1_000_000.0;
+
+pub struct Vibrato {
+    phase: u32,
+    since_note_us: f32,
+}
+
+impl Vibrato {
+    pub const fn new() -> Self {
+        Vibrato {
+            phase: 0,
+            since_note_us: 0.0,
+        }
+    }
+
+    /// Advances the LFO by one update of `step_us` and returns the offset to
+    /// add to the pitch, `depth_mv` either way at most.
+    ///
+    /// A new note starts the LFO over silent: it waits `delay_ms`, then fades
+    /// in over as long again. Zero delay starts at full depth.
+    pub fn update(
+        &mut self,
+        note: bool,
+        rate_hz: f32,
+        depth_mv: f32,
+        delay_ms: f32,
+        step_us: f32,
+    ) -> f32 {
+        if note {
+            self.phase = 0;
+            self.since_note_us = 0.0;
+        }
+        let delay_us = delay_ms * 1000.0;
+        // Stops counting once faded in, long before an f32 runs out of steps
+        if self.since_note_us < delay_us * 2.0 {
+            self.since_note_us += step_us;
+        }
+        let fade = if delay_us > 0.0 {
+            ((self.since_note_us - delay_us) / delay_us).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let sine = (wave::sine(self.phase) as f32 - 127.5) / 127.5;
+        // Saturates for nonsense rates rather than wrapping
+        let step = (rate_hz * step_us * PHASE_PER_HZ_US) as u32;
+        self.phase = self.phase.wrapping_add(step);
+        sine * depth_mv * fade
+    }
+}
+
+impl Default for Vibrato {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,234 @@
+//! Per-voice oscillator and CV state, so a second DCO is another instance
+//! instead of another set of free-floating resources.
+//!
+//! Updated from the publish task.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+
+use eurorack_oxide_utils::voct::{MvOct, Voltage};
+
+use crate::config::AVG_BUF_SIZE;
+use crate::osc::Oscillator;
+use crate::pitch::{self, Glide};
+
+/// Ticks from one pitch update to the next: the CV is measured at half the
+/// tick rate and published once per averaging buffer.
+pub const PUBLISH_TICKS: u32 = AVG_BUF_SIZE as u32 * 2;
+
+/// Shared by reference: the publish task publishes, everything else reads.
+pub struct Voice {
+    pub osc: Oscillator,
+    tick_hz: u32,
+    cv_mv: AtomicI32,
+    pitch_mv: AtomicI32,
+    lfo: AtomicBool,
+    smooth: AtomicBool,
+    /// Fixed LFO rate as `f32` bits, zero when the CV sets it.
+    rate: AtomicU32,
+    /// Frequency set by the PLL as `f32` bits, zero when not locked.
+    locked: AtomicU32,
+}
+
+impl Voice {
+    pub const fn new(tick_hz: u32) -> Self {
+        Voice {
+            osc: Oscillator::new(),
+            tick_hz,
+            cv_mv: AtomicI32::new(0),
+            pitch_mv: AtomicI32::new(0),
+            lfo: AtomicBool::new(false),
+            smooth: AtomicBool::new(false),
+            rate: AtomicU32::new(0),
+            locked: AtomicU32::new(0),
+        }
+    }
+
+    /// Last CV reading at the input.
+    pub fn cv_mv(&self) -> i32 {
+        self.cv_mv.load(Ordering::Relaxed)
+    }
+
+    /// Last published pitch in mV/oct.
+    pub fn pitch_mv(&self) -> i32 {
+        self.pitch_mv.load(Ordering::Relaxed)
+    }
+
+    /// Switches between audio and LFO range from the next update on.
+    pub fn set_lfo(&self, lfo: bool) {
+        self.lfo.store(lfo, Ordering::Relaxed);
+    }
+
+    /// Slews the oscillator to every new pitch over [`PUBLISH_TICKS`],
+    /// arriving as the next update comes in, rather than stepping at its next
+    /// cycle, from the next update on.
+    pub fn set_smooth(&self, smooth: bool) {
+        self.smooth.store(smooth, Ordering::Relaxed);
+    }
+
+    /// Fixes the LFO rate regardless of the CV, for tap tempo. `None` hands
+    /// it back to the CV.
+    pub fn set_rate(&self, hz: Option<f32>) {
+        let bits = hz.map_or(0, |hz| {
+            hz.clamp(pitch::LFO_MIN_HZ, pitch::LFO_MAX_HZ).to_bits()
+        });
+        self.rate.store(bits, Ordering::Relaxed);
+    }
+
+    /// Locks the frequency to the sync input in any range, or `None` to
+    /// follow the CV.
+    pub fn set_lock(&self, hz: Option<f32>) {
+        self.locked
+            .store(hz.map_or(0, f32::to_bits), Ordering::Relaxed);
+    }
+
+    /// Frequency the oscillator runs at for a pitch, in the current range.
+    pub fn hz_at(&self, pitch_mv: f32) -> f32 {
+        let locked = self.locked.load(Ordering::Relaxed);
+        if locked != 0 {
+            return f32::from_bits(locked);
+        }
+
+        let hz = MvOct(pitch_mv).hz();
+        if !self.lfo.load(Ordering::Relaxed) {
+            return hz;
+        }
+
+        match self.rate.load(Ordering::Relaxed) {
+            0 => pitch::lfo_hz(hz),
+            bits => f32::from_bits(bits),
+        }
+    }
+
+    /// Frequency of the last published pitch.
+    pub fn hz(&self) -> f32 {
+        self.hz_at(self.pitch_mv() as f32)
+    }
+
+    /// Glides the oscillator towards the pitch for a CV reading from
+    /// [`pitch::cv_mv`], or `forced_mv` instead of the CV. While `hold` is set
+    /// the reading is kept but nothing is published.
+    ///
+    /// Returns the published pitch in mV/oct.
+    pub fn update(
+        &self,
+        cv_mv: f32,
+        glide: &mut Glide,
+        offset_mv: i32,
+        forced_mv: Option<i32>,
+        glide_step: f32,
+        hold: bool,
+    ) -> Option<f32> {
+        self.cv_mv.store(cv_mv as i32, Ordering::Relaxed);
+
+        let target = match forced_mv {
+            Some(mv) => mv as f32,
+            None => pitch::pitch_mv(cv_mv, offset_mv),
+        };
+        let pitch = glide.update(target, glide_step);
+        if hold {
+            return None;
+        }
+
+        self.pitch_mv.store(pitch as i32, Ordering::Relaxed);
+        let step = pitch::tuning_word(self.hz_at(pitch), self.tick_hz);
+        if self.smooth.load(Ordering::Relaxed) {
+            self.osc.slew_step(step, PUBLISH_TICKS);
+        } else {
+            self.osc.set_step(step);
+        }
+        Some(pitch)
+    }
+}
+
+/// CV averaging buffer, owned by the measurement task.
+pub struct Input {
+    buf: [u16; AVG_BUF_SIZE],
+}
+
+impl Input {
+    pub const fn new() -> Self {
+        Input {
+            buf: [0; AVG_BUF_SIZE],
+        }
+    }
+
+    /// Stores the sample for slot `index` of the averaging buffer.
+    pub fn store(&mut self, index: usize, sample: u16) {
+        if let Some(slot) = self.buf.get_mut(index) {
+            *slot = sample;
+        }
+    }
+
+    pub fn avg(&self) -> u32 {
+        pitch::avg(&self.buf)
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_HZ: u32 = 200_000;
+
+    #[test]
+    fn cvs_past_the_input_range_publish_the_range_ends() {
+        for (cv, end) in [
+            (0.0, 6000.0),
+            (f32::MAX, pitch::MIN_PITCH_MV),
+            (-f32::MAX, pitch::MAX_PITCH_MV),
+            (f32::NAN, pitch::MIN_PITCH_MV),
+        ] {
+            let voice = Voice::new(TICK_HZ);
+            let published = voice.update(cv, &mut Glide::new(), 0, None, 0.0, false);
+            assert_eq!(published, Some(end));
+            let step = voice.osc.step();
+            assert!(step > 0 && step < 1 << 31);
+        }
+    }
+
+    #[test]
+    fn held_update_keeps_the_running_pitch() {
+        let voice = Voice::new(TICK_HZ);
+        let mut glide = Glide::new();
+        voice.update(3000.0, &mut glide, 0, None, 0.0, false);
+        let step = voice.osc.step();
+        assert_eq!(voice.update(0.0, &mut glide, 0, None, 0.0, true), None);
+        assert_eq!(voice.osc.step(), step);
+        assert_eq!(voice.cv_mv(), 0);
+    }
+
+    #[test]
+    fn empty_input_averages_to_zero() {
+        assert_eq!(Input::new().avg(), 0);
+    }
+
+    #[test]
+    fn full_scale_input_averages_to_full_scale() {
+        let mut input = Input::new();
+        for index in 0..AVG_BUF_SIZE {
+            input.store(index, 4095);
+        }
+        assert_eq!(input.avg(), 4095);
+    }
+
+    #[test]
+    fn slot_past_the_buffer_is_ignored() {
+        let mut input = Input::new();
+        input.store(AVG_BUF_SIZE, 4095);
+        input.store(usize::MAX, 4095);
+        assert_eq!(input.avg(), 0);
+    }
+}
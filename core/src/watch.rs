@@ -0,0 +1,117 @@
+//! Live monitoring of oscillator state over RTT.
+
+use core::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+
+/// Shortest streaming interval, keeps the RTT buffer from overflowing.
+const MIN_INTERVAL_MS: u16 = 10;
+
+/// Watchable values, streamed under their symbolic names.
+#[derive(Clone, Copy)]
+pub enum Channel {
+    Cv,
+    Pitch,
+    FineTune,
+    Step,
+    Temperature,
+    SyncHz,
+    /// 1 while the supply is below the brown-out warning threshold.
+    SupplyLow,
+    /// ADC conversions that failed since boot.
+    AdcErrors,
+}
+
+pub const CHANNELS: [Channel; 8] = [
+    Channel::Cv,
+    Channel::Pitch,
+    Channel::FineTune,
+    Channel::Step,
+    Channel::Temperature,
+    Channel::SyncHz,
+    Channel::SupplyLow,
+    Channel::AdcErrors,
+];
+
+pub const ALL: u8 = u8::MAX >> (8 - CHANNELS.len());
+
+impl Channel {
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Cv => "cv_mv",
+            Channel::Pitch => "pitch_mv",
+            Channel::FineTune => "fine_tune",
+            Channel::Step => "step",
+            Channel::Temperature => "temp_c",
+            Channel::SyncHz => "sync_hz",
+            Channel::SupplyLow => "supply_low",
+            Channel::AdcErrors => "adc_errors",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        CHANNELS.iter().copied().find(|ch| ch.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    BadRate,
+    UnknownChannel,
+}
+
+/// Selection of streamed channels and the streaming rate, shared between the
+/// console and the streaming task.
+pub struct Watch {
+    mask: AtomicU8,
+    interval_ms: AtomicU16,
+}
+
+impl Watch {
+    pub const fn new(mask: u8, interval_ms: u16) -> Self {
+        Watch {
+            mask: AtomicU8::new(mask),
+            interval_ms: AtomicU16::new(interval_ms),
+        }
+    }
+
+    pub fn is_watched(&self, ch: Channel) -> bool {
+        self.mask.load(Ordering::Relaxed) & ch.bit() != 0
+    }
+
+    pub fn interval_ms(&self) -> u16 {
+        self.interval_ms.load(Ordering::Relaxed)
+    }
+
+    /// Applies the arguments of a `watch` console command: `off`, `all`,
+    /// `rate <ms>` or a list of channel names to stream.
+    pub fn command(&self, args: &str) -> Result<(), CommandError> {
+        let mut words = args.split_whitespace();
+
+        match words.next() {
+            None | Some("off") => self.mask.store(0, Ordering::Relaxed),
+            Some("all") => self.mask.store(ALL, Ordering::Relaxed),
+            Some("rate") => {
+                let ms = words
+                    .next()
+                    .and_then(|w| w.parse::<u16>().ok())
+                    .filter(|&ms| ms >= MIN_INTERVAL_MS)
+                    .ok_or(CommandError::BadRate)?;
+                self.interval_ms.store(ms, Ordering::Relaxed);
+            }
+            Some(first) => {
+                let mut mask = 0;
+                for name in core::iter::once(first).chain(words) {
+                    mask |= Channel::from_name(name)
+                        .ok_or(CommandError::UnknownChannel)?
+                        .bit();
+                }
+                self.mask.store(mask, Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+}
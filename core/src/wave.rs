@@ -0,0 +1,145 @@
+//! Band-limited waveforms rendered to the 8-bit DAC from the oscillator phase.
+//!
+//! Runs once per tick at the highest priority with no FPU, so everything is
+//! fixed point.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+/// Q16 fixed point one.
+const ONE: i32 = 1 << 16;
+
+const HALF_TURN: u32 = 1 << 31;
+const QUARTER_TURN: u32 = 1 << 30;
+
+/// First quarter of a sine at 256 points per cycle, sampled half a step in so
+/// the other quarters are exact mirrors. Peak is 127.5.
+const QUARTER_SINE: [u8; 64] = [
+    2, 5, 8, 11, 14, 17, 20, 23, 26, 29, 32, 36, 39, 41, 44, 47, 50, 53, 56, 59, 61, 64, 67, 70,
+    72, 75, 77, 80, 82, 84, 87, 89, 91, 93, 96, 98, 100, 101, 103, 105, 107, 109, 110, 112, 113,
+    115, 116, 117, 118, 120, 121, 122, 122, 123, 124, 125, 125, 126, 126, 127, 127, 127, 127, 127,
+];
+
+/// Sawtooth with polyBLEP corrections around the wrap, where `phase` is the
+/// oscillator phase and `step` its increment per sample.
+pub fn saw(phase: u32, step: u32) -> u8 {
+    to_dac(saw_q16(phase, step))
+}
+
+/// Square low for the first half of the cycle, built from two polyBLEP saws
+/// half a cycle apart so both edges are smoothed.
+pub fn square(phase: u32, step: u32) -> u8 {
+    let y = saw_q16(phase, step).wrapping_sub(saw_q16(phase.wrapping_add(HALF_TURN), step));
+    to_dac(y)
+}
+
+fn saw_q16(phase: u32, step: u32) -> i32 {
+    // Position in the cycle and per-sample increment, Q16
+    let t = (phase >> 16) as i32;
+    let dt = ((step >> 16) as i32).max(1);
+
+    let y = t.wrapping_mul(2).wrapping_sub(ONE);
+    y.wrapping_sub(blep(t, dt))
+}
+
+/// Sine starting at its minimum, in line with the square output going low at
+/// phase zero.
+pub fn sine(phase: u32) -> u8 {
+    let index = (phase.wrapping_sub(QUARTER_TURN) >> 24) as u8;
+    let i = (index & 63) as usize;
+    // Falling quarters read the table backwards
+    let i = if index & 64 != 0 {
+        63usize.saturating_sub(i)
+    } else {
+        i
+    };
+    let magnitude = QUARTER_SINE.get(i).copied().unwrap_or(0);
+
+    if index & 128 == 0 {
+        128u8.saturating_add(magnitude)
+    } else {
+        127u8.saturating_sub(magnitude)
+    }
+}
+
+/// Triangle rising from its minimum at phase zero.
+pub fn triangle(phase: u32) -> u8 {
+    let folded = if phase & HALF_TURN != 0 {
+        !phase
+    } else {
+        phase
+    };
+    ((folded << 1) >> 24) as u8
+}
+
+/// Morph positions per shape: sine at 0, triangle at 100, saw at 200 and square
+/// at [`MORPH_MAX`].
+pub const MORPH_STEP: i32 = 100;
+pub const MORPH_MAX: i32 = 3 * MORPH_STEP;
+
+/// Crossfade between the two shapes either side of `position`, sample by
+/// sample.
+pub fn morph(phase: u32, step: u32, position: i32) -> u8 {
+    let position = position.clamp(0, MORPH_MAX);
+    let shape = position.wrapping_div(MORPH_STEP);
+    // Weight of the upper shape, out of 256
+    let weight = position
+        .wrapping_rem(MORPH_STEP)
+        .wrapping_mul(256)
+        .wrapping_div(MORPH_STEP) as u32;
+
+    let a = morph_shape(shape, phase, step) as u32;
+    if weight == 0 {
+        return a as u8;
+    }
+    let b = morph_shape(shape.wrapping_add(1), phase, step) as u32;
+
+    (a.wrapping_mul(256u32.wrapping_sub(weight))
+        .wrapping_add(b.wrapping_mul(weight))
+        >> 8) as u8
+}
+
+fn morph_shape(shape: i32, phase: u32, step: u32) -> u8 {
+    match shape {
+        0 => sine(phase),
+        1 => triangle(phase),
+        2 => saw(phase, step),
+        _ => square(phase, step),
+    }
+}
+
+/// PolyBLEP residual for a falling unit step at the wrap, Q16.
+fn blep(t: i32, dt: i32) -> i32 {
+    if t < dt {
+        // Just after the wrap
+        let x = q16_div(t, dt);
+        x.wrapping_mul(2)
+            .wrapping_sub(q16_mul(x, x))
+            .wrapping_sub(ONE)
+    } else if t > ONE.wrapping_sub(dt) {
+        // Just before it
+        let x = q16_div(t.wrapping_sub(ONE), dt);
+        q16_mul(x, x)
+            .wrapping_add(x.wrapping_mul(2))
+            .wrapping_add(ONE)
+    } else {
+        0
+    }
+}
+
+fn q16_mul(a: i32, b: i32) -> i32 {
+    ((a as i64).wrapping_mul(b as i64) >> 16) as i32
+}
+
+fn q16_div(a: i32, b: i32) -> i32 {
+    ((a as i64) << 16).checked_div(b as i64).unwrap_or(0) as i32
+}
+
+/// Maps -1..1 in Q16 to the DAC range.
+fn to_dac(y: i32) -> u8 {
+    (y.wrapping_add(ONE) >> 9).clamp(0, 255) as u8
+}
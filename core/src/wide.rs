@@ -0,0 +1,55 @@
+//! 64-bit values shared between priorities, for counts that would wrap in
+//! 32 bits. The Cortex-M3 has no 64-bit atomics, so the value is two words
+//! behind a sequence count.
+//!
+//! Written from the tick interrupt.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A `u64` with one writer. Readers at the writer's priority or below see
+/// either the old or the new value, never half of each: a store always
+/// completes before they resume, and one that landed between their reads
+/// changes the sequence count, so they read again. A reader above the
+/// writer's priority could spin on a store it interrupted, so there must be
+/// none.
+pub struct Wide {
+    seq: AtomicU32,
+    high: AtomicU32,
+    low: AtomicU32,
+}
+
+impl Wide {
+    pub const fn new(value: u64) -> Self {
+        Wide {
+            seq: AtomicU32::new(0),
+            high: AtomicU32::new((value >> 32) as u32),
+            low: AtomicU32::new(value as u32),
+        }
+    }
+
+    pub fn load(&self) -> u64 {
+        loop {
+            let seq = self.seq.load(Ordering::SeqCst);
+            let high = self.high.load(Ordering::SeqCst);
+            let low = self.low.load(Ordering::SeqCst);
+            if self.seq.load(Ordering::SeqCst) == seq {
+                return (high as u64) << 32 | low as u64;
+            }
+        }
+    }
+
+    /// Only ever called from the one writer.
+    pub fn store(&self, value: u64) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        self.high.store((value >> 32) as u32, Ordering::SeqCst);
+        self.low.store(value as u32, Ordering::SeqCst);
+        self.seq.fetch_add(1, Ordering::SeqCst);
+    }
+}
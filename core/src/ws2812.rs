@@ -0,0 +1,45 @@
+//! WS2812 RGB LED encoding for a PWM channel fed by DMA.
+//!
+//! Every bit is one PWM period; the compare value sets whether the high time
+//! reads as a zero or a one.
+
+pub const BITS: usize = 24;
+/// One compare value per bit plus a trailing zero that holds the line low.
+pub const BUF_LEN: usize = BITS + 1;
+
+#[derive(Clone, Copy)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+pub const RED: Rgb = Rgb(255, 0, 0);
+
+/// Fills `buf` with the compare values for `color`, green first and most
+/// significant bit first as the LED expects.
+pub fn encode(color: Rgb, t0h: u16, t1h: u16, buf: &mut [u16; BUF_LEN]) {
+    let grb = (color.1 as u32) << 16 | (color.0 as u32) << 8 | color.2 as u32;
+
+    for (i, slot) in buf.iter_mut().take(BITS).enumerate() {
+        *slot = if grb & (1 << (BITS - 1 - i)) != 0 {
+            t1h
+        } else {
+            t0h
+        };
+    }
+    buf[BITS] = 0;
+}
+
+/// Fully saturated color for a hue in degrees at brightness `value`.
+pub fn hue(degrees: u16, value: u8) -> Rgb {
+    let h = degrees % 360;
+    let v = value as u32;
+    let rising = (v * (h % 60) as u32 / 60) as u8;
+    let falling = value - rising;
+
+    match h / 60 {
+        0 => Rgb(value, rising, 0),
+        1 => Rgb(falling, value, 0),
+        2 => Rgb(0, value, rising),
+        3 => Rgb(0, falling, value),
+        4 => Rgb(rising, 0, value),
+        _ => Rgb(value, 0, falling),
+    }
+}
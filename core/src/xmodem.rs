@@ -0,0 +1,325 @@
+//! XMODEM receiver for firmware updates over the console: 128-byte blocks
+//! and XMODEM-1K's 1024-byte ones, each checked with a CRC-16.
+//!
+//! Fed every byte the serial line brings during an update, whatever the
+//! sender or the noise on the line makes of them. The caller owns the line
+//! and the clock: it sends the replies the events call for, and
+//! [`Receiver::idle`] when the line has been quiet for a second.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use crate::crc::Crc16;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+/// Asks the sender for CRC-16 blocks instead of the old checksum ones.
+pub const CRC_MODE: u8 = b'C';
+
+/// Largest block, XMODEM-1K's.
+pub const BLOCK: usize = 1024;
+const SHORT_BLOCK: usize = 128;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Waiting for a block, the end or a cancel.
+    Header,
+    Number,
+    Complement,
+    Data,
+    CrcHigh,
+    CrcLow,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event<'a> {
+    /// Nothing to answer yet.
+    None,
+    /// The next block of the image, to be stored and acknowledged with
+    /// [`ACK`].
+    Block(&'a [u8]),
+    /// The previous block again, its ACK was lost: acknowledge and drop it.
+    Repeat,
+    /// A block that didn't check out, to be asked for again with [`NAK`].
+    Bad,
+    /// The whole image is in; acknowledge with [`ACK`].
+    End,
+    /// A block from somewhere else in the transfer, which can't be recovered
+    /// from: cancel with two [`CAN`]s.
+    Lost,
+    /// The sender gave up.
+    Cancelled,
+}
+
+pub struct Receiver {
+    state: State,
+    buf: [u8; BLOCK],
+    size: usize,
+    len: usize,
+    number: u8,
+    complement: u8,
+    crc: u16,
+    /// Number of the block expected next.
+    expected: u8,
+    started: bool,
+    /// The last header byte was a CAN.
+    cancelling: bool,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Receiver {
+            state: State::Header,
+            buf: [0; BLOCK],
+            size: 0,
+            len: 0,
+            number: 0,
+            complement: 0,
+            crc: 0,
+            expected: 1,
+            started: false,
+            cancelling: false,
+        }
+    }
+
+    /// Feeds one received byte.
+    pub fn feed(&mut self, byte: u8) -> Event<'_> {
+        match self.state {
+            State::Header => {
+                let cancelling = self.cancelling;
+                self.cancelling = false;
+                match byte {
+                    SOH | STX => {
+                        self.size = if byte == SOH { SHORT_BLOCK } else { BLOCK };
+                        self.len = 0;
+                        self.state = State::Number;
+                    }
+                    EOT => return Event::End,
+                    // Two in a row, so a stray one isn't taken for a cancel
+                    CAN if cancelling => return Event::Cancelled,
+                    CAN => self.cancelling = true,
+                    // Line noise or the tail of a block already given up on
+                    _ => {}
+                }
+            }
+            State::Number => {
+                self.number = byte;
+                self.state = State::Complement;
+            }
+            State::Complement => {
+                self.complement = byte;
+                self.state = State::Data;
+            }
+            State::Data => {
+                if let Some(slot) = self.buf.get_mut(self.len) {
+                    *slot = byte;
+                }
+                self.len = self.len.saturating_add(1);
+                if self.len >= self.size {
+                    self.state = State::CrcHigh;
+                }
+            }
+            State::CrcHigh => {
+                self.crc = (byte as u16) << 8;
+                self.state = State::CrcLow;
+            }
+            State::CrcLow => {
+                self.crc |= byte as u16;
+                self.state = State::Header;
+                return self.check();
+            }
+        }
+        Event::None
+    }
+
+    /// The line has been quiet for a while. Drops a block cut off halfway
+    /// and returns what to send: [`CRC_MODE`] until the first block, to
+    /// start the sender, [`NAK`] after it, to have the block sent again.
+    pub fn idle(&mut self) -> u8 {
+        self.state = State::Header;
+        self.cancelling = false;
+        if self.started {
+            NAK
+        } else {
+            CRC_MODE
+        }
+    }
+
+    /// Whether any block has come in yet.
+    pub fn started(&self) -> bool {
+        self.started
+    }
+
+    fn check(&mut self) -> Event<'_> {
+        let data = self.buf.get(..self.size).unwrap_or(&[]);
+        let mut crc = Crc16::new();
+        crc.update(data);
+        if self.number != !self.complement || crc.finish() != self.crc {
+            return Event::Bad;
+        }
+
+        if self.number == self.expected {
+            self.expected = self.expected.wrapping_add(1);
+            self.started = true;
+            Event::Block(data)
+        } else if self.number == self.expected.wrapping_sub(1) {
+            Event::Repeat
+        } else {
+            Event::Lost
+        }
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Got {
+        Block(usize, u8),
+        Repeat,
+        Bad,
+        End,
+        Lost,
+        Cancelled,
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Fault {
+        None,
+        Data,
+        Complement,
+        Crc,
+    }
+
+    /// Feeds block `number` of `size` bytes of `fill`, with a bit flipped
+    /// where `fault` says.
+    fn send(
+        receiver: &mut Receiver,
+        number: u8,
+        fill: u8,
+        size: usize,
+        fault: Fault,
+    ) -> Option<Got> {
+        let data = [fill; BLOCK];
+        let data = data.get(..size).unwrap_or(&[]);
+        let mut crc = Crc16::new();
+        crc.update(data);
+        let crc = crc.finish() ^ (fault == Fault::Crc) as u16;
+        let complement = !number ^ (fault == Fault::Complement) as u8;
+        let header = [if size == BLOCK { STX } else { SOH }, number, complement];
+        let data = data
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| b ^ (fault == Fault::Data && i == 7) as u8);
+        let bytes = header.iter().copied().chain(data).chain(crc.to_be_bytes());
+        feed(receiver, bytes)
+    }
+
+    /// Feeds `bytes` and returns the one event they make, if any.
+    fn feed(receiver: &mut Receiver, bytes: impl IntoIterator<Item = u8>) -> Option<Got> {
+        let mut got = None;
+        for byte in bytes {
+            let event = match receiver.feed(byte) {
+                Event::None => continue,
+                Event::Block(data) => Got::Block(data.len(), data.first().copied().unwrap_or(0)),
+                Event::Repeat => Got::Repeat,
+                Event::Bad => Got::Bad,
+                Event::End => Got::End,
+                Event::Lost => Got::Lost,
+                Event::Cancelled => Got::Cancelled,
+            };
+            assert_eq!(got, None);
+            got = Some(event);
+        }
+        got
+    }
+
+    #[test]
+    fn blocks_come_out_in_order() {
+        let mut receiver = Receiver::new();
+        assert_eq!(
+            send(&mut receiver, 1, 7, 128, Fault::None),
+            Some(Got::Block(128, 7))
+        );
+        assert_eq!(
+            send(&mut receiver, 2, 8, BLOCK, Fault::None),
+            Some(Got::Block(BLOCK, 8))
+        );
+        assert_eq!(feed(&mut receiver, [EOT]), Some(Got::End));
+    }
+
+    #[test]
+    fn corrupt_block_is_asked_for_again() {
+        let mut receiver = Receiver::new();
+        assert_eq!(send(&mut receiver, 1, 7, 128, Fault::Data), Some(Got::Bad));
+        assert_eq!(
+            send(&mut receiver, 1, 7, 128, Fault::None),
+            Some(Got::Block(128, 7))
+        );
+        // Block number that doesn't match its complement
+        assert_eq!(
+            send(&mut receiver, 2, 7, 128, Fault::Complement),
+            Some(Got::Bad)
+        );
+        assert_eq!(send(&mut receiver, 2, 7, 128, Fault::Crc), Some(Got::Bad));
+    }
+
+    #[test]
+    fn repeated_block_is_dropped() {
+        let mut receiver = Receiver::new();
+        send(&mut receiver, 1, 7, 128, Fault::None);
+        assert_eq!(
+            send(&mut receiver, 1, 7, 128, Fault::None),
+            Some(Got::Repeat)
+        );
+        assert_eq!(send(&mut receiver, 3, 7, 128, Fault::None), Some(Got::Lost));
+    }
+
+    #[test]
+    fn block_numbers_wrap() {
+        let mut receiver = Receiver::new();
+        for n in 1..=300u32 {
+            let number = n as u8;
+            assert_eq!(
+                send(&mut receiver, number, number, 128, Fault::None),
+                Some(Got::Block(128, number))
+            );
+        }
+    }
+
+    #[test]
+    fn cut_off_block_is_dropped_when_idle() {
+        let mut receiver = Receiver::new();
+        assert_eq!(receiver.idle(), CRC_MODE);
+        assert_eq!(feed(&mut receiver, [SOH, 1, !1, 7, 7, 7]), None);
+        assert_eq!(receiver.idle(), CRC_MODE);
+        assert_eq!(
+            send(&mut receiver, 1, 7, 128, Fault::None),
+            Some(Got::Block(128, 7))
+        );
+        assert!(receiver.started());
+        assert_eq!(receiver.idle(), NAK);
+    }
+
+    #[test]
+    fn cancel_takes_two() {
+        let mut receiver = Receiver::new();
+        assert_eq!(feed(&mut receiver, [CAN, 0x55, CAN]), None);
+        assert_eq!(feed(&mut receiver, [CAN]), Some(Got::Cancelled));
+    }
+}
@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::cli;
+
+fuzz_target!(|data: &[u8]| {
+    let mut editor = cli::Editor::new();
+    for &b in data {
+        let mut echoed = 0;
+        if let Some(line) = editor.feed(b, |bytes| echoed += bytes.len()) {
+            let line = line.as_str();
+            assert!(line.len() <= cli::LINE);
+            assert!(line.bytes().all(|b| (0x20..=0x7e).contains(&b)));
+            cli::Command::parse(line);
+        }
+        assert!(echoed <= cli::LINE * 3 + cli::PROMPT.len() + 4);
+    }
+});
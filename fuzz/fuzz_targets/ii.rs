@@ -0,0 +1,24 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::ii;
+
+// Each input byte is a bus event: the top two bits pick address, data, read
+// or stop, and the rest is the data byte
+fuzz_target!(|data: &[u8]| {
+    let mut responder = ii::Responder::new();
+    for &b in data {
+        match b >> 6 {
+            0 => responder.start(),
+            1 => responder.receive(b & 0x3f),
+            2 => {
+                responder.transmit(|_| i16::from(b));
+            }
+            _ => {
+                if let Some((reg, _)) = responder.stop() {
+                    assert!(reg.writable());
+                }
+            }
+        }
+    }
+});
@@ -0,0 +1,48 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::{arp, midi};
+
+fuzz_target!(|data: &[u8]| {
+    let (&first, bytes) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let channel = if first & 0x10 != 0 {
+        Some(first & 0x0f)
+    } else {
+        None
+    };
+
+    let mut parser = midi::Parser::new();
+    let mut notes = midi::NoteStack::new();
+    let mut controllers = midi::Controllers::new();
+    let mut arp = arp::Arp::new();
+    for &b in bytes {
+        match parser.feed(b, channel) {
+            Some(midi::Message::NoteOn { note, velocity }) => {
+                assert!(note < 0x80 && (1..0x80).contains(&velocity));
+                assert!(midi::velocity_gain(velocity, first as i32 % 4) <= 4095);
+                notes.press(note);
+                assert_eq!(notes.current(), Some(note));
+            }
+            Some(midi::Message::NoteOff { note }) => notes.release(note),
+            Some(midi::Message::Clock) => {
+                let intervals = &arp::INTERVALS[first as usize % arp::INTERVALS.len()];
+                match arp.step(first as i32 % 5, &notes, intervals) {
+                    Some(arp::Step::Note(note)) => assert!(notes.held().contains(&note)),
+                    Some(arp::Step::Offset(mv)) => {
+                        assert!(notes.held().is_empty() && (0.0..=1000.0).contains(&mv))
+                    }
+                    None => assert_eq!(first % 5, 0),
+                }
+            }
+            Some(midi::Message::ControlChange { control, value }) => {
+                if let Some((cc, value)) = controllers.change(control, value) {
+                    assert!(cc < 32 && value < 0x4000);
+                }
+            }
+            _ => {}
+        }
+    }
+});
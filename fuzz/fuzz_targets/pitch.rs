@@ -0,0 +1,24 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::pitch;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 7 {
+        return;
+    }
+
+    let vref = u16::from_le_bytes([data[0], data[1]]);
+    let offset = i32::from_le_bytes([data[2], data[3], data[4], data[5]]);
+    let tick_hz = data[6] as u32 * 1000;
+    let samples: Vec<u16> = data[7..]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+
+    let cv = pitch::cv_mv(pitch::avg(&samples), vref);
+    let mv = pitch::pitch_mv(cv, offset);
+    assert!(mv >= pitch::MIN_PITCH_MV && mv <= pitch::MAX_PITCH_MV);
+
+    pitch::tuning_word(mv, tick_hz);
+});
@@ -0,0 +1,46 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::config::TIM3_FREQ_HZ;
+use oxide_dco_core::{pitch, voice::Voice};
+
+const TURN: f64 = 4_294_967_296.0;
+
+// Invariants of the CV to tuning word chain over the whole input range: two
+// ADC readings, a VREFINT reading and a fine tune offset.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 8 {
+        return;
+    }
+
+    let a = u16::from_le_bytes([data[0], data[1]]) & 0xfff;
+    let b = u16::from_le_bytes([data[2], data[3]]) & 0xfff;
+    let (low, high) = (a.min(b), a.max(b));
+    // Anything near the nominal 1489, the supply stays within a few percent
+    let vref = 1400 + u16::from_le_bytes([data[4], data[5]]) % 200;
+    let offset = i16::from_le_bytes([data[6], data[7]]) as i32 % 1001;
+
+    // The input stage inverts: a higher reading never raises the pitch
+    let top = pitch::pitch_mv(pitch::cv_mv(low as u32, vref), offset);
+    let bottom = pitch::pitch_mv(pitch::cv_mv(high as u32, vref), offset);
+    assert!(bottom <= top);
+
+    // A higher pitch never gives a smaller tuning word
+    let voice = Voice::new(TIM3_FREQ_HZ);
+    let word_bottom = pitch::tuning_word(voice.hz_at(bottom), TIM3_FREQ_HZ);
+    let word_top = pitch::tuning_word(voice.hz_at(top), TIM3_FREQ_HZ);
+    assert!(word_bottom <= word_top);
+
+    // Nothing in the range saturates the accumulator or stops it, and the
+    // frequency the word gives is within a step and the f32 rounding of the
+    // one asked for
+    for &mv in [bottom, top].iter() {
+        let hz = voice.hz_at(mv);
+        let word = pitch::tuning_word(hz, TIM3_FREQ_HZ);
+        assert!(word > 0 && word < u32::MAX / 2);
+
+        let actual = word as f64 * TIM3_FREQ_HZ as f64 / TURN;
+        let error = (actual - hz as f64).abs();
+        assert!(error <= TIM3_FREQ_HZ as f64 / TURN + hz as f64 * 1e-6);
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::encoder;
+
+fuzz_target!(|data: &[u8]| {
+    let mut q = encoder::Quadrature::new(30);
+    let mut now: u32 = 0;
+
+    // Each byte is one edge: phase levels in the low bits, time since the
+    // previous edge in the rest.
+    for &b in data {
+        now = now.wrapping_add((b as u32 >> 2) * 1000);
+        let detents = q.update(b & 0b10 != 0, b & 0b01 != 0, now);
+        assert!(detents.abs() <= 1);
+    }
+});
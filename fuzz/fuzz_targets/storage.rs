@@ -0,0 +1,68 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::storage;
+use storage::{Error, Flash, Storage, ERASED, PAGE, PAGES};
+
+/// Flash in RAM that enforces the one-to-zero programming rule.
+struct Ram([[u16; PAGE / 2]; PAGES]);
+
+impl Flash for Ram {
+    fn read(&self, page: usize, offset: usize) -> u16 {
+        self.0
+            .get(page)
+            .and_then(|p| p.get(offset / 2))
+            .copied()
+            .unwrap_or(ERASED)
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), Error> {
+        let p = self.0.get_mut(page).ok_or(Error::Flash)?;
+        *p = [ERASED; PAGE / 2];
+        Ok(())
+    }
+
+    fn program(&mut self, page: usize, offset: usize, value: u16) -> Result<(), Error> {
+        let slot = self
+            .0
+            .get_mut(page)
+            .and_then(|p| p.get_mut(offset / 2))
+            .ok_or(Error::Flash)?;
+        if *slot != ERASED && value != 0 {
+            return Err(Error::Flash);
+        }
+        *slot = value;
+        Ok(())
+    }
+}
+
+// The first 2K of input is the flash contents found at boot, the rest a
+// list of writes: key, length, data
+fuzz_target!(|data: &[u8]| {
+    let mut ram = Ram([[ERASED; PAGE / 2]; PAGES]);
+    let (image, mut ops) = data.split_at(data.len().min(PAGES * PAGE));
+    for (i, pair) in image.chunks_exact(2).enumerate() {
+        if let Some(slot) = ram
+            .0
+            .get_mut(i / (PAGE / 2))
+            .and_then(|p| p.get_mut(i % (PAGE / 2)))
+        {
+            *slot = u16::from_le_bytes([pair[0], pair[1]]);
+        }
+    }
+
+    let mut storage = match Storage::mount(ram) {
+        Ok(storage) => storage,
+        Err(_) => return,
+    };
+    while let [key, len, rest @ ..] = ops {
+        let len = (*len as usize).min(rest.len());
+        let (value, next) = rest.split_at(len);
+        ops = next;
+        if storage.write(*key, value).is_ok() {
+            let mut buf = [0; storage::MAX_LEN];
+            assert_eq!(storage.read(*key, &mut buf), Some(len));
+            assert_eq!(&buf[..len], value);
+        }
+    }
+});
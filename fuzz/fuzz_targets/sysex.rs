@@ -0,0 +1,34 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::{outbox, sysex};
+
+fuzz_target!(|data: &[u8]| {
+    let mut rx = sysex::Receiver::new();
+    let mut out = outbox::Outbox::new();
+    for &b in data {
+        match rx.feed(b) {
+            Some(sysex::Request::SetWave { .. }) => {
+                rx.samples();
+            }
+            Some(sysex::Request::SetParam { page, value }) => {
+                // Replies round-trip through the receiver
+                let mut echo = sysex::Receiver::new();
+                let mut decoded = None;
+                sysex::param(page, value, |b| decoded = decoded.or(echo.feed(b)));
+                assert!(decoded == Some(sysex::Request::SetParam { page, value }));
+            }
+            Some(sysex::Request::SetAmp { point, code }) => {
+                let mut echo = sysex::Receiver::new();
+                let mut decoded = None;
+                sysex::amp(point, code, |b| decoded = decoded.or(echo.feed(b)));
+                assert!(decoded == Some(sysex::Request::SetAmp { point, code }));
+            }
+            _ => {}
+        }
+    }
+
+    sysex::ack(0x7f, sysex::OK, |b| out.push(b));
+    while out.pop().is_some() {}
+    assert!(out.is_empty());
+});
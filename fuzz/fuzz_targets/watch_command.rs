@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use oxide_dco_core::watch;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = core::str::from_utf8(data) {
+        let w = watch::Watch::new(0, 100);
+        let _ = w.command(line);
+        assert!(w.interval_ms() >= 10);
+    }
+});
@@ -1,6 +1,21 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
-  RAM : ORIGIN = 0x20000000, LENGTH = 20K
-}
\ No newline at end of file
+  FLASH : ORIGIN = 0x08000000, LENGTH = 58K
+  /* Settings storage, two 1K pages, left out of the image */
+  STORAGE : ORIGIN = 0x0800E800, LENGTH = 2K
+  /* User wavetables, one 1K page per bank */
+  WAVETABLES : ORIGIN = 0x0800F000, LENGTH = 4K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K - 64
+  /* Crash record, above the stack so nothing clears it at reset */
+  CRASH : ORIGIN = 0x20004FC0, LENGTH = 64
+}
+
+SECTIONS
+{
+  .wavetables : ALIGN(1024)
+  {
+    KEEP(*(.wavetables .wavetables.*));
+  } > WAVETABLES
+}
+INSERT AFTER .rodata;
@@ -0,0 +1,302 @@
+//! Host-side simulator: drives the oscillator core with a synthetic pitch CV
+//! and sync input, the way the tick and measurement tasks do, and writes what
+//! the DAC and the pulse output would produce to a WAV file. With `--replay`
+//! it plays back a recording from the firmware's `recorder` feature instead.
+//!
+//! ```text
+//! cd sim
+//! cargo run --target x86_64-unknown-linux-gnu -- --pitch 4000 --sync 220 out.wav
+//! ```
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use oxide_dco_core::config::{AVG_BUF_SIZE, CV_GAIN, CV_OFFSET_MV, TIM3_FREQ_HZ, VREF_SCALE};
+use oxide_dco_core::osc::Edge;
+use oxide_dco_core::pitch::{self, Glide};
+use oxide_dco_core::voice::{Input, Voice};
+use oxide_dco_core::wave;
+
+mod replay;
+
+use replay::Replayer;
+
+const USAGE: &str = "usage: oxide-dco-sim [options] <out.wav>
+
+  --seconds <s>     length of the render, default 1
+  --pitch <mV>      pitch CV in mV/oct, default 4000
+  --sweep <mV>      move the CV linearly to this pitch over the render
+  --glide <ms>      glide time per octave, default 0
+  --sync <Hz>       hard sync from a second oscillator at this frequency
+  --soft            soft sync instead of hard sync
+  --steps           step the pitch at every update, as with `smooth` off
+  --wave <shape>    DAC shape: saw, square, sine, triangle or morph=<0-300>
+  --replay <log>    play back the events in an RTT log from the `recorder`
+                    feature instead of the synthetic CV and sync; --seconds
+                    is then how long to run on after the last one
+  --clock <MHz>     system clock the recording ran at, default 28
+
+The WAV runs at the tick rate, left is the DAC and right the pulse output.";
+
+/// VREFINT reading at a 3.3 V supply, 1.2 V on a 12-bit ADC.
+const VREF: u16 = 1489;
+const MV_IN_OCT: f64 = 1000.0;
+
+#[derive(Clone, Copy)]
+enum Shape {
+    Saw,
+    Square,
+    Sine,
+    Triangle,
+    Morph(i32),
+}
+
+struct Options {
+    seconds: f64,
+    pitch_mv: f64,
+    sweep_mv: Option<f64>,
+    glide_ms: f64,
+    sync_hz: Option<f64>,
+    soft: bool,
+    steps: bool,
+    shape: Shape,
+    replay: Option<String>,
+    clock_mhz: f64,
+    path: String,
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn number(args: &mut impl Iterator<Item = String>, flag: &str) -> f64 {
+    args.next()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| fail(&format!("{} needs a number", flag)))
+}
+
+fn parse_shape(text: &str) -> Option<Shape> {
+    match text {
+        "saw" => Some(Shape::Saw),
+        "square" => Some(Shape::Square),
+        "sine" => Some(Shape::Sine),
+        "triangle" => Some(Shape::Triangle),
+        _ => {
+            let position = text.strip_prefix("morph=")?.parse().ok()?;
+            Some(Shape::Morph(position))
+        }
+    }
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options {
+        seconds: 1.0,
+        pitch_mv: 4000.0,
+        sweep_mv: None,
+        glide_ms: 0.0,
+        sync_hz: None,
+        soft: false,
+        steps: false,
+        shape: Shape::Saw,
+        replay: None,
+        clock_mhz: 28.0,
+        path: String::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seconds" => options.seconds = number(&mut args, "--seconds"),
+            "--pitch" => options.pitch_mv = number(&mut args, "--pitch"),
+            "--sweep" => options.sweep_mv = Some(number(&mut args, "--sweep")),
+            "--glide" => options.glide_ms = number(&mut args, "--glide"),
+            "--sync" => options.sync_hz = Some(number(&mut args, "--sync")),
+            "--soft" => options.soft = true,
+            "--steps" => options.steps = true,
+            "--wave" => {
+                options.shape = args
+                    .next()
+                    .as_deref()
+                    .and_then(parse_shape)
+                    .unwrap_or_else(|| fail("unknown --wave shape"))
+            }
+            "--replay" => {
+                options.replay = Some(args.next().unwrap_or_else(|| fail("--replay needs a log")))
+            }
+            "--clock" => options.clock_mhz = number(&mut args, "--clock"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => fail(&format!("unknown option {}", arg)),
+            _ if options.path.is_empty() => options.path = arg,
+            _ => fail("more than one output file"),
+        }
+    }
+    if options.path.is_empty() {
+        fail("no output file");
+    }
+    options
+}
+
+/// ADC reading the input stage produces for a pitch, before clipping.
+fn adc_reading(pitch_mv: f64) -> f64 {
+    let cv_mv = (CV_OFFSET_MV as f64 - pitch_mv) / CV_GAIN as f64;
+    (cv_mv * VREF as f64 / VREF_SCALE as f64).round()
+}
+
+/// The reading rounded and clipped like the real converter.
+fn adc_sample(pitch_mv: f64) -> u16 {
+    adc_reading(pitch_mv).clamp(0.0, 4095.0) as u16
+}
+
+fn dac(shape: Shape, phase: u32, step: u32) -> u8 {
+    match shape {
+        Shape::Saw => wave::saw(phase, step),
+        Shape::Square => wave::square(phase, step),
+        Shape::Sine => wave::sine(phase),
+        Shape::Triangle => wave::triangle(phase),
+        Shape::Morph(position) => wave::morph(phase, step, position),
+    }
+}
+
+/// 16-bit stereo PCM header for `frames` frames.
+fn write_header(out: &mut impl Write, frames: u32) -> io::Result<()> {
+    let rate = TIM3_FREQ_HZ;
+    let data = frames * 4;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, two channels
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&rate.to_le_bytes())?;
+    out.write_all(&(rate * 4).to_le_bytes())?;
+    out.write_all(&4u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data.to_le_bytes())
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let voice = Voice::new(TIM3_FREQ_HZ);
+    voice.set_smooth(!options.steps);
+    let mut input = Input::new();
+    let mut glide = Glide::new();
+    let mut replayer = match &options.replay {
+        Some(path) => Some(Replayer::new(replay::read(path, options.clock_mhz * 1e6)?)),
+        None => None,
+    };
+    let tail = (options.seconds * TIM3_FREQ_HZ as f64) as u64;
+    let frames = match &replayer {
+        Some(replayer) => replayer.end() + tail,
+        None => tail,
+    } as u32;
+    // Pitch is published once per averaging buffer of measurements, which
+    // run at half the tick rate
+    let publish_s = AVG_BUF_SIZE as f64 * 2.0 / TIM3_FREQ_HZ as f64;
+    let glide_step = if options.glide_ms > 0.0 {
+        (MV_IN_OCT * publish_s * 1000.0 / options.glide_ms) as f32
+    } else {
+        0.0
+    };
+    let sync_step = options
+        .sync_hz
+        .map(|hz| (hz / TIM3_FREQ_HZ as f64 * 4_294_967_296.0) as u64);
+
+    for mv in Some(options.pitch_mv).into_iter().chain(options.sweep_mv) {
+        if !(0.0..=4095.0).contains(&adc_reading(mv)) {
+            eprintln!("{} mV is outside the CV input's range, the ADC clips", mv);
+        }
+    }
+
+    let mut out = BufWriter::new(File::create(&options.path)?);
+    write_header(&mut out, frames)?;
+
+    let mut counter = 0;
+    let mut sync_phase = 0u64;
+    let mut high = false;
+    let mut wraps = 0u32;
+    let mut first_wrap = None;
+    let mut last_wrap = 0;
+    for frame in 0..frames {
+        let t = frame as f64 / frames.max(1) as f64;
+        let target = match options.sweep_mv {
+            Some(end) => options.pitch_mv + (end - options.pitch_mv) * t,
+            None => options.pitch_mv,
+        };
+
+        if let Some(replayer) = &mut replayer {
+            replayer.play(
+                frame as u64,
+                &voice,
+                &mut glide,
+                glide_step,
+                options.soft,
+                VREF,
+            );
+        } else if let Some(step) = sync_step {
+            // The sync input's rising edge, handled before the next tick like
+            // the hard sync interrupt
+            sync_phase += step;
+            if sync_phase >> 32 != 0 {
+                sync_phase &= 0xffff_ffff;
+                if options.soft {
+                    voice.osc.soft_reset_to(0);
+                } else {
+                    voice.osc.reset();
+                }
+            }
+        }
+
+        if replayer.is_none() && frame % 2 == 0 {
+            input.store(counter % AVG_BUF_SIZE, adc_sample(target));
+            counter += 1;
+            if counter % AVG_BUF_SIZE == 0 {
+                let cv = pitch::cv_mv(input.avg(), VREF);
+                voice.update(cv, &mut glide, 0, None, glide_step, false);
+            }
+        }
+
+        let osc = &voice.osc;
+        match osc.tick() {
+            Edge::None => {}
+            Edge::Reset | Edge::Toggle => high = osc.is_high(),
+        }
+        if osc.at_zero() {
+            wraps += 1;
+            first_wrap.get_or_insert(frame);
+            last_wrap = frame;
+        }
+
+        let sample = dac(options.shape, osc.phase(), osc.step());
+        let left = (sample as i16 - 128) * 256;
+        let right: i16 = if high { i16::MAX / 2 } else { i16::MIN / 2 };
+        out.write_all(&left.to_le_bytes())?;
+        out.write_all(&right.to_le_bytes())?;
+    }
+    out.flush()?;
+
+    // Frequency from the cycles between the first and the last wrap, against
+    // the one the CV asks for
+    if let (Some(first), true) = (first_wrap, wraps > 1) {
+        let measured = (wraps - 1) as f64 * TIM3_FREQ_HZ as f64 / (last_wrap - first) as f64;
+        eprintln!("{} cycles, {:.3} Hz average", wraps - 1, measured);
+        if options.sweep_mv.is_none() && options.sync_hz.is_none() && options.replay.is_none() {
+            let expected = voice.hz_at(options.pitch_mv as f32) as f64;
+            let cents = 1200.0 * (measured / expected).log2();
+            eprintln!("expected {:.3} Hz, {:+.2} cents", expected, cents);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = parse(env::args().skip(1));
+    if let Err(e) = run(&options) {
+        eprintln!("{}: {}", options.path, e);
+        process::exit(1);
+    }
+}
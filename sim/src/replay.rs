@@ -0,0 +1,132 @@
+//! Recordings from the firmware's `recorder` feature, read back from the
+//! `replay <time> <kind> <value>` lines it logs over RTT.
+
+use std::fs;
+use std::io;
+
+use oxide_dco_core::config::{FINE_TUNE_STEP, TIM3_FREQ_HZ};
+use oxide_dco_core::midi::{Message, NoteStack, Parser};
+use oxide_dco_core::note;
+use oxide_dco_core::pitch::{self, Glide};
+use oxide_dco_core::recorder::Kind;
+use oxide_dco_core::voice::Voice;
+
+pub struct Event {
+    /// Ticks since the first event.
+    pub tick: u64,
+    pub kind: Kind,
+    pub value: i32,
+}
+
+/// Every event in the log at `path`, in timestamp order, with the cycle
+/// counter at `clock_hz` turned into ticks. Lines that aren't replay events
+/// are skipped, so the whole RTT log can be passed in as it is.
+pub fn read(path: &str, clock_hz: f64) -> io::Result<Vec<Event>> {
+    let text = fs::read_to_string(path)?;
+    let mut events = Vec::new();
+    // The cycle counter wraps every couple of minutes. Events are drained
+    // about in the order they happened, so a time far below the last one is
+    // the next lap, and one just below it an event recorded out of order
+    let mut laps = 0u64;
+    let mut last = 0u32;
+    for line in text.lines() {
+        let fields = match line.find("replay ") {
+            Some(at) => &line[at + "replay ".len()..],
+            None => continue,
+        };
+        if let Some(count) = fields.strip_prefix("dropped ") {
+            eprintln!("{} events dropped here, the replay has a gap", count.trim());
+            continue;
+        }
+        let mut fields = fields.split_whitespace().map(str::parse::<i64>);
+        let (time, kind, value) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(Ok(time)), Some(Ok(kind)), Some(Ok(value))) => (time as u32, kind, value),
+            _ => continue,
+        };
+        let kind = match Kind::from_u8(kind as u8) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        if time < last && last - time > u32::MAX / 2 {
+            laps += 1;
+        }
+        if time >= last || last - time > u32::MAX / 2 {
+            last = time;
+        }
+        events.push(((laps << 32) | time as u64, kind, value as i32));
+    }
+
+    let start = events.iter().map(|e| e.0).min().unwrap_or(0);
+    let mut events: Vec<Event> = events
+        .into_iter()
+        .map(|(cycles, kind, value)| Event {
+            tick: ((cycles - start) as f64 * TIM3_FREQ_HZ as f64 / clock_hz) as u64,
+            kind,
+            value,
+        })
+        .collect();
+    // Stable, so events on one tick keep the order they were drained in
+    events.sort_by_key(|e| e.tick);
+    Ok(events)
+}
+
+/// Feeds the events back through the core on the ticks they happened at,
+/// the way the firmware's tasks handle them: an ADC average publishes the
+/// pitch, a sync edge resets the oscillator, encoder steps move the fine
+/// tune and a held MIDI note overrides the CV, as with `src` set to `midi`.
+pub struct Replayer {
+    events: Vec<Event>,
+    next: usize,
+    parser: Parser,
+    notes: NoteStack,
+    fine_mv: i32,
+}
+
+impl Replayer {
+    pub fn new(events: Vec<Event>) -> Self {
+        Replayer {
+            events,
+            next: 0,
+            parser: Parser::new(),
+            notes: NoteStack::new(),
+            fine_mv: 0,
+        }
+    }
+
+    /// Tick of the last event.
+    pub fn end(&self) -> u64 {
+        self.events.last().map_or(0, |e| e.tick)
+    }
+
+    /// Applies every event due by `tick`, before the oscillator ticks.
+    pub fn play(
+        &mut self,
+        tick: u64,
+        voice: &Voice,
+        glide: &mut Glide,
+        glide_step: f32,
+        soft: bool,
+        vref: u16,
+    ) {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.tick <= tick) {
+            self.next += 1;
+            match event.kind {
+                Kind::AdcAverage => {
+                    let cv = pitch::cv_mv(event.value as u32, vref);
+                    let forced = self.notes.current().map(|n| {
+                        note::mv(n as i32).clamp(pitch::MIN_PITCH_MV, pitch::MAX_PITCH_MV) as i32
+                    });
+                    voice.update(cv, glide, self.fine_mv, forced, glide_step, false);
+                }
+                Kind::SyncEdge if soft => voice.osc.soft_reset_to(0),
+                Kind::SyncEdge => voice.osc.reset(),
+                Kind::EncoderSteps => self.fine_mv += event.value * FINE_TUNE_STEP,
+                Kind::MidiByte => match self.parser.feed(event.value as u8, None) {
+                    Some(Message::NoteOn { note, .. }) => self.notes.press(note),
+                    Some(Message::NoteOff { note }) => self.notes.release(note),
+                    _ => {}
+                },
+            }
+        }
+    }
+}
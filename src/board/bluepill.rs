@@ -0,0 +1,204 @@
+//! Blue Pill: the STM32F103C8 board the module was designed around, running
+//! from the HSI, or from its 8 MHz crystal with `clock-72mhz`. The `dual`,
+//! `pwm-cv`, `env-cv`, `transpose-cv` and `mod-cv` pins need the 64-pin
+//! STM32F103RB instead, on the same layout.
+
+use stm32f1xx_hal::gpio::{
+    gpiob, gpioc, Alternate, Analog, Floating, Input, OpenDrain, Output, PullUp, PushPull,
+};
+
+// Clock tree, from the HSI by default: its 4 MHz PLL input times 7, and the
+// ADC at pclk2 / 2, its limit
+#[cfg(not(feature = "clock-72mhz"))]
+pub const HSE_HZ: Option<u32> = None;
+#[cfg(not(feature = "clock-72mhz"))]
+pub const SYSCLK_HZ: u32 = 28_000_000;
+#[cfg(not(feature = "clock-72mhz"))]
+pub const PCLK1_HZ: u32 = 14_000_000;
+#[cfg(not(feature = "clock-72mhz"))]
+pub const ADCCLK_HZ: u32 = 14_000_000;
+
+// Full speed from the crystal: APB1 and the ADC at their limits or below
+#[cfg(feature = "clock-72mhz")]
+pub const HSE_HZ: Option<u32> = Some(8_000_000);
+#[cfg(feature = "clock-72mhz")]
+pub const SYSCLK_HZ: u32 = 72_000_000;
+#[cfg(feature = "clock-72mhz")]
+pub const PCLK1_HZ: u32 = 36_000_000;
+#[cfg(feature = "clock-72mhz")]
+pub const ADCCLK_HZ: u32 = 12_000_000;
+
+/// GPIOA CRL with the R-2R ladder: PA0-PA7 push-pull.
+pub const GPIOA_CRL_R2R: u32 = 0x3333_3333;
+/// GPIOA CRL with an SPI DAC: PA4 push-pull for CS, PA5 and PA7 alternate
+/// push-pull for SCK and MOSI.
+pub const GPIOA_CRL_SPI_DAC: u32 = 0xb4b3_3333;
+/// GPIOA CRH: PA8 alternate push-pull for TIM1_CH1, PA9 push-pull for the
+/// detuned oscillator, PA10/PA11 pull-up inputs for the encoder, PA12
+/// push-pull for the scope trigger.
+pub const GPIOA_CRH: u32 = 0x0003_883b;
+
+// GPIOA pins
+/// Lowest R-2R ladder bit, the rest follow on the next seven pins. At most 8,
+/// so the whole ladder stays in the port's low half.
+pub const R2R_LSB: u32 = 0;
+pub const SPI_CS: u32 = 4;
+pub const DETUNE: u32 = 9;
+/// Encoder phase A, with B on the next pin. Both sit on EXTI lines 10 and
+/// 11, which `init` routes through EXTICR3 to the EXTI15_10 interrupt.
+pub const ENCODER_A: u32 = 10;
+pub const SCOPE: u32 = 12;
+
+// GPIOB pins, also their EXTI lines
+pub const OUT: u32 = 1;
+pub const HARD_SYNC: u32 = 5;
+pub const SUB1: u32 = 8;
+pub const SUB2: u32 = 9;
+
+// GPIOC pins, also their EXTI lines
+pub const OUT2: u32 = 6;
+pub const HARD_SYNC2: u32 = 7;
+
+/// TIM2 partial remap 2, which puts CH3 on PB10 for the CV output. CH1 and
+/// CH2 move onto ladder pins, but their outputs stay off.
+pub const TIM2_REMAP: u8 = 0b10;
+
+/// TIM3 partial remap, which also puts CH2 on the hard sync pin for capture.
+pub const TIM3_REMAP: u8 = 0b10;
+
+/// The tuning LED on PC13 lights when the pin is low.
+pub const TUNE_LED_ACTIVE_LOW: bool = true;
+
+pub type Button = gpiob::PB12<Input<PullUp>>;
+pub type Cv = gpiob::PB0<Analog>;
+/// Second voice CV on PC0, ADC channel 10.
+pub type Cv2 = gpioc::PC0<Analog>;
+/// Pulse width CV on PC1, ADC channel 11.
+pub type PwCv = gpioc::PC1<Analog>;
+/// Envelope CV on PC2, ADC channel 12.
+pub type EnvCv = gpioc::PC2<Analog>;
+/// Transpose CV on PC3, ADC channel 13, through the same input stage as the
+/// pitch CV.
+pub type TransposeCv = gpioc::PC3<Analog>;
+/// Modulation CVs on PC4 and PC5, ADC channels 14 and 15.
+pub type ModCv1 = gpioc::PC4<Analog>;
+pub type ModCv2 = gpioc::PC5<Analog>;
+/// MIDI gate on PC14, which only sinks 3 mA so it needs a buffer.
+pub type Gate = gpioc::PC14<Output<PushPull>>;
+pub type HardSync = gpiob::PB5<Input<Floating>>;
+pub type HardSync2 = gpioc::PC7<Input<Floating>>;
+pub type Out = gpiob::PB1<Output<PushPull>>;
+pub type Out2 = gpioc::PC6<Output<PushPull>>;
+pub type Ring = gpiob::PB10<Output<PushPull>>;
+pub type Scl = gpiob::PB6<Alternate<OpenDrain>>;
+pub type Sda = gpiob::PB7<Alternate<OpenDrain>>;
+pub type SegmentData = gpiob::PB15<Output<PushPull>>;
+pub type SegmentClock = gpiob::PB13<Output<PushPull>>;
+pub type SegmentLatch = gpiob::PB14<Output<PushPull>>;
+pub type Sub1 = gpiob::PB8<Output<PushPull>>;
+pub type Sub2 = gpiob::PB9<Output<PushPull>>;
+pub type SyncOut = gpiob::PB11<Output<PushPull>>;
+/// Note-change trigger on PB2, where BOOT1 is only sampled at reset.
+pub type Trigger = gpiob::PB2<Output<PushPull>>;
+pub type TuneLed = gpioc::PC13<Output<PushPull>>;
+
+/// Pins on GPIOB and GPIOC, set up for the features picked.
+pub struct Pins {
+    pub button: Button,
+    pub cv: Cv,
+    #[cfg(feature = "dual")]
+    pub cv2: Cv2,
+    #[cfg(feature = "pwm-cv")]
+    pub pw_cv: PwCv,
+    #[cfg(feature = "env-cv")]
+    pub env_cv: EnvCv,
+    #[cfg(feature = "transpose-cv")]
+    pub transpose_cv: TransposeCv,
+    #[cfg(feature = "mod-cv")]
+    pub mod_cv1: ModCv1,
+    #[cfg(feature = "mod-cv")]
+    pub mod_cv2: ModCv2,
+    pub gate: Gate,
+    pub hard_sync: HardSync,
+    #[cfg(feature = "dual")]
+    pub hard_sync2: HardSync2,
+    pub out: Out,
+    #[cfg(feature = "dual")]
+    pub out2: Out2,
+    #[cfg(not(any(
+        feature = "midi-out",
+        feature = "cli",
+        feature = "ii",
+        feature = "cv-out"
+    )))]
+    pub ring: Ring,
+    pub scl: Scl,
+    pub sda: Sda,
+    #[cfg(feature = "segments")]
+    pub segments: (SegmentData, SegmentClock, SegmentLatch),
+    pub sub1: Sub1,
+    pub sub2: Sub2,
+    #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+    pub sync_out: SyncOut,
+    pub trigger: Trigger,
+    pub tune_led: TuneLed,
+}
+
+/// Sets up every pin. PB10 and PB11 are the ring mod and sync outputs, or
+/// with `midi` and `cli` USART3 (PB11 stays a floating input for RX, PB10
+/// turns into TX for `midi-out` and `cli`), or with `ii` I2C2 SCL and SDA.
+/// `cv-out` turns PB10 into TIM2_CH3 instead.
+pub fn split(mut gpiob: gpiob::Parts, mut gpioc: gpioc::Parts) -> Pins {
+    #[cfg(any(feature = "midi-out", feature = "cli", feature = "cv-out"))]
+    gpiob.pb10.into_alternate_push_pull(&mut gpiob.crh);
+    #[cfg(feature = "ii")]
+    {
+        gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
+        gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
+    }
+
+    Pins {
+        button: gpiob.pb12.into_pull_up_input(&mut gpiob.crh),
+        cv: gpiob.pb0.into_analog(&mut gpiob.crl),
+        #[cfg(feature = "dual")]
+        cv2: gpioc.pc0.into_analog(&mut gpioc.crl),
+        #[cfg(feature = "pwm-cv")]
+        pw_cv: gpioc.pc1.into_analog(&mut gpioc.crl),
+        #[cfg(feature = "env-cv")]
+        env_cv: gpioc.pc2.into_analog(&mut gpioc.crl),
+        #[cfg(feature = "transpose-cv")]
+        transpose_cv: gpioc.pc3.into_analog(&mut gpioc.crl),
+        #[cfg(feature = "mod-cv")]
+        mod_cv1: gpioc.pc4.into_analog(&mut gpioc.crl),
+        #[cfg(feature = "mod-cv")]
+        mod_cv2: gpioc.pc5.into_analog(&mut gpioc.crl),
+        gate: gpioc.pc14.into_push_pull_output(&mut gpioc.crh),
+        hard_sync: gpiob.pb5.into_floating_input(&mut gpiob.crl),
+        #[cfg(feature = "dual")]
+        hard_sync2: gpioc.pc7.into_floating_input(&mut gpioc.crl),
+        out: gpiob.pb1.into_push_pull_output(&mut gpiob.crl),
+        #[cfg(feature = "dual")]
+        out2: gpioc.pc6.into_push_pull_output(&mut gpioc.crl),
+        #[cfg(not(any(
+            feature = "midi-out",
+            feature = "cli",
+            feature = "ii",
+            feature = "cv-out"
+        )))]
+        ring: gpiob.pb10.into_push_pull_output(&mut gpiob.crh),
+        scl: gpiob.pb6.into_alternate_open_drain(&mut gpiob.crl),
+        sda: gpiob.pb7.into_alternate_open_drain(&mut gpiob.crl),
+        #[cfg(feature = "segments")]
+        segments: (
+            gpiob.pb15.into_push_pull_output(&mut gpiob.crh),
+            gpiob.pb13.into_push_pull_output(&mut gpiob.crh),
+            gpiob.pb14.into_push_pull_output(&mut gpiob.crh),
+        ),
+        sub1: gpiob.pb8.into_push_pull_output(&mut gpiob.crh),
+        sub2: gpiob.pb9.into_push_pull_output(&mut gpiob.crh),
+        #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+        sync_out: gpiob.pb11.into_push_pull_output(&mut gpiob.crh),
+        trigger: gpiob.pb2.into_push_pull_output(&mut gpiob.crl),
+        tune_led: gpioc.pc13.into_push_pull_output(&mut gpioc.crh),
+    }
+}
@@ -0,0 +1,49 @@
+//! Board support: the pins, clock tree and raw register values of each
+//! hardware variant, picked with a `board-*` feature.
+//!
+//! Everything `init` and the tasks need to know about a layout is in its
+//! board file: the typed pins, handed out by `split`, the GPIOA
+//! configuration words for the port written raw, the pin numbers behind the
+//! BSRR and IDR accesses, and the clocks. A new layout is a copy of
+//! `bluepill.rs` with its own values and a feature to select it.
+
+#[cfg(not(feature = "board-bluepill"))]
+compile_error!("pick a board with a `board-*` feature");
+
+#[cfg(feature = "board-bluepill")]
+mod bluepill;
+#[cfg(feature = "board-bluepill")]
+pub use self::bluepill::*;
+
+/// HSI frequency, and the PLL input it makes halved.
+const HSI_HZ: u32 = 8_000_000;
+
+/// Whether the F103's clock tree makes the board's clocks exactly: the
+/// system clock straight from the HSI or the crystal, or through the PLL at
+/// 2 to 16 times its input, APB1 a power of two below it up to 16 and no
+/// faster than 36 MHz, and the ADC divided from it by 2, 4, 6 or 8 to at
+/// most 14 MHz. The HAL rounds to the nearest tree it can make instead of
+/// failing, so this is checked before it gets the chance.
+const fn clock_tree_exact() -> bool {
+    let (direct, pll_in) = match HSE_HZ {
+        Some(hz) => (hz, hz),
+        None => (HSI_HZ, HSI_HZ / 2),
+    };
+    let pll = SYSCLK_HZ % pll_in == 0 && SYSCLK_HZ / pll_in >= 2 && SYSCLK_HZ / pll_in <= 16;
+    let sysclk = SYSCLK_HZ <= 72_000_000 && (SYSCLK_HZ == direct || pll);
+
+    let apb1 = SYSCLK_HZ / PCLK1_HZ;
+    let pclk1 =
+        PCLK1_HZ <= 36_000_000 && SYSCLK_HZ % PCLK1_HZ == 0 && apb1.is_power_of_two() && apb1 <= 16;
+
+    let adc = SYSCLK_HZ / ADCCLK_HZ;
+    let adcclk =
+        ADCCLK_HZ <= 14_000_000 && SYSCLK_HZ % ADCCLK_HZ == 0 && matches!(adc, 2 | 4 | 6 | 8);
+
+    sysclk && pclk1 && adcclk
+}
+
+const _: () = assert!(
+    clock_tree_exact(),
+    "the board's clocks can't be made by the clock tree"
+);
@@ -0,0 +1,62 @@
+//! Reboot into the STM32 system bootloader, for firmware updates without an
+//! SWD probe.
+//!
+//! The request is left in a backup register, which keeps its value across a
+//! reset, and the bootloader is entered right after the reset, before any
+//! peripheral has been set up. The F103's ROM bootloader only talks to
+//! USART1, on PA9 (TX) and PA10 (RX); there is no USB DFU on this part.
+
+use stm32f1xx_hal::pac;
+
+/// Start of system memory: the bootloader's stack pointer, then its reset
+/// vector.
+const SYSTEM_MEMORY: u32 = 0x1fff_f000;
+
+/// Backup data register 1. Written raw, the backup domain has nothing else
+/// in it.
+const BKP_DR1: *mut u32 = 0x4000_6c04 as *mut u32;
+const MAGIC: u32 = 0xb007;
+
+// RCC APB1ENR and PWR CR bits for backup register access
+const APB1ENR_BKPEN: u32 = 1 << 27;
+const APB1ENR_PWREN: u32 = 1 << 28;
+const CR_DBP: u32 = 1 << 8;
+
+/// Resets into the bootloader. It stays there until the next power cycle
+/// or a go command from the flashing tool.
+pub fn enter() -> ! {
+    defmt::info!("rebooting into the bootloader");
+    unsafe {
+        unlock_backup();
+        core::ptr::write_volatile(BKP_DR1, MAGIC);
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Jumps to the bootloader if [`enter`] asked for it. Runs before RAM is
+/// initialised, so it only touches registers.
+#[cortex_m_rt::pre_init]
+unsafe fn check() {
+    unlock_backup();
+    if core::ptr::read_volatile(BKP_DR1) & 0xffff != MAGIC {
+        return;
+    }
+    // Cleared first, so leaving the bootloader starts the firmware again
+    core::ptr::write_volatile(BKP_DR1, 0);
+
+    let sp = core::ptr::read_volatile(SYSTEM_MEMORY as *const u32);
+    let reset = core::ptr::read_volatile((SYSTEM_MEMORY + 4) as *const u32);
+    cortex_m::register::msp::write(sp);
+    let reset: extern "C" fn() -> ! = core::mem::transmute(reset as usize);
+    reset()
+}
+
+/// Clocks the backup domain and allows writes to it. Both stay on, they cost
+/// next to nothing.
+pub unsafe fn unlock_backup() {
+    let rcc = &*pac::RCC::ptr();
+    rcc.apb1enr
+        .modify(|r, w| w.bits(r.bits() | APB1ENR_BKPEN | APB1ENR_PWREN));
+    let pwr = &*pac::PWR::ptr();
+    pwr.cr.modify(|r, w| w.bits(r.bits() | CR_DBP));
+}
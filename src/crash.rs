@@ -0,0 +1,168 @@
+//! Crash records: what stopped the firmware before the last reset, kept in a
+//! reserved RAM region for the console to show after the reboot.
+//!
+//! The region sits above the stack and the startup code doesn't clear it, so
+//! a record survives resets but not power cycles. A CRC tells a record from
+//! whatever the RAM held at power-up.
+//!
+//! Written from the panic and HardFault handlers, so nothing in here may
+//! panic.
+#![deny(
+    clippy::arithmetic_side_effects,
+    clippy::expect_used,
+    clippy::indexing_slicing,
+    clippy::panic,
+    clippy::unwrap_used
+)]
+
+use oxide_dco_core::crc::Crc32;
+
+/// Bytes kept from the end of a panic's source path.
+pub const FILE: usize = 20;
+
+const MAGIC: u32 = 0xc4a5_11ed;
+
+// Record kinds
+const PANIC: u32 = 1;
+const HARD_FAULT: u32 = 2;
+
+/// Exception numbers of the task interrupts, IRQ number plus 16.
+const TASKS: [(u32, &str); 9] = [
+    (16 + 13, "dac_refill"),
+    (16 + 21, "software"),
+    (16 + 23, "hard_sync"),
+    (16 + 28, "measure"),
+    (16 + 29, "tick"),
+    (16 + 33, "ii_event"),
+    (16 + 34, "ii_error"),
+    (16 + 39, "serial_rx"),
+    (16 + 40, "encoder"),
+];
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Record {
+    magic: u32,
+    kind: u32,
+    pc: u32,
+    lr: u32,
+    /// Exception that was running, 0 in idle or init.
+    exception: u32,
+    line: u32,
+    file: [u8; FILE],
+    file_len: u32,
+    crc: u32,
+}
+
+pub enum Crash<'a> {
+    Panic { file: &'a str, line: u32 },
+    HardFault { pc: u32, lr: u32 },
+}
+
+/// The region reserved in `memory.x`, read and written with volatile
+/// accesses only.
+const RECORD: *mut Record = 0x2000_4fc0 as *mut Record;
+
+impl Record {
+    fn sum(&self) -> u32 {
+        let mut crc = Crc32::new();
+        for word in [
+            self.magic,
+            self.kind,
+            self.pc,
+            self.lr,
+            self.exception,
+            self.line,
+            self.file_len,
+        ]
+        .iter()
+        {
+            crc.update(&word.to_le_bytes());
+        }
+        crc.update(&self.file);
+        crc.finish()
+    }
+
+    pub fn crash(&self) -> Crash<'_> {
+        match self.kind {
+            PANIC => {
+                let file = self.file.get(..self.file_len as usize).unwrap_or(&[]);
+                Crash::Panic {
+                    file: core::str::from_utf8(file).unwrap_or("?"),
+                    line: self.line,
+                }
+            }
+            _ => Crash::HardFault {
+                pc: self.pc,
+                lr: self.lr,
+            },
+        }
+    }
+
+    /// The task that was running.
+    pub fn task(&self) -> &'static str {
+        match self.exception {
+            0 => "idle",
+            n => TASKS
+                .iter()
+                .find(|&&(e, _)| e == n)
+                .map_or("an exception", |&(_, name)| name),
+        }
+    }
+}
+
+fn store(mut record: Record) {
+    record.magic = MAGIC;
+    record.crc = record.sum();
+    unsafe { core::ptr::write_volatile(RECORD, record) };
+}
+
+fn empty(kind: u32, exception: u32) -> Record {
+    Record {
+        magic: 0,
+        kind,
+        pc: 0,
+        lr: 0,
+        exception,
+        line: 0,
+        file: [0; FILE],
+        file_len: 0,
+        crc: 0,
+    }
+}
+
+/// Records a panic at `file:line` inside `exception`. Long paths keep their
+/// end, where the file name is.
+pub fn panic(file: &str, line: u32, exception: u32) {
+    let mut record = empty(PANIC, exception);
+    let bytes = file.as_bytes();
+    let mut tail = bytes.get(bytes.len().saturating_sub(FILE)..).unwrap_or(&[]);
+    // Cut on a character boundary
+    while core::str::from_utf8(tail).is_err() {
+        tail = tail.get(1..).unwrap_or(&[]);
+    }
+    for (slot, &b) in record.file.iter_mut().zip(tail) {
+        *slot = b;
+    }
+    record.file_len = tail.len() as u32;
+    record.line = line;
+    store(record);
+}
+
+/// Records a HardFault at `pc`, returning to `lr`, inside `exception`.
+pub fn hard_fault(pc: u32, lr: u32, exception: u32) {
+    let mut record = empty(HARD_FAULT, exception);
+    record.pc = pc;
+    record.lr = lr;
+    store(record);
+}
+
+/// The latest crash since power-up, if there was one.
+pub fn last() -> Option<Record> {
+    let record = unsafe { core::ptr::read_volatile(RECORD) };
+    if record.magic == MAGIC && record.crc == record.sum() {
+        Some(record)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,125 @@
+//! Flash controller access: page erase and half-word programming, shared by
+//! the wavetables and the settings storage.
+//!
+//! Code runs from the same flash, so the CPU stalls while the controller is
+//! busy: about 20 ms per erase and 50 µs per half-word. Nothing is erased or
+//! programmed while the supply is low.
+
+use oxide_dco_core::storage;
+use stm32f1xx_hal::pac;
+
+use crate::supply;
+
+pub const PAGE: usize = 1024;
+
+/// Settings storage, two pages below the wavetables as laid out in
+/// `memory.x`. The firmware image doesn't cover them, so flashing a new
+/// build keeps the settings.
+pub const STORAGE_BASE: u32 = 0x0800_e800;
+
+// FLASH register bits
+const SR_BSY: u32 = 1 << 0;
+const SR_PGERR: u32 = 1 << 2;
+const SR_WRPRTERR: u32 = 1 << 4;
+const CR_PG: u32 = 1 << 0;
+const CR_PER: u32 = 1 << 1;
+const CR_STRT: u32 = 1 << 6;
+const CR_LOCK: u32 = 1 << 7;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+/// The flash controller reported a programming or protection error, or the
+/// supply was too low to start.
+#[derive(Clone, Copy, Debug)]
+pub struct Error;
+
+/// Erases the page at `base`.
+pub fn erase(base: u32) -> Result<(), Error> {
+    unlocked(|flash| {
+        flash.cr.modify(|r, w| unsafe { w.bits(r.bits() | CR_PER) });
+        flash.ar.write(|w| unsafe { w.bits(base) });
+        flash
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR_STRT) });
+        wait(flash)
+    })
+}
+
+/// Programs consecutive half-words from `addr`, which must be erased.
+pub fn program(addr: u32, half_words: impl Iterator<Item = u16>) -> Result<(), Error> {
+    unlocked(|flash| {
+        flash.cr.modify(|r, w| unsafe { w.bits(r.bits() | CR_PG) });
+        for (i, half_word) in half_words.enumerate() {
+            let at = (addr as usize + i * 2) as *mut u16;
+            unsafe { core::ptr::write_volatile(at, half_word) };
+            wait(flash)?;
+        }
+        Ok(())
+    })
+}
+
+/// Runs `f` with the controller unlocked, locking it again whatever `f`
+/// returns.
+fn unlocked(f: impl FnOnce(&pac::flash::RegisterBlock) -> Result<(), Error>) -> Result<(), Error> {
+    // The flash is only touched from this module, and the controller stays
+    // locked outside of it.
+    let flash = unsafe { &*pac::FLASH::ptr() };
+
+    if supply::low() {
+        return Err(Error);
+    }
+    if flash.cr.read().bits() & CR_LOCK != 0 {
+        flash.keyr.write(|w| unsafe { w.bits(KEY1) });
+        flash.keyr.write(|w| unsafe { w.bits(KEY2) });
+    }
+    let result = f(flash);
+    flash
+        .cr
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(CR_PG | CR_PER)) | CR_LOCK) });
+    result
+}
+
+fn wait(flash: &pac::flash::RegisterBlock) -> Result<(), Error> {
+    while flash.sr.read().bits() & SR_BSY != 0 {}
+
+    let sr = flash.sr.read().bits();
+    // Error flags clear by writing one
+    flash
+        .sr
+        .write(|w| unsafe { w.bits(sr & (SR_PGERR | SR_WRPRTERR)) });
+    if sr & (SR_PGERR | SR_WRPRTERR) != 0 {
+        return Err(Error);
+    }
+    Ok(())
+}
+
+/// The two settings storage pages.
+pub struct Pages;
+
+impl Pages {
+    fn addr(page: usize, offset: usize) -> Option<u32> {
+        if page >= storage::PAGES || offset >= PAGE {
+            return None;
+        }
+        Some(STORAGE_BASE + (page * PAGE + offset) as u32)
+    }
+}
+
+impl storage::Flash for Pages {
+    fn read(&self, page: usize, offset: usize) -> u16 {
+        match Self::addr(page, offset) {
+            Some(addr) => unsafe { core::ptr::read_volatile(addr as *const u16) },
+            None => storage::ERASED,
+        }
+    }
+
+    fn erase(&mut self, page: usize) -> Result<(), storage::Error> {
+        let base = Self::addr(page, 0).ok_or(storage::Error::Flash)?;
+        erase(base).map_err(|_| storage::Error::Flash)
+    }
+
+    fn program(&mut self, page: usize, offset: usize, value: u16) -> Result<(), storage::Error> {
+        let addr = Self::addr(page, offset).ok_or(storage::Error::Flash)?;
+        program(addr, core::iter::once(value)).map_err(|_| storage::Error::Flash)
+    }
+}
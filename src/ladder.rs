@@ -0,0 +1,39 @@
+//! The R-2R ladder on eight GPIOA pins from `board::R2R_LSB` up.
+//!
+//! Every code goes out as a single BSRR write that sets and resets the eight
+//! bits together, so the ladder never passes through a mix of the old and new
+//! codes, and the rest of the port is neither read nor written: the encoder
+//! pull-ups, the scope trigger and the detuned output on GPIOA can't be
+//! disturbed by a sample, however the tasks interleave.
+
+use oxide_dco_core::dac;
+use stm32f1xx_hal::pac;
+
+use crate::board;
+
+/// The ladder, owned by the tick once `init` has set it up.
+pub struct Ladder(());
+
+impl Ladder {
+    /// Makes the ladder pins push-pull outputs at 0 V. Called once from
+    /// `init`, which holds the port.
+    pub fn new(gpioa: &pac::GPIOA) -> Self {
+        gpioa.crl.write(|w| unsafe { w.bits(board::GPIOA_CRL_R2R) });
+        let mut ladder = Ladder(());
+        ladder.write(0);
+        ladder
+    }
+
+    pub fn write(&mut self, code: u16) {
+        let gpioa = unsafe { &*pac::GPIOA::ptr() };
+        gpioa
+            .bsrr
+            .write(|w| unsafe { w.bits(dac::r2r_bsrr(code) << board::R2R_LSB) });
+    }
+
+    /// Levels on the pins, for the self-test reading the ladder back.
+    pub fn read(&self) -> u8 {
+        let gpioa = unsafe { &*pac::GPIOA::ptr() };
+        (gpioa.idr.read().bits() >> board::R2R_LSB) as u8
+    }
+}
@@ -1,225 +1,3356 @@
 #![no_main]
 #![no_std]
 
-// TODO(alexyer): Update to conditionally compile to halt for release.
-use panic_semihosting as _;
+use defmt_rtt as _;
 
-use rtfm::app;
+use rtfm::{
+    app,
+    cyccnt::{Instant, U32Ext},
+    Mutex,
+};
 
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
 use stm32f1xx_hal as hal;
 
 use crate::hal::{
-    adc, gpio,
+    adc,
+    dma::{dma1, DmaExt},
+    gpio,
     gpio::ExtiPin,
+    i2c::{BlockingI2c, DutyCycle, Mode},
     pac,
     prelude::*,
-    rcc::Enable,
+    rcc::{Clocks, Enable},
     timer::{CountDownTimer, Event, Timer},
+    watchdog::IndependentWatchdog,
 };
 
-use core::sync::atomic::{AtomicI16, AtomicU32, Ordering};
+use cortex_m::peripheral::DWT;
+
+use core::fmt::{self, Write as _};
+use core::sync::atomic::{AtomicBool, AtomicI16, AtomicU16, AtomicU32, Ordering};
+
+mod board;
+mod bootloader;
+mod crash;
+mod flash;
+#[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+mod ladder;
+#[cfg(feature = "qemu")]
+mod qemu;
+mod supply;
+mod update;
+mod wavetable;
+
+#[cfg(feature = "adc-sync")]
+use oxide_dco_core::sampling;
+#[cfg(feature = "segments")]
+use oxide_dco_core::segments;
+use oxide_dco_core::{
+    amp, arp, chord, cli, custom, cv_out, dac, division, ii, kick, midi, note, params, pitch, pll,
+    poly, post, preset, profile, settings, sysex, watch, wave, ws2812,
+};
+
+#[cfg(all(feature = "midi", feature = "cli"))]
+compile_error!("the `midi` and `cli` features both need USART3");
+#[cfg(all(feature = "mcp4922", feature = "dac8568"))]
+compile_error!("pick one external DAC");
+#[cfg(all(feature = "dac-dma", any(feature = "mcp4922", feature = "dac8568")))]
+compile_error!("the `dac-dma` feature streams to the R-2R ladder");
+#[cfg(all(feature = "ii", any(feature = "midi", feature = "cli")))]
+compile_error!("the `ii` feature needs PB10/PB11 for I2C2");
+#[cfg(all(
+    feature = "cv-out",
+    any(feature = "midi-out", feature = "cli", feature = "ii")
+))]
+compile_error!("the `cv-out` feature needs PB10 for TIM2_CH3");
+
+use oxide_dco_core::amp::Curve;
+use oxide_dco_core::arp::{Arp, Step};
+use oxide_dco_core::button::{Button, Click, Clicks};
+use oxide_dco_core::calibrate::{self, Calibration, Calibrator, Failure};
+use oxide_dco_core::capture::Capture;
+use oxide_dco_core::chord::Chord;
+use oxide_dco_core::cli::{Command, Editor};
+use oxide_dco_core::config::{AVG_BUF_SIZE, TIM3_FREQ_HZ};
+use oxide_dco_core::crc::Crc32;
+#[cfg(feature = "cv-out")]
+use oxide_dco_core::cv_out::Dither;
+#[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+use oxide_dco_core::dac::SpiDac as _;
+use oxide_dco_core::display::{Line, Ssd1306};
+use oxide_dco_core::drift::Drift;
+use oxide_dco_core::encoder::{Acceleration, Quadrature};
+use oxide_dco_core::fault::{Fault, Faults};
+use oxide_dco_core::glitch::{self, GlitchFilter};
+use oxide_dco_core::health::Monitor;
+use oxide_dco_core::heartbeat::{Beat, Heartbeats};
+use oxide_dco_core::hooks::Hooks;
+use oxide_dco_core::ii::{Register, Responder};
+use oxide_dco_core::jobs::{Burnin, Runner, TestSignal};
+use oxide_dco_core::kick::Sweep;
+use oxide_dco_core::matrix::{self, Mods};
+use oxide_dco_core::midi::{Controllers, Message, NoteStack, Parser, Playing};
+use oxide_dco_core::noise::Noise;
+use oxide_dco_core::note::Note;
+use oxide_dco_core::osc::{Edge, Oscillator, Sub};
+use oxide_dco_core::outbox::Outbox;
+use oxide_dco_core::params::{
+    Param, Params, ARP_CLOCK_MIDI, ARP_CLOCK_SYNC, ARP_CLOCK_TAP, ARP_OFF, CHANNEL_OMNI,
+    CV_OUT_PITCH, CV_OUT_TRIANGLE, DAC_AMPLITUDE, DAC_MIDI_CV, DAC_MORPH, DAC_PINK, DAC_SAW,
+    DAC_SINE, DAC_TRIANGLE, DAC_WAVETABLE, DAC_WHITE, LFO_SYNC_90, LFO_SYNC_FREE, PLL_OFF, POLY_ON,
+    RANGE_LFO, RATE_CV, RATE_MIDI, RATE_TAP, RING_XOR, SLEEP_AUTO, SMOOTH_ON, SQUARE_NOISE,
+    SYNC_EDGE_FALLING, SYNC_EDGE_RISING, SYNC_REVERSE, SYNC_SOFT, TRACK_GATE, TRACK_SYNC, TUNER_ON,
+};
+use oxide_dco_core::pitch::{Glide, Override, Semitones};
+use oxide_dco_core::pll::Pll;
+use oxide_dco_core::poly::{Allocator, Voices};
+use oxide_dco_core::post::{Check, Failures};
+use oxide_dco_core::preset::Preset;
+use oxide_dco_core::profile::{Profiler, Report, Span};
+#[cfg(feature = "recorder")]
+use oxide_dco_core::recorder::{Kind, Recorder};
+#[cfg(feature = "segments")]
+use oxide_dco_core::segments::Hc595;
+use oxide_dco_core::settings::Settings;
+use oxide_dco_core::sleep::AutoSleep;
+use oxide_dco_core::source::{Arbiter, Source};
+use oxide_dco_core::storage::{self, Flash, Storage};
+use oxide_dco_core::sysex::{Receiver, Request};
+use oxide_dco_core::tap::Tap;
+use oxide_dco_core::trigger::NoteChange;
+use oxide_dco_core::vibrato::Vibrato;
+use oxide_dco_core::voice::{Input, Voice, PUBLISH_TICKS};
+use oxide_dco_core::watch::{Channel, Watch};
+use oxide_dco_core::ws2812::Rgb;
 
-use eurorack_oxide_utils::voct::MvOct;
-use eurorack_oxide_utils::voct::Voltage;
+#[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+use crate::ladder::Ladder;
 
-const AVG_BUF_SIZE: usize = 32;
-const TIM3_FREQ_HZ: u32 = 200000;
+const SYSCLK_HZ: u32 = board::SYSCLK_HZ;
 const SEC_IN_US: u32 = 1000000;
-const FINE_TUNE_STEP: i16 = 2;
+const MV_IN_OCT: i32 = 1000;
+// Pitch is published once per averaging buffer of TIM2 samples
+const PUBLISH_US: u32 = AVG_BUF_SIZE as u32 * SEC_IN_US / (TIM3_FREQ_HZ / 2);
+// Mid-scale reading of the pulse width CV and the swing it gives at the rails,
+// in percent.
+#[cfg(feature = "pwm-cv")]
+const PW_CV_CENTER: i32 = 2048;
+#[cfg(feature = "pwm-cv")]
+const PW_CV_RANGE: i32 = 45;
+// Change in the envelope reading that keeps the module awake, about 1%
+#[cfg(feature = "env-cv")]
+const ENV_STILL: i32 = 40;
+const UI_POLL_MS: u32 = 5;
+const DOUBLE_CLICK_MS: u32 = 300;
+const LONG_PRESS_MS: u32 = 600;
+const DISPLAY_INTERVAL_MS: u32 = 100;
+// Quiet time on the pages before the settings are saved
+const SETTINGS_SAVE_MS: u32 = 5000;
+// Longer than the slowest flash erase, which stalls every task
+const WATCHDOG_TIMEOUT_MS: u32 = 500;
+const WATCHDOG_CHECK_MS: u32 = 100;
+const SLEEP_AFTER_MS: u32 = 60_000;
+// Measurement rate while asleep, against the usual half tick rate
+const SLEEP_MEASURE_DIVIDER: u32 = 10;
+const POST_BLINK_MS: u32 = 200;
+const POST_PAUSE_MS: u32 = 1000;
+const POST_REPEATS: u8 = 2;
+// Encoder button held at power-up for this long wipes the storage, a
+// shorter hold enters the bootloader
+const FACTORY_RESET_HOLD_MS: u32 = 5000;
+const FACTORY_RESET_BLINKS: u8 = 10;
+const FACTORY_RESET_BLINK_MS: u32 = 50;
+// GPIO input sampling lags a write by a couple of bus cycles
+const POST_SETTLE_CYCLES: u32 = 16;
+const BURNIN_DWELL_MS: u32 = 2000;
+const BURNIN_SWEEPS: u16 = 1000;
+const TEST_LOW_MV: i32 = 0;
+const TEST_HIGH_MV: i32 = 8000;
+const TEST_DURATION_MS: u32 = 10_000;
+// Note-change trigger length, counted in pitch publishes
+const TRIGGER_MS: u32 = 5;
+const TRIGGER_PUBLISHES: u8 = (TRIGGER_MS * 1000 / PUBLISH_US) as u8;
+// MIDI note that leaves the CV untransposed in `sum` mode, C4
+const MIDI_TRANSPOSE_ROOT: i32 = 60;
+// Conversions tried per sample before the slot keeps its old value
+const ADC_ATTEMPTS: u8 = 2;
+// Polls of the ADC flags before a conversion started from TIM3 counts as
+// failed, at least 100 µs however fast a poll is, four conversions' worth
+#[cfg(feature = "adc-sync")]
+const ADC_SYNC_POLLS: u32 = SYSCLK_HZ / 10_000;
+// ADC CR2 bits: power, external trigger enable and its selection, all ones
+// for SWSTART
+#[cfg(feature = "adc-sync")]
+const ADC_CR2_ADON: u32 = 1 << 0;
+#[cfg(feature = "adc-sync")]
+const ADC_CR2_EXTTRIG: u32 = 1 << 20;
+#[cfg(feature = "adc-sync")]
+const ADC_CR2_EXTSEL: u32 = 0b111 << 17;
+#[cfg(feature = "adc-sync")]
+const ADC_EXTSEL_TIM3_TRGO: u32 = 0b100 << 17;
+// USART CR1 transmit interrupt enable, set while output is queued
+const USART_TXEIE: u32 = 1 << 7;
+
+const RCC_CSR_RMVF: u32 = 1 << 24;
+const DBGMCU_CR_DBG_SLEEP: u32 = 1 << 0;
+const RCC_CSR_IWDGRSTF: u32 = 1 << 29;
+// Parameters played from MIDI controllers, by MSB controller number
+const CC_PARAMS: [(u8, Param); 6] = [
+    // Modulation wheel
+    (1, Param::Vibrato),
+    // Portamento time
+    (5, Param::Glide),
+    // Effect controls 1 and 2
+    (12, Param::PulseWidth),
+    (13, Param::Detune),
+    // General purpose 1 and 2
+    (16, Param::FineTune),
+    (17, Param::VibratoRate),
+];
+// I2C SR1 flags the follower handles, and the error flags it clears
+const I2C_ADDR: u32 = 1 << 1;
+const I2C_STOPF: u32 = 1 << 4;
+const I2C_RXNE: u32 = 1 << 6;
+const I2C_TXE: u32 = 1 << 7;
+const I2C_ERRORS: u32 = 0b1111 << 8;
+// Sync output pulse length, long enough for an EXTI input to see the level
+const SYNC_OUT_TICKS: u8 = 2;
+// Scope trigger pulse length, as for the sync output
+const SCOPE_TICKS: u8 = 2;
+// Longest tapped interval, the slowest LFO period
+const TAP_TIMEOUT_MS: u32 = 20_000;
+// How often the arpeggiator's clock task looks for a tapped tempo
+const ARP_POLL_MS: u32 = 10;
+// How often a CV calibration run looks at the CV and the sync input
+const CAL_POLL_MS: u32 = 100;
+// Measurement buffers between internal temperature readings
+const TEMP_INTERVAL: usize = 256;
+const WATCH_INTERVAL_MS: u16 = 100;
+#[cfg(feature = "profile")]
+const PROFILE_INTERVAL_MS: u32 = 1000;
+#[cfg(feature = "telemetry")]
+const TELEMETRY_INTERVAL_MS: u32 = 1000;
+const LED_INTERVAL_MS: u32 = 20;
+const TUNE_POLL_MS: u32 = 10;
+// Within this many cents of a note the tuning LED stays lit
+const IN_TUNE_CENTS: u32 = 3;
+// Error accumulated over polls per toggle: 50 cents off blinks every 50 ms,
+// 5 cents every 500 ms
+const TUNE_BLINK_CENTS: u32 = 250;
+// Status LED stays bright this long after a hard sync edge
+const LED_FLASH_MS: u32 = 40;
+const LED_DIM: u8 = 32;
+// TIM1 counts at SYSCLK, one PWM period per WS2812 bit at ~800 kHz
+const WS2812_PERIOD: u16 = (SYSCLK_HZ / 800_000) as u16;
+const WS2812_T0H: u16 = WS2812_PERIOD / 3;
+const WS2812_T1H: u16 = WS2812_PERIOD * 2 / 3;
+#[cfg(feature = "recorder")]
+const REPLAY_DRAIN_MS: u32 = 10;
+// Each digit is lit for one scan period
+#[cfg(feature = "segments")]
+const SEGMENT_SCAN_MS: u32 = 2;
+// Scans between frame rebuilds, a multiple of the digit count
+#[cfg(feature = "segments")]
+const SEGMENT_REFRESH_SCANS: u8 = 48;
+#[cfg(feature = "watch")]
+const WATCH_DEFAULT: u8 = watch::ALL;
+#[cfg(not(feature = "watch"))]
+const WATCH_DEFAULT: u8 = 0;
+
+type Display = Ssd1306<BlockingI2c<pac::I2C1, (board::Scl, board::Sda)>>;
+
+#[cfg(feature = "mcp4922")]
+type SpiDac = dac::Mcp4922;
+#[cfg(feature = "dac8568")]
+type SpiDac = dac::Dac8568;
+
+#[cfg(feature = "segments")]
+type Segments = Hc595<board::SegmentData, board::SegmentClock, board::SegmentLatch>;
+
+fn set_level<P: OutputPin>(pin: &mut P, high: bool) {
+    if high {
+        pin.set_high().ok();
+    } else {
+        pin.set_low().ok();
+    }
+}
+
+/// One averaging buffer's worth of measurements, handed from the measurement
+/// interrupt to `publish`.
+struct Reading {
+    avg: u32,
+    #[cfg(feature = "dual")]
+    avg2: u32,
+    vref: u16,
+    /// Pulse width CV offset in percent.
+    pw_cv: i32,
+    /// Envelope CV reading, `amp::ENV_FULL` without the input.
+    env: u16,
+    /// Transpose CV reading, `None` without the input.
+    transpose: Option<u16>,
+    /// Modulation CV readings, for the matrix to route.
+    #[cfg(feature = "mod-cv")]
+    mod_cv: [u16; 2],
+    /// A conversion in the buffer failed, or the checks rejected a sample.
+    missed: bool,
+}
+
+/// Converts `pin`, trying again on a failure, which is usually a one-off.
+/// Every failed attempt is counted; `None` once they all fail.
+fn convert<P>(adc: &mut adc::Adc<pac::ADC1>, pin: &mut P, faults: &Faults) -> Option<u16>
+where
+    P: embedded_hal::adc::Channel<pac::ADC1, ID = u8>,
+{
+    for _ in 0..ADC_ATTEMPTS {
+        match adc.read(pin) {
+            Ok(sample) => return Some(sample),
+            Err(_) => {
+                if faults.raise(Fault::Adc) {
+                    defmt::warn!("adc read failed");
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Converts a pitch CV input, with a software start like the other inputs
+/// unless the `adc-sync` feature times it.
+#[cfg(not(feature = "adc-sync"))]
+fn convert_cv<P>(adc: &mut adc::Adc<pac::ADC1>, pin: &mut P, faults: &Faults) -> Option<u16>
+where
+    P: embedded_hal::adc::Channel<pac::ADC1, ID = u8>,
+{
+    convert(adc, pin, faults)
+}
+
+/// Converts a pitch CV input like [`convert`], but with the conversion
+/// started by the TIM3 compare `init` sets up, so sampling ends just before
+/// a tick. The trigger is taken back as soon as the conversion starts, and
+/// the ADC is left powered down on software starts, the way the HAL's reads
+/// leave it. Should the compare never start one, `adc` converts by software
+/// instead, so a stopped trigger costs the timing and not the reading.
+#[cfg(feature = "adc-sync")]
+fn convert_cv<P>(adc: &mut adc::Adc<pac::ADC1>, pin: &mut P, faults: &Faults) -> Option<u16>
+where
+    P: embedded_hal::adc::Channel<pac::ADC1, ID = u8>,
+{
+    // The registers are only touched while `adc` is borrowed, so nothing
+    // else converts meanwhile
+    let adc1 = unsafe { &*pac::ADC1::ptr() };
+    let channel = P::channel() as u32;
+    // The short sampling time the trigger count is worked out for; the HAL
+    // sets its own again on its next read of the channel
+    let smp = |r: u32, at: u32| (r & !(0b111 << at)) | (sampling::SAMPLE_TIME_BITS << at);
+    if channel < 10 {
+        adc1.smpr2
+            .modify(|r, w| unsafe { w.bits(smp(r.bits(), 3 * channel)) });
+    } else {
+        adc1.smpr1
+            .modify(|r, w| unsafe { w.bits(smp(r.bits(), 3 * (channel - 10))) });
+    }
+    adc1.sqr3.write(|w| unsafe { w.bits(channel) });
+    // Powered up on its own, as writing ADON over a set one would start a
+    // conversion, then given tSTAB to settle
+    if adc1.cr2.read().bits() & ADC_CR2_ADON == 0 {
+        adc1.cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() | ADC_CR2_ADON) });
+        cortex_m::asm::delay(SYSCLK_HZ / SEC_IN_US);
+    }
+
+    let mut sample = None;
+    let mut stalled = false;
+    for _ in 0..ADC_ATTEMPTS {
+        // Clears EOC and STRT from anything before
+        adc1.dr.read();
+        adc1.sr.write(|w| unsafe { w.bits(0) });
+        // EXTSEL: TIM3_TRGO, with the external trigger on. Changing them
+        // along with ADON doesn't start a conversion.
+        adc1.cr2.modify(|r, w| unsafe {
+            w.bits((r.bits() & !ADC_CR2_EXTSEL) | ADC_EXTSEL_TIM3_TRGO | ADC_CR2_EXTTRIG)
+        });
+        let started = poll(|| adc1.sr.read().strt().bit_is_set());
+        // Back to SWSTART before the conversion ends, after which the next
+        // compare could start another; the ADC ignores them until then
+        adc1.cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() | ADC_CR2_EXTSEL) });
+        if !started {
+            stalled = true;
+            break;
+        }
+        if poll(|| adc1.sr.read().eoc().bit_is_set()) {
+            sample = Some(adc1.dr.read().data().bits());
+            break;
+        }
+        if faults.raise(Fault::Adc) {
+            defmt::warn!("adc read failed");
+        }
+    }
+    adc1.cr2
+        .modify(|r, w| unsafe { w.bits(r.bits() & !ADC_CR2_ADON) });
+
+    if stalled {
+        convert(adc, pin, faults)
+    } else {
+        sample
+    }
+}
+
+/// Whether `done` comes true within [`ADC_SYNC_POLLS`] polls.
+#[cfg(feature = "adc-sync")]
+fn poll(done: impl Fn() -> bool) -> bool {
+    (0..ADC_SYNC_POLLS).any(|_| done())
+}
+
+/// Whether a pitch CV sample may move the pitch. Raises the fault once the
+/// input turns out broken, and again if it breaks after recovering.
+fn sane(monitor: &mut Monitor, broken: &mut bool, sample: u16, faults: &Faults) -> bool {
+    let health = monitor.check(sample);
+    if health.is_fault() && !*broken {
+        faults.raise(Fault::CvInput);
+        defmt::warn!("cv input at a rail or stuck, holding the pitch");
+    }
+    *broken = health.is_fault();
+    !health.is_bad()
+}
+
+/// Phase a sync edge resets the oscillators to, `None` when the LFO free-runs.
+fn sync_phase(params: &Params) -> Option<u32> {
+    if params.get(Param::Range) != RANGE_LFO {
+        return Some(0);
+    }
+
+    match params.get(Param::LfoSync) {
+        LFO_SYNC_90 => Some(1 << 30),
+        LFO_SYNC_FREE => None,
+        _ => Some(0),
+    }
+}
+
+/// Whether a sync input at level `high` still matches the edge polarity, so
+/// the edge wasn't a spike already gone. Both polarities can't tell.
+fn sync_settled(high: bool, params: &Params) -> bool {
+    match params.get(Param::SyncEdge) {
+        SYNC_EDGE_RISING => high,
+        SYNC_EDGE_FALLING => !high,
+        _ => true,
+    }
+}
+
+/// Clock divider/multiplier factor picked on the menu, if that mode is on.
+fn clock_ratio(params: &Params) -> Option<pll::Ratio> {
+    pll::CLOCK_RATIOS
+        .get((params.get(Param::Clock) - 1) as usize)
+        .copied()
+}
+
+/// Plays the arpeggiator's next step: a held note goes to `playing`, an
+/// interval to the offset `publish` adds to the pitch.
+fn arp_step(
+    arp: &mut Arp,
+    notes: &NoteStack,
+    params: &Params,
+    playing: &Playing,
+    offset: &AtomicI16,
+) {
+    let intervals = arp::INTERVALS
+        .get(params.get(Param::ArpIntervals) as usize)
+        .unwrap_or(&arp::INTERVALS[0]);
+    match arp.step(params.get(Param::Arp), notes, intervals) {
+        Some(Step::Note(note)) => {
+            playing.set(Some(note));
+            offset.store(0, Ordering::Relaxed);
+        }
+        Some(Step::Offset(mv)) => offset.store(mv as i16, Ordering::Relaxed),
+        None => offset.store(0, Ordering::Relaxed),
+    }
+}
+
+/// Level at the hard sync jack. Reading IDR doesn't touch the pin the sync
+/// task owns.
+fn sync_input_high() -> bool {
+    let idr = unsafe { (*pac::GPIOB::ptr()).idr.read().bits() };
+    idr & (1 << board::HARD_SYNC) != 0
+}
 
-const fn circle_time() -> u32 {
-    SEC_IN_US / TIM3_FREQ_HZ
+/// Applies a sync edge to `osc` with the sync flavour and LFO phase picked on
+/// the menu.
+fn sync(osc: &Oscillator, params: &Params) {
+    // The PLL pulls the phase in itself
+    if params.get(Param::Pll) != PLL_OFF {
+        return;
+    }
+
+    let phase = match sync_phase(params) {
+        Some(phase) => phase,
+        None => return,
+    };
+
+    match params.get(Param::Sync) {
+        SYNC_SOFT => osc.soft_reset_to(phase),
+        SYNC_REVERSE => osc.reverse(),
+        _ => osc.reset_to(phase),
+    }
+}
+
+/// Sample of the mode on `dac` for the oscillator at `phase`, stepping by
+/// `step` a tick, or `None` in the modes that send a level instead. In the
+/// paraphonic mode it is the voices' mix `ahead` ticks from now instead.
+fn dac_sample(
+    params: &Params,
+    chord: &mut Chord,
+    noise: &mut Noise,
+    voices: &Voices,
+    ahead: u32,
+    phase: u32,
+    step: u32,
+) -> Option<u8> {
+    let dac_mode = params.get(Param::Dac);
+    let bank = params.get(Param::Bank) as usize;
+    let morph = params.get(Param::Morph);
+    let shape = |phase: u32, step: u32| match dac_mode {
+        DAC_WAVETABLE => Some(wavetable::sample(bank, (phase >> 24) as u8)),
+        DAC_SAW => Some(wave::saw(phase, step)),
+        DAC_SINE => Some(wave::sine(phase)),
+        DAC_TRIANGLE => Some(wave::triangle(phase)),
+        DAC_MORPH => Some(wave::morph(phase, step, morph)),
+        _ => None,
+    };
+    match dac_mode {
+        DAC_WHITE => Some(noise.white()),
+        DAC_PINK => Some(noise.pink()),
+        _ if params.get(Param::Poly) == POLY_ON => shape(phase, step)
+            .map(|root| voices.mix(ahead, |phase, step| shape(phase, step).unwrap_or(root))),
+        _ => shape(phase, step).map(|root| {
+            // Chord mode stacks the same shape above the pitch
+            match chord::SHAPES.get((params.get(Param::Chord) - 1) as usize) {
+                Some(shape_notes) => chord.mix(root, step, shape_notes, |phase, step| {
+                    shape(phase, step).unwrap_or(root)
+                }),
+                None => root,
+            }
+        }),
+    }
+}
+
+#[defmt::timestamp]
+fn timestamp() -> u64 {
+    (DWT::get_cycle_count() / (SYSCLK_HZ / SEC_IN_US)) as u64
+}
+
+/// Times the rest of the calling handler with the `profile` feature.
+fn span(profiler: &Profiler, task: profile::Task) -> Option<Span<'_>> {
+    if cfg!(feature = "profile") {
+        Some(profiler.span(task, DWT::get_cycle_count))
+    } else {
+        None
+    }
+}
+
+/// Drives the detuned oscillator's pin on PA9.
+fn set_detune(high: bool) {
+    let bits = if high {
+        1 << board::DETUNE
+    } else {
+        1 << (board::DETUNE + 16)
+    };
+    // BSRR writes are atomic, so this doesn't have to lock the port
+    unsafe { (*pac::GPIOA::ptr()).bsrr.write(|w| w.bits(bits)) };
 }
 
-fn us_to_period(us: u32) -> u32 {
-    us / circle_time() / 2
+/// Stops everything with the outputs in a safe state: interrupts off, so the
+/// DAC holds its last code, and the square outputs low.
+fn safe_outputs() {
+    cortex_m::interrupt::disable();
+    // Nothing else runs any more, so the ports are ours
+    unsafe {
+        // Square and sub-octaves
+        (*pac::GPIOB::ptr())
+            .bsrr
+            .write(|w| w.bits(((1 << board::OUT) | (1 << board::SUB1) | (1 << board::SUB2)) << 16));
+        // Detuned oscillator
+        (*pac::GPIOA::ptr())
+            .bsrr
+            .write(|w| w.bits(1 << (board::DETUNE + 16)));
+        // Second voice
+        if cfg!(feature = "dual") {
+            (*pac::GPIOC::ptr())
+                .bsrr
+                .write(|w| w.bits(1 << (board::OUT2 + 16)));
+        }
+    }
+}
+
+/// Stops at a breakpoint in debug builds, or resets to get the module playing
+/// again in release builds.
+fn halt() -> ! {
+    if cfg!(debug_assertions) {
+        loop {
+            cortex_m::asm::bkpt();
+        }
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Exception number the core is handling, 0 in thread mode.
+fn active_exception() -> u32 {
+    unsafe { (*cortex_m::peripheral::SCB::ptr()).icsr.read() & 0x1ff }
 }
 
-fn avg(buf: &mut [u16; AVG_BUF_SIZE]) -> u32 {
-    let mut acc: u32 = 0;
-    for i in 0..buf.len() {
-        acc += buf[i] as u32;
+/// Leaves the outputs safe and logs the panic location over RTT and in the
+/// crash record, then halts.
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    safe_outputs();
+    match info.location() {
+        Some(at) => {
+            crash::panic(at.file(), at.line(), active_exception());
+            defmt::error!("panic at {}:{}", at.file(), at.line());
+        }
+        None => {
+            crash::panic("", 0, active_exception());
+            defmt::error!("panic");
+        }
     }
+    halt()
+}
 
-    acc / AVG_BUF_SIZE as u32
+/// As for a panic, with the faulting address from the stacked frame.
+#[cortex_m_rt::exception]
+fn HardFault(frame: &cortex_m_rt::ExceptionFrame) -> ! {
+    safe_outputs();
+    // The stacked xPSR holds the exception that was interrupted
+    crash::hard_fault(frame.pc, frame.lr, frame.xpsr & 0x1ff);
+    defmt::error!("hardfault pc={:x} lr={:x}", frame.pc, frame.lr);
+    halt()
 }
 
-#[app(device = stm32f1xx_hal::pac, peripherals = true)]
+/// Writes a crash record the way the console shows it.
+fn write_crash(out: &mut impl fmt::Write, record: &crash::Record) -> fmt::Result {
+    match record.crash() {
+        crash::Crash::Panic { file, line } => {
+            write!(out, "panic at {}:{}", file, line)?;
+        }
+        crash::Crash::HardFault { pc, lr } => {
+            write!(out, "hardfault pc={:08x} lr={:08x}", pc, lr)?;
+        }
+    }
+    writeln!(out, " in {}", record.task())
+}
+
+/// Blinks the code of every failed self-test check on the tuning LED, a
+/// couple of times over, before the tasks start.
+fn blink_codes(led: &mut impl OutputPin, failures: Failures) {
+    let ms = |ms: u32| cortex_m::asm::delay(ms * (SYSCLK_HZ / 1000));
+    for _ in 0..POST_REPEATS {
+        for check in failures.iter() {
+            for _ in 0..check.blinks() {
+                set_level(led, !board::TUNE_LED_ACTIVE_LOW);
+                ms(POST_BLINK_MS);
+                set_level(led, board::TUNE_LED_ACTIVE_LOW);
+                ms(POST_BLINK_MS);
+            }
+            ms(POST_PAUSE_MS);
+        }
+    }
+}
+
+/// Factory reset: erases both storage pages, so the mount formats them and
+/// the pages, presets, amplitude curve and CV calibration all start from
+/// their defaults, then blinks the tuning LED fast to confirm.
+fn factory_reset(led: &mut impl OutputPin) {
+    defmt::warn!("factory reset");
+    let mut pages = flash::Pages;
+    for page in 0..storage::PAGES {
+        if pages.erase(page).is_err() {
+            defmt::error!("factory reset: erasing page {} failed", page);
+        }
+    }
+
+    let ms = |ms: u32| cortex_m::asm::delay(ms * (SYSCLK_HZ / 1000));
+    for _ in 0..FACTORY_RESET_BLINKS {
+        set_level(led, !board::TUNE_LED_ACTIVE_LOW);
+        ms(FACTORY_RESET_BLINK_MS);
+        set_level(led, board::TUNE_LED_ACTIVE_LOW);
+        ms(FACTORY_RESET_BLINK_MS);
+    }
+}
+
+/// Status LED color: hue follows the octave, flashing on hard sync and red
+/// while any fault is latched.
+fn status_color(pitch_mv: i32, flash: bool, faults: &Faults) -> Rgb {
+    if faults.any() {
+        return ws2812::RED;
+    }
+
+    let octave = pitch_mv.div_euclid(MV_IN_OCT).clamp(0, 7) as u16;
+    let value = if flash { u8::MAX } else { LED_DIM };
+    ws2812::hue(octave * 45, value)
+}
+
+/// Draws note, frequency, fine tune and the active menu page. A missing or
+/// unresponsive display just drops the frame.
+fn draw_status(display: &mut Display, hz: f32, frozen: bool, params: &Params) {
+    let mut rows = [Line::new(), Line::new(), Line::new(), Line::new()];
+
+    match Note::from_hz(hz) {
+        Some(note) => write!(
+            rows[0],
+            "{}{} {:+}C",
+            note.name(),
+            note.octave(),
+            note.cents as i32
+        ),
+        None => write!(rows[0], "--"),
+    }
+    .ok();
+
+    let dhz = (hz * 10.0) as u32;
+    write!(rows[1], "{}.{} HZ", dhz / 10, dhz % 10).ok();
+    write!(rows[2], "FINE {:+}MV", params.get(Param::FineTune)).ok();
+    if frozen {
+        write!(rows[2], " HOLD").ok();
+    }
+    if params.get(Param::Tuner) == TUNER_ON {
+        write!(rows[2], " REF").ok();
+    }
+
+    let page = params.page();
+    let info = page.info();
+    let value = params.get(page);
+    match info.label(value) {
+        Some(label) => write!(rows[3], "> {} {}", info.name, label),
+        None => write!(rows[3], "> {} {:+}", info.name, value),
+    }
+    .ok();
+
+    for (i, row) in rows.iter().enumerate() {
+        if display.draw_row(i as u8, row.as_str()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Console output into the outbox, locked one piece at a time so the serial
+/// task never waits for a whole reply to be formatted.
+struct Console<M>(M);
+
+impl<M: Mutex<T = Outbox>> fmt::Write for Console<M> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.lock(|outbox| {
+            outbox.write_str(s).ok();
+            // The receive task owns the USART, but can't run inside the lock
+            unsafe {
+                (*pac::USART3::ptr())
+                    .cr1
+                    .modify(|r, w| w.bits(r.bits() | USART_TXEIE))
+            };
+        });
+        Ok(())
+    }
+}
+
+/// Writes a page value the way the menu shows it.
+fn write_value(out: &mut impl fmt::Write, p: Param, params: &Params) -> fmt::Result {
+    let info = p.info();
+    let value = params.get(p);
+    match info.label(value) {
+        Some(label) => writeln!(out, "{} {}", info.name, label),
+        None => writeln!(out, "{} {}", info.name, value),
+    }
+}
+
+/// Queues a SysEx reply from a task below the receive interrupt.
+fn send_ack(outbox: &mut Outbox, command: u8, status: u8) {
+    if cfg!(feature = "midi-out") {
+        sysex::ack(command, status, |b| outbox.push(b));
+        // The receive task owns the USART, but can't run inside the lock
+        unsafe {
+            (*pac::USART3::ptr())
+                .cr1
+                .modify(|r, w| w.bits(r.bits() | USART_TXEIE))
+        };
+    }
+}
+
+/// Recalls preset `slot` and shows it on the preset page. Returns `false`
+/// for empty slots, which change nothing.
+fn recall(
+    storage: &Option<Storage<flash::Pages>>,
+    params: &Params,
+    current: &mut u8,
+    slot: u8,
+) -> bool {
+    let preset = match storage.as_ref().and_then(|s| preset::load(s, slot)) {
+        Some(preset) => preset,
+        None => return false,
+    };
+    preset.apply(params);
+    params.set(Param::Preset, slot as i32);
+    *current = slot;
+    defmt::debug!("preset {}", slot);
+    true
+}
+
+#[app(device = stm32f1xx_hal::pac, peripherals = true, monotonic = rtfm::cyccnt::CYCCNT)]
 const APP: () = {
     struct Resources {
         adc1: adc::Adc<pac::ADC1>,
-        ch0: gpio::gpiob::PB0<gpio::Analog>,
+        // Amplitude compensation, loaded from the settings storage
+        amp_curve: Curve,
+        button_pin: board::Button,
+        // CV input correction, loaded from the settings storage
+        calibration: Calibration,
+        ch0: board::Cv,
+        #[cfg(feature = "dual")]
+        ch10: board::Cv2,
+        #[cfg(feature = "pwm-cv")]
+        ch11: board::PwCv,
+        #[cfg(feature = "env-cv")]
+        ch12: board::EnvCv,
+        #[cfg(feature = "transpose-cv")]
+        ch13: board::TransposeCv,
+        #[cfg(feature = "mod-cv")]
+        ch14: board::ModCv1,
+        #[cfg(feature = "mod-cv")]
+        ch15: board::ModCv2,
+        clocks: Clocks,
+        #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+        dac_dma: dma1::C3,
+        display: Display,
         exti: pac::EXTI,
+        gate: board::Gate,
         gpioa: pac::GPIOA,
-        hard_sync: gpio::gpiob::PB5<gpio::Input<gpio::Floating>>,
-        out: gpio::gpiob::PB1<gpio::Output<gpio::PushPull>>,
+        #[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+        ladder: Ladder,
+        hard_sync: board::HardSync,
+        i2c2: pac::I2C2,
+        #[cfg(feature = "dual")]
+        hard_sync2: board::HardSync2,
+        iwdg: IndependentWatchdog,
+        led_dma: dma1::C5,
+        out: board::Out,
+        #[cfg(feature = "dual")]
+        out2: board::Out2,
+        params: Params,
+        // Preset last recalled or picked on the menu
+        preset: u8,
+        #[cfg(not(any(
+            feature = "midi-out",
+            feature = "cli",
+            feature = "ii",
+            feature = "cv-out"
+        )))]
+        ring: board::Ring,
+        #[cfg(feature = "segments")]
+        segments: Segments,
+        // `None` if the flash failed, so nothing can be saved
+        storage: Option<Storage<flash::Pages>>,
+        sub1: board::Sub1,
+        sub2: board::Sub2,
+        #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+        sync_out: board::SyncOut,
         tim2: CountDownTimer<pac::TIM2>,
         tim3: CountDownTimer<pac::TIM3>,
+        trigger: board::Trigger,
+        tune_led: board::TuneLed,
+        usart3: pac::USART3,
 
-        #[init([0; AVG_BUF_SIZE])]
-        avg_buf: [u16; AVG_BUF_SIZE],
+        #[init(Acceleration::new(SYSCLK_HZ / SEC_IN_US))]
+        accel: Acceleration,
 
-        #[init(AtomicU32::new(0))]
-        counter: AtomicU32,
+        #[init(Arp::new())]
+        arp: Arp,
 
+        // Arpeggiator interval above the pitch with no note held, in mV
         #[init(AtomicI16::new(0))]
-        fine_tune: AtomicI16,
+        arp_offset: AtomicI16,
 
+        // Tapped arpeggiator step in cycles, 0 until two taps came in
         #[init(AtomicU32::new(0))]
-        period: AtomicU32,
+        arp_period: AtomicU32,
+
+        // Auto-sleep: the DAC holds its level and the measurement slows down
+        #[init(AtomicBool::new(false))]
+        asleep: AtomicBool,
+
+        // Pitch offset written over I2C, in mV
+        #[init(AtomicI16::new(0))]
+        bus_offset: AtomicI16,
+
+        #[init(Button::new())]
+        button: Button,
+
+        #[init(Calibrator::new())]
+        calibrator: Calibrator,
+
+        // TIM3 counts at SYSCLK, so the capture is in cycles too
+        #[init(Capture::new(SYSCLK_HZ / TIM3_FREQ_HZ))]
+        capture: Capture,
+
+        #[init(Clicks::new(
+            (DOUBLE_CLICK_MS / UI_POLL_MS) as u16,
+            (LONG_PRESS_MS / UI_POLL_MS) as u16,
+        ))]
+        clicks: Clicks,
+
+        // Code for the filtered PWM output, set by `publish` and sent by the
+        // measurement task
+        #[init(AtomicU16::new(0))]
+        cv_level: AtomicU16,
+
+        #[init([0; dac::FRAME])]
+        dac_buf: [u8; dac::FRAME],
+
+        // BSRR words for the ladder with `dac-dma`, played in a loop by DMA1
+        // channel 3 on every TIM3 update while `dac_refill` rewrites the half
+        // that has just gone out. Zeros leave the pins alone.
+        #[init([0; 2 * dac::BLOCK])]
+        dac_block: [u32; 2 * dac::BLOCK],
+
+        // DAC code for the modes `publish` sets: amplitude and
+        // MIDI-to-CV. The tick task owns the DAC and sends it.
+        #[init(AtomicU16::new(0))]
+        dac_level: AtomicU16,
+
+        #[init(Quadrature::new(SYSCLK_HZ / SEC_IN_US))]
+        encoder: Quadrature,
+
+        // Last envelope reading, which keeps auto-sleep away while it moves
+        #[init(AtomicU16::new(0))]
+        envelope: AtomicU16,
+
+        #[init(Faults::new())]
+        faults: Faults,
+
+        // Pitch hold: the measurement keeps the CV reading but stops publishing
+        #[init(AtomicBool::new(false))]
+        frozen: AtomicBool,
+
+        #[init(Heartbeats::new())]
+        heartbeats: Heartbeats,
+
+        #[init(Input::new())]
+        input: Input,
+
+        #[init(Glide::new())]
+        glide: Glide,
+
+        #[cfg(feature = "dual")]
+        #[init(Glide::new())]
+        glide2: Glide,
+
+        // Sync lockouts, the shortest until the first pitch scales them
+        #[init(GlitchFilter::new(glitch::MIN_HOLDOFF_US * (SYSCLK_HZ / SEC_IN_US)))]
+        glitch: GlitchFilter,
+
+        #[cfg(feature = "dual")]
+        #[init(GlitchFilter::new(glitch::MIN_HOLDOFF_US * (SYSCLK_HZ / SEC_IN_US)))]
+        glitch2: GlitchFilter,
+
+        #[cfg(feature = "dual")]
+        #[init(Input::new())]
+        input2: Input,
+
+        // Kick drum trigger, fired by sync edges and MIDI notes
+        #[init(kick::Trigger::new())]
+        kick: kick::Trigger,
+
+        #[init([0; ws2812::BUF_LEN])]
+        led_buf: [u16; ws2812::BUF_LEN],
+
+        #[init(Noise::new())]
+        noise: Noise,
+
+        #[init(NoteChange::new())]
+        note_change: NoteChange,
+
+        // Held MIDI notes, for last-note priority and the arpeggiator
+        #[init(NoteStack::new())]
+        notes: NoteStack,
+
+        // Detuned unison oscillator on PA9
+        #[init(Oscillator::new())]
+        osc2: Oscillator,
+
+        // SysEx replies or console output waiting for the USART3 transmitter
+        #[init(Outbox::new())]
+        outbox: Outbox,
+
+        #[init(Override::new())]
+        pitch_override: Override,
+
+        #[init(Playing::new())]
+        playing: Playing,
+
+        #[init(Pll::new())]
+        pll: Pll,
+
+        // Paraphonic voices, on the square, detune and sub-octave pins
+        #[init(Voices::new())]
+        poly: Voices,
+
+        #[init(Profiler::new())]
+        profiler: Profiler,
+
+        #[cfg(feature = "recorder")]
+        #[init(Recorder::new())]
+        recorder: Recorder,
+
+        // Last window of handler timings, for the console
+        #[init(None)]
+        report: Option<Report>,
+
+        #[init(Sub::new())]
+        sub: Sub,
+
+        #[init(Tap::new(TAP_TIMEOUT_MS * (SYSCLK_HZ / 1000)))]
+        tap: Tap,
+
+        #[init(AtomicI16::new(0))]
+        temperature: AtomicI16,
+
+        // A sync edge came in for track and hold to sample on
+        #[init(AtomicBool::new(false))]
+        track_edge: AtomicBool,
+
+        #[init(Voice::new(TIM3_FREQ_HZ))]
+        voice: Voice,
+
+        // Independent second DCO, for 64-pin parts with a spare ADC input
+        #[cfg(feature = "dual")]
+        #[init(Voice::new(TIM3_FREQ_HZ))]
+        voice2: Voice,
+
+        #[init(Watch::new(WATCH_DEFAULT, WATCH_INTERVAL_MS))]
+        watch: Watch,
     }
 
-    #[init]
+    #[init(resources = [dac_block, faults], schedule = [arp_tick, cal_tick, led_tick, profile_tick, replay_drain, segments_tick, telemetry_tick, tune_tick, ui_tick, watch_tick, watchdog_tick], spawn = [snapshot])]
     fn init(cx: init::Context) -> init::LateResources {
+        // Free-running cycle counter used for timestamps
+        let mut core = cx.core;
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+        // Keep HCLK running through WFI, or the cycle counter every task is
+        // scheduled on would stop while idle sleeps
+        cx.device
+            .DBGMCU
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | DBGMCU_CR_DBG_SLEEP) });
+
+        // Reset flags, cleared so the next reset reports its own
+        let watchdog_reset = cx.device.RCC.csr.read().bits() & RCC_CSR_IWDGRSTF != 0;
+        cx.device
+            .RCC
+            .csr
+            .modify(|r, w| unsafe { w.bits(r.bits() | RCC_CSR_RMVF) });
+
         let mut flash = cx.device.FLASH.constrain();
         let mut rcc = cx.device.RCC.constrain();
         let mut afio = cx.device.AFIO.constrain(&mut rcc.apb2);
 
         // Init clocks
-        let clocks = rcc
-            .cfgr
-            .adcclk(10.mhz())
-            .sysclk(30.mhz())
-            .pclk1(15.mhz())
+        let cfgr = match board::HSE_HZ {
+            Some(hz) => rcc.cfgr.use_hse(hz.hz()),
+            None => rcc.cfgr,
+        };
+        let clocks = cfgr
+            .adcclk(board::ADCCLK_HZ.hz())
+            .sysclk(SYSCLK_HZ.hz())
+            .pclk1(board::PCLK1_HZ.hz())
             .freeze(&mut flash.acr);
+        // The board file is checked against the clock tree at build time.
+        // Should the HAL still differ, the timers and the USART follow it and
+        // only what counts cycles against the constants runs off
+        if clocks.sysclk().0 != SYSCLK_HZ
+            || clocks.pclk1().0 != board::PCLK1_HZ
+            || clocks.adcclk().0 != board::ADCCLK_HZ
+        {
+            defmt::error!(
+                "clock tree differs from the board: sysclk={} pclk1={} adcclk={}",
+                clocks.sysclk().0,
+                clocks.pclk1().0,
+                clocks.adcclk().0
+            );
+            cx.resources.faults.raise(Fault::Clock);
+        }
+
+        // Init brown-out warning, settled long before the storage mount
+        supply::enable();
 
         // Init ADC
         let mut adc1 = adc::Adc::adc1(cx.device.ADC1, &mut rcc.apb2, clocks);
         adc1.set_sample_time(adc::SampleTime::T_239);
-        let mut gpiob = cx.device.GPIOB.split(&mut rcc.apb2);
-        let ch0 = gpiob.pb0.into_analog(&mut gpiob.crl);
+
+        // Init the GPIOB and GPIOC pins for the board and features
+        let pins = board::split(
+            cx.device.GPIOB.split(&mut rcc.apb2),
+            cx.device.GPIOC.split(&mut rcc.apb2),
+        );
+        let ch0 = pins.cv;
 
         // Init timers
         let mut tim2 = Timer::tim2(cx.device.TIM2, &clocks, &mut rcc.apb1)
             .start_count_down((TIM3_FREQ_HZ / 2).hz());
         tim2.listen(Event::Update);
+        // CV output: TIM2_CH3 in PWM mode 1 at the measurement rate, the
+        // compare preloaded so each new value starts a whole period
+        #[cfg(feature = "cv-out")]
+        {
+            afio.mapr
+                .modify_mapr(|_, w| unsafe { w.tim2_remap().bits(board::TIM2_REMAP) });
+            let tim2_regs = unsafe { &*pac::TIM2::ptr() };
+            tim2_regs
+                .ccmr2_output()
+                .write(|w| unsafe { w.bits((0b110 << 4) | (1 << 3)) });
+            tim2_regs.ccer.write(|w| unsafe { w.bits(1 << 8) });
+        }
 
         let mut tim3 =
             Timer::tim3(cx.device.TIM3, &clocks, &mut rcc.apb1).start_count_down(TIM3_FREQ_HZ.hz());
         tim3.listen(Event::Update);
 
-        // Init out pin
-        let out = gpiob.pb1.into_push_pull_output(&mut gpiob.crl);
+        // Out pin and the sub-octave outputs
+        let mut out = pins.out;
+        let sub1 = pins.sub1;
+        let sub2 = pins.sub2;
+
+        // XOR ring-mod output and the sync output for chaining
+        #[cfg(not(any(
+            feature = "midi-out",
+            feature = "cli",
+            feature = "ii",
+            feature = "cv-out"
+        )))]
+        let ring = pins.ring;
+        #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+        let sync_out = pins.sync_out;
 
-        // Init DAC port
+        // Init MIDI input or the console on USART3. The output for SysEx
+        // replies or the console replaces the ring mod.
+        let usart3 = cx.device.USART3;
+        #[cfg(any(feature = "midi", feature = "cli"))]
+        {
+            pac::USART3::enable(&mut rcc.apb1);
+            let baud = if cfg!(feature = "cli") {
+                cli::BAUD
+            } else {
+                midi::BAUD
+            };
+            usart3
+                .brr
+                .write(|w| unsafe { w.bits(clocks.pclk1().0 / baud) });
+            // UE, RXNEIE and RE, plus TE for the output
+            let te = if cfg!(any(feature = "midi-out", feature = "cli")) {
+                1 << 3
+            } else {
+                0
+            };
+            usart3
+                .cr1
+                .write(|w| unsafe { w.bits((1 << 13) | (1 << 5) | (1 << 2) | te) });
+        }
+        // A firmware update asked for on the console takes over here, before
+        // any output or interrupt is started
+        if cfg!(feature = "cli") && update::requested() {
+            update::run(&usart3);
+        }
+
+        // Init I2C follower on I2C2, in place of the ring mod and sync output
+        let i2c2 = cx.device.I2C2;
+        #[cfg(feature = "ii")]
+        {
+            pac::I2C2::enable(&mut rcc.apb1);
+            // Event, buffer and error interrupts, FREQ in MHz
+            let freq = clocks.pclk1().0 / 1_000_000;
+            i2c2.cr2
+                .write(|w| unsafe { w.bits((1 << 10) | (1 << 9) | (1 << 8) | freq) });
+            // 7-bit address, bit 14 must be kept set
+            i2c2.oar1
+                .write(|w| unsafe { w.bits((1 << 14) | (ii::ADDRESS as u32) << 1) });
+            // PE, then ACK, which only sticks once the peripheral is on
+            i2c2.cr1.write(|w| unsafe { w.bits(1) });
+            i2c2.cr1.write(|w| unsafe { w.bits((1 << 10) | 1) });
+        }
+
+        // Note-change trigger output
+        let trigger = pins.trigger;
+
+        // Init DAC port: the R-2R ladder, or the SPI DAC with CS high
         let gpioa = cx.device.GPIOA;
         pac::GPIOA::enable(&mut rcc.apb2);
-        gpioa.crl.write(|w| unsafe { w.bits(0x33333333) });
+        #[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+        let mut ladder = Ladder::new(&gpioa);
+        #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+        {
+            gpioa
+                .crl
+                .write(|w| unsafe { w.bits(board::GPIOA_CRL_SPI_DAC) });
+            gpioa.bsrr.write(|w| unsafe { w.bits(1 << board::SPI_CS) });
+        }
 
         // Init Hard Sync pin
-        let mut hard_sync = gpiob.pb5.into_floating_input(&mut gpiob.crl);
+        let mut hard_sync = pins.hard_sync;
         hard_sync.make_interrupt_source(&mut afio);
+        // The TIM3 remap also puts CH2 on the sync pin for measuring the
+        // frequency: input on TI2 with an 8-sample filter, rising edges
+        afio.mapr
+            .modify_mapr(|_, w| unsafe { w.tim3_remap().bits(board::TIM3_REMAP) });
+        let tim3_regs = unsafe { &*pac::TIM3::ptr() };
+        tim3_regs
+            .ccmr1_input()
+            .write(|w| unsafe { w.bits((0b0011 << 12) | (0b01 << 8)) });
+        tim3_regs
+            .ccer
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 4)) });
+        // The pitch CV conversions start on a channel 1 compare placed so
+        // they sample right up to a tick: PWM mode 2 raises OC1REF on the
+        // compare, and OC1REF is TRGO. CC1E stays off, so no pin is driven.
+        #[cfg(feature = "adc-sync")]
+        {
+            let period = tim3_regs.arr.read().bits() + 1;
+            let count = sampling::trigger_count(period, clocks.pclk1_tim().0, board::ADCCLK_HZ);
+            tim3_regs.ccr1.write(|w| unsafe { w.bits(count) });
+            tim3_regs
+                .ccmr1_output()
+                .modify(|r, w| unsafe { w.bits(r.bits() | (0b111 << 4)) });
+            // MMS
+            tim3_regs
+                .cr2
+                .modify(|r, w| unsafe { w.bits(r.bits() | (0b100 << 4)) });
+        }
         hard_sync.trigger_on_edge(&cx.device.EXTI, gpio::Edge::RISING);
         hard_sync.enable_interrupt(&cx.device.EXTI);
 
-        // Init Encoder
-        // Into pull up input
-        gpioa.crh.write(|w| unsafe { w.bits(0x8800) });
-        gpioa.bsrr.write(|w| unsafe { w.bits(1 << 10) });
-        gpioa.bsrr.write(|w| unsafe { w.bits(1 << 11) });
+        // Init display
+        let i2c = BlockingI2c::i2c1(
+            cx.device.I2C1,
+            (pins.scl, pins.sda),
+            &mut afio.mapr,
+            Mode::Fast {
+                frequency: 400_000.hz(),
+                duty_cycle: DutyCycle::Ratio2to1,
+            },
+            clocks,
+            &mut rcc.apb1,
+            1000,
+            10,
+            1000,
+            1000,
+        );
+        let mut display = Ssd1306::new(i2c);
+        // Runs without a display fitted
+        display.init().ok();
+
+        // Init 7-segment readout for builds without the OLED
+        #[cfg(feature = "segments")]
+        let segments = {
+            let (data, clock, latch) = pins.segments;
+            Hc595::new(data, clock, latch)
+        };
+
+        // Tuning LED and MIDI gate output
+        let mut tune_led = pins.tune_led;
+        let gate = pins.gate;
+
+        // Init second voice: its own CV, output and hard sync
+        #[cfg(feature = "dual")]
+        let ch10 = pins.cv2;
+        #[cfg(feature = "dual")]
+        let out2 = pins.out2;
+        #[cfg(feature = "dual")]
+        let hard_sync2 = {
+            let mut pin = pins.hard_sync2;
+            pin.make_interrupt_source(&mut afio);
+            pin.trigger_on_edge(&cx.device.EXTI, gpio::Edge::RISING);
+            pin.enable_interrupt(&cx.device.EXTI);
+            pin
+        };
+
+        // Pulse width CV
+        #[cfg(feature = "pwm-cv")]
+        let ch11 = pins.pw_cv;
+
+        // Envelope CV for the amplitude DAC
+        #[cfg(feature = "env-cv")]
+        let ch12 = pins.env_cv;
+
+        // Transpose CV, rounded to semitones
+        #[cfg(feature = "transpose-cv")]
+        let ch13 = pins.transpose_cv;
+
+        // Modulation CVs, routed by the matrix
+        #[cfg(feature = "mod-cv")]
+        let (ch14, ch15) = (pins.mod_cv1, pins.mod_cv2);
+
+        // Encoder button, held at power-up it reboots into the bootloader
+        // once let go, or asks for a factory reset if held on for long
+        // enough. The tuning LED stays lit while it is held.
+        let button_pin = pins.button;
+        // The pull-up needs a moment to charge the pin
+        cortex_m::asm::delay(SYSCLK_HZ / 1000);
+        let mut wipe = false;
+        if button_pin.is_low().unwrap_or(false) {
+            set_level(&mut tune_led, !board::TUNE_LED_ACTIVE_LOW);
+            let mut held_ms = 0;
+            while button_pin.is_low().unwrap_or(false) && held_ms < FACTORY_RESET_HOLD_MS {
+                cortex_m::asm::delay(10 * (SYSCLK_HZ / 1000));
+                held_ms += 10;
+            }
+            set_level(&mut tune_led, board::TUNE_LED_ACTIVE_LOW);
+            if held_ms < FACTORY_RESET_HOLD_MS {
+                bootloader::enter();
+            }
+            wipe = true;
+        }
+
+        // Init Encoder, status LED, detuned oscillator and scope trigger pins,
+        // with the encoder pull-ups
+        let encoder_pins = 0b11 << board::ENCODER_A;
+        gpioa.crh.write(|w| unsafe { w.bits(board::GPIOA_CRH) });
+        gpioa.bsrr.write(|w| unsafe { w.bits(encoder_pins) });
 
-        // Make interrupt source
+        // Make interrupt source: both encoder EXTI lines from port A
+        let exticr_shift = (board::ENCODER_A % 4) * 4;
         afio.exticr3
             .exticr3()
-            .modify(|r, w| unsafe { w.bits((r.bits() & !(0xf << 10)) | (0 << 10)) });
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(0xff << exticr_shift)) });
 
-        // Trigger on Falling edge
+        // Trigger on both edges of both phases
         cx.device
             .EXTI
             .ftsr
-            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 10)) });
+            .modify(|r, w| unsafe { w.bits(r.bits() | encoder_pins) });
         cx.device
             .EXTI
             .rtsr
-            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << 10)) });
+            .modify(|r, w| unsafe { w.bits(r.bits() | encoder_pins) });
 
         // Enable EXTI interrupt
         cx.device
             .EXTI
             .imr
-            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 10)) });
+            .modify(|r, w| unsafe { w.bits(r.bits() | encoder_pins) });
         let exti = cx.device.EXTI;
 
+        // Init status LED: TIM1_CH1 PWM, the duty cycle of every bit is written
+        // to CCR1 by DMA1 channel 5 on each update event.
+        pac::TIM1::enable(&mut rcc.apb2);
+        let tim1 = cx.device.TIM1;
+        tim1.arr
+            .write(|w| unsafe { w.bits(WS2812_PERIOD as u32 - 1) });
+        tim1.ccr1.write(|w| unsafe { w.bits(0) });
+        // PWM mode 1 with preload, so each value lasts exactly one period
+        tim1.ccmr1_output()
+            .write(|w| unsafe { w.bits((0b110 << 4) | (1 << 3)) });
+        tim1.ccer.write(|w| unsafe { w.bits(1) });
+        tim1.bdtr.write(|w| unsafe { w.bits(1 << 15) });
+        tim1.dier.write(|w| unsafe { w.bits(1 << 8) });
+        tim1.cr1.write(|w| unsafe { w.bits((1 << 7) | 1) });
+
+        let dma1 = cx.device.DMA1.split(&mut rcc.ahb);
+        let mut led_dma = dma1.5;
+        led_dma.set_peripheral_address(&tim1.ccr1 as *const _ as u32, false);
+        // Memory to peripheral, 16 bit on both sides
+        led_dma
+            .ch()
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 4) | (0b01 << 8) | (0b01 << 10)) });
+
+        // Init external DAC on SPI1 at the fastest pclk2 divider the chip
+        // takes, fed by DMA1 channel 3 from the tick task
+        #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+        let dac_dma = {
+            pac::SPI1::enable(&mut rcc.apb2);
+            let spi1 = cx.device.SPI1;
+            // SSM and SSI for a software CS, SPE, BR and MSTR
+            let cpha = if SpiDac::CPHA { 1 } else { 0 };
+            let br = dac::spi_baud_bits(clocks.pclk2().0, SpiDac::MAX_CLOCK_HZ);
+            spi1.cr1.write(|w| unsafe {
+                w.bits((1 << 9) | (1 << 8) | (1 << 6) | (br << 3) | (1 << 2) | cpha)
+            });
+            if let Some(setup) = SpiDac::SETUP {
+                gpioa
+                    .bsrr
+                    .write(|w| unsafe { w.bits(1 << (board::SPI_CS + 16)) });
+                for &b in setup.iter() {
+                    while spi1.sr.read().txe().bit_is_clear() {}
+                    spi1.dr.write(|w| unsafe { w.bits(b as u32) });
+                }
+                while spi1.sr.read().bsy().bit_is_set() {}
+                gpioa.bsrr.write(|w| unsafe { w.bits(1 << board::SPI_CS) });
+            }
+            // TXDMAEN
+            spi1.cr2.write(|w| unsafe { w.bits(1 << 1) });
+
+            let mut dac_dma = dma1.3;
+            dac_dma.set_peripheral_address(&spi1.dr as *const _ as u32, false);
+            // Memory to peripheral, 8 bit on both sides
+            dac_dma
+                .ch()
+                .cr
+                .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 4)) });
+            dac_dma
+        };
+
+        // Stream the ladder: DMA1 channel 3 takes the TIM3 update request, so
+        // every tick writes the next word of `dac_block` to the port, with an
+        // interrupt at each half for `dac_refill`
+        #[cfg(feature = "dac-dma")]
+        {
+            let block = cx.resources.dac_block;
+            let mut dac_dma = dma1.3;
+            dac_dma.set_peripheral_address(&gpioa.bsrr as *const _ as u32, false);
+            dac_dma.set_memory_address(block.as_ptr() as u32, true);
+            dac_dma.set_transfer_length(block.len());
+            // Memory to peripheral, circular, 32 bit on both sides, half and
+            // full transfer interrupts, at high priority so the LED's
+            // transfers can't hold a sample up
+            dac_dma.ch().cr.modify(|r, w| unsafe {
+                w.bits(
+                    r.bits()
+                        | (0b10 << 12)
+                        | (0b10 << 10)
+                        | (0b10 << 8)
+                        | (1 << 5)
+                        | (1 << 4)
+                        | (1 << 2)
+                        | (1 << 1),
+                )
+            });
+            dac_dma.start();
+            // UDE
+            tim3_regs
+                .dier
+                .modify(|r, w| unsafe { w.bits(r.bits() | (1 << 8)) });
+        }
+
+        if watchdog_reset {
+            defmt::warn!("reset by the watchdog");
+        }
+        if let Some(record) = crash::last() {
+            match record.crash() {
+                crash::Crash::Panic { file, line } => {
+                    defmt::warn!(
+                        "last crash: panic at {}:{} in {}",
+                        file,
+                        line,
+                        record.task()
+                    )
+                }
+                crash::Crash::HardFault { pc, lr } => defmt::warn!(
+                    "last crash: hardfault pc={:x} lr={:x} in {}",
+                    pc,
+                    lr,
+                    record.task()
+                ),
+            }
+        }
+
+        // Init settings storage, formatting it on first boot or after a
+        // factory reset, and load the settings saved there
+        if wipe {
+            factory_reset(&mut tune_led);
+            // Let go first, so the menu doesn't take the hold for a press
+            while button_pin.is_low().unwrap_or(false) {}
+        }
+        let storage = Storage::mount(flash::Pages).ok();
+        let params = Params::new();
+        let loaded = storage.as_ref().map(settings::load);
+        match loaded {
+            Some(Ok(saved)) => saved.apply(&params),
+            Some(Err(settings::Error::Missing)) => defmt::info!("no settings saved"),
+            Some(Err(settings::Error::Corrupt)) => defmt::warn!("settings corrupt, using defaults"),
+            Some(Err(settings::Error::OldLayout)) => {
+                defmt::warn!("settings from another layout, using defaults")
+            }
+            None => defmt::warn!("storage mount failed"),
+        }
+        let amp_curve = Curve::new();
+        match storage.as_ref().map(|s| amp::load(s, &amp_curve)) {
+            Some(Err(settings::Error::Corrupt)) | Some(Err(settings::Error::OldLayout)) => {
+                defmt::warn!("amplitude curve unreadable, using the default")
+            }
+            _ => {}
+        }
+        let calibration = Calibration::new();
+        match storage.as_ref().map(|s| calibrate::load(s, &calibration)) {
+            Some(Err(settings::Error::Corrupt)) | Some(Err(settings::Error::OldLayout)) => {
+                defmt::warn!("calibration unreadable, leaving the CV uncorrected")
+            }
+            _ => {}
+        }
+        params.power_up();
+        // The preset page comes back as it was left, without recalling it
+        let preset = params.get(Param::Preset) as u8;
+
+        // Power-on self-test
+        let mut failures = Failures::new();
+        // The first conversion after turning VREFINT on is thrown away
+        adc1.read_vref();
+        failures.record(Check::Vref, post::vref_ok(adc1.read_vref()));
+        // The R-2R ladder reads back on every pin, an SPI DAC only on CS
+        #[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+        let dac_ok = post::walk(
+            8,
+            |pattern| ladder.write(dac::from_u8(pattern as u8)),
+            || {
+                cortex_m::asm::delay(POST_SETTLE_CYCLES);
+                ladder.read() as u32
+            },
+        );
+        #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+        let dac_ok = post::walk(
+            1,
+            |pattern| {
+                let bit = if pattern == 0 { 16 } else { 0 };
+                gpioa
+                    .bsrr
+                    .write(|w| unsafe { w.bits(1 << (board::SPI_CS + bit)) });
+            },
+            || {
+                cortex_m::asm::delay(POST_SETTLE_CYCLES);
+                gpioa.idr.read().bits() >> board::SPI_CS
+            },
+        );
+        failures.record(Check::Dac, dac_ok);
+        // Left idle: CS high for an SPI DAC, 0 V for the ladder
+        #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+        gpioa.bsrr.write(|w| unsafe { w.bits(1 << board::SPI_CS) });
+        let gpiob_regs = unsafe { &*pac::GPIOB::ptr() };
+        failures.record(
+            Check::Output,
+            post::walk(
+                1,
+                |pattern| {
+                    if pattern == 0 {
+                        out.set_low().ok();
+                    } else {
+                        out.set_high().ok();
+                    }
+                },
+                || {
+                    cortex_m::asm::delay(POST_SETTLE_CYCLES);
+                    gpiob_regs.idr.read().bits() >> board::OUT
+                },
+            ),
+        );
+        failures.record(
+            Check::Settings,
+            !matches!(loaded, None | Some(Err(settings::Error::Corrupt))),
+        );
+        if !failures.is_empty() {
+            for check in failures.iter() {
+                defmt::error!("self-test failed: {}", check.name());
+            }
+            cx.resources.faults.raise(Fault::SelfTest);
+            blink_codes(&mut tune_led, failures);
+        }
+
+        // Init watchdog last, once the storage mount and its erases are done.
+        // It stops while a debugger has the core halted.
+        let mut iwdg = IndependentWatchdog::new(cx.device.IWDG);
+        iwdg.stop_on_debug(&cx.device.DBGMCU, true);
+        // QEMU has no cycle counter to schedule the feeding on
+        if !cfg!(feature = "qemu") {
+            iwdg.start(WATCHDOG_TIMEOUT_MS.ms());
+        }
+
+        cx.spawn.snapshot().ok();
+        cx.schedule.arp_tick(cx.start).ok();
+        cx.schedule.cal_tick(cx.start).ok();
+        cx.schedule.led_tick(cx.start).ok();
+        #[cfg(feature = "profile")]
+        cx.schedule.profile_tick(cx.start).ok();
+        #[cfg(feature = "segments")]
+        cx.schedule.segments_tick(cx.start).ok();
+        #[cfg(feature = "telemetry")]
+        cx.schedule.telemetry_tick(cx.start).ok();
+        cx.schedule.tune_tick(cx.start).ok();
+        cx.schedule.ui_tick(cx.start).ok();
+        cx.schedule.watch_tick(cx.start).ok();
+        cx.schedule.watchdog_tick(cx.start).ok();
+        #[cfg(feature = "recorder")]
+        cx.schedule.replay_drain(cx.start).ok();
+
         init::LateResources {
             adc1,
+            amp_curve,
+            button_pin,
+            calibration,
             ch0,
+            #[cfg(feature = "dual")]
+            ch10,
+            #[cfg(feature = "pwm-cv")]
+            ch11,
+            #[cfg(feature = "env-cv")]
+            ch12,
+            #[cfg(feature = "transpose-cv")]
+            ch13,
+            #[cfg(feature = "mod-cv")]
+            ch14,
+            #[cfg(feature = "mod-cv")]
+            ch15,
+            clocks,
+            #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+            dac_dma,
+            display,
             exti,
+            gate,
             gpioa,
+            #[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+            ladder,
             hard_sync,
+            #[cfg(feature = "dual")]
+            hard_sync2,
+            i2c2,
+            iwdg,
+            led_dma,
             out,
+            #[cfg(feature = "dual")]
+            out2,
+            params,
+            preset,
+            #[cfg(not(any(
+                feature = "midi-out",
+                feature = "cli",
+                feature = "ii",
+                feature = "cv-out"
+            )))]
+            ring,
+            #[cfg(feature = "segments")]
+            segments,
+            storage,
+            sub1,
+            sub2,
+            #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+            sync_out,
             tim2,
             tim3,
+            trigger,
+            tune_led,
+            usart3,
+        }
+    }
+
+    #[idle(resources = [display, &frozen, &heartbeats, &params, &pitch_override, &voice])]
+    fn idle(cx: idle::Context) -> ! {
+        #[cfg(feature = "qemu")]
+        qemu::run(&cx.resources.voice.osc);
+
+        let mut next = Instant::now();
+
+        let mut runner = Runner::new();
+        let mut burnin = Burnin::new(
+            cx.resources.pitch_override,
+            BURNIN_DWELL_MS * (SYSCLK_HZ / 1000),
+            BURNIN_SWEEPS,
+        );
+        let mut test = TestSignal::new(
+            cx.resources.pitch_override,
+            cx.resources.params,
+            TEST_LOW_MV,
+            TEST_HIGH_MV,
+            TEST_DURATION_MS * (SYSCLK_HZ / 1000),
+        );
+        // Burn-in firmware has no use for the generator
+        if cfg!(feature = "burnin") {
+            runner.start(&mut burnin);
+        } else {
+            runner.start(&mut test);
+        }
+
+        loop {
+            cx.resources.heartbeats.beat(Beat::Idle);
+            runner.poll(DWT::get_cycle_count());
+
+            let now = Instant::now();
+            if now < next {
+                // The tick wakes it again within 5 µs at the latest
+                cortex_m::asm::wfi();
+                continue;
+            }
+            next = now + (DISPLAY_INTERVAL_MS * (SYSCLK_HZ / 1000)).cycles();
+
+            let hz = cx.resources.voice.hz();
+            let frozen = cx.resources.frozen.load(Ordering::Relaxed);
+            draw_status(cx.resources.display, hz, frozen, cx.resources.params);
         }
     }
 
-    #[task(binds = EXTI15_10, priority = 1, resources = [exti, &fine_tune, gpioa])]
+    #[task(binds = EXTI15_10, priority = 1, resources = [accel, encoder, exti, gpioa, &params, &profiler, recorder])]
     fn encoder_handler(mut cx: encoder_handler::Context) {
-        let bits = cx.resources.gpioa.lock(|gpioa| gpioa.idr.read().bits());
+        let _span = span(cx.resources.profiler, profile::Task::Encoder);
 
-        let state = (bits & (1 << 11)) == 0;
+        // Clear first so an edge arriving while we sample re-triggers us.
+        cx.resources
+            .exti
+            .pr
+            .write(|w| unsafe { w.bits(0b11 << board::ENCODER_A) });
 
-        match state {
-            true => cx
-                .resources
-                .fine_tune
-                .fetch_add(FINE_TUNE_STEP, Ordering::Relaxed),
-            false => cx
-                .resources
-                .fine_tune
-                .fetch_add(-FINE_TUNE_STEP, Ordering::Relaxed),
-        };
+        let bits = cx.resources.gpioa.idr.read().bits();
+        let a = (bits & (1 << board::ENCODER_A)) != 0;
+        let b = (bits & (1 << (board::ENCODER_A + 1))) != 0;
 
-        cx.resources.exti.pr.write(|w| unsafe { w.bits(1 << 10) });
+        let now = DWT::get_cycle_count();
+        let detents = cx.resources.encoder.update(a, b, now);
+        if detents != 0 {
+            let params = cx.resources.params;
+            let page = params.page();
+            let steps = if page.info().accelerate {
+                cx.resources.accel.steps(detents, now)
+            } else {
+                detents as i16
+            };
+            params.nudge(page, steps as i32);
+
+            #[cfg(feature = "recorder")]
+            cx.resources
+                .recorder
+                .lock(|r| r.record(now, Kind::EncoderSteps, steps as i32));
+        }
     }
 
-    #[task(binds = EXTI9_5, priority = 3, resources = [&counter, hard_sync])]
+    #[task(binds = EXTI9_5, priority = 3, resources = [arp, &arp_offset, &arp_period, glitch, glitch2, hard_sync, hard_sync2, &kick, notes, &osc2, &params, &playing, &profiler, recorder, tap, &track_edge, &voice, &voice2])]
     fn hard_sync(cx: hard_sync::Context) {
-        cx.resources.counter.store(0, Ordering::Relaxed);
+        static mut CLOCK_EDGES: u32 = 0;
+
+        let _span = span(cx.resources.profiler, profile::Task::HardSync);
+
+        let params = cx.resources.params;
+        let now = DWT::get_cycle_count();
+
+        // Both voices share the EXTI9_5 vector
+        #[cfg(feature = "dual")]
+        {
+            let sync2 = cx.resources.hard_sync2;
+            if sync2.check_interrupt() {
+                sync2.clear_interrupt_pending_bit();
+                let settled = sync_settled(sync2.is_high().unwrap_or(false), params);
+                if cx.resources.glitch2.accept(now, settled) {
+                    sync(&cx.resources.voice2.osc, params);
+                }
+            }
+            if !cx.resources.hard_sync.check_interrupt() {
+                return;
+            }
+        }
+
         cx.resources.hard_sync.clear_interrupt_pending_bit();
+        let settled = sync_settled(cx.resources.hard_sync.is_high().unwrap_or(false), params);
+        if !cx.resources.glitch.accept(now, settled) {
+            return;
+        }
+        defmt::trace!("sync");
+        cx.resources.kick.fire();
+        cx.resources.track_edge.store(true, Ordering::Relaxed);
+
+        // Tap tempo: the sync edges also set the LFO rate and the
+        // arpeggiator's clock, from the same taps
+        let arp_on = params.get(Param::Arp) != ARP_OFF;
+        let lfo_tap = params.get(Param::Rate) == RATE_TAP && params.get(Param::Range) == RANGE_LFO;
+        let arp_tap = arp_on && params.get(Param::ArpClock) == ARP_CLOCK_TAP;
+        if lfo_tap || arp_tap {
+            if let Some(interval) = cx.resources.tap.tap(now) {
+                if lfo_tap {
+                    let hz = SYSCLK_HZ as f32 / interval as f32;
+                    cx.resources.voice.set_rate(Some(hz));
+                }
+                if arp_tap {
+                    cx.resources.arp_period.store(interval, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if params.get(Param::Tuner) == TUNER_ON {
+            // The tuner reference runs free of the edges
+        } else if arp_on && params.get(Param::ArpClock) == ARP_CLOCK_SYNC {
+            // The edges step the arpeggiator instead of syncing the oscillator
+            arp_step(
+                cx.resources.arp,
+                cx.resources.notes,
+                params,
+                cx.resources.playing,
+                cx.resources.arp_offset,
+            );
+        } else if let Some(ratio) = clock_ratio(params) {
+            // Clock utility: the frequency follows the input times the ratio,
+            // restarting on every edge, or every Nth when dividing
+            *CLOCK_EDGES = (*CLOCK_EDGES + 1) % ratio.den;
+            if *CLOCK_EDGES == 0 {
+                cx.resources.voice.osc.reset();
+                cx.resources.osc2.reset();
+            }
+        } else {
+            sync(&cx.resources.voice.osc, params);
+            sync(cx.resources.osc2, params);
+        }
+        custom::HOOKS.on_sync();
+        #[cfg(feature = "recorder")]
+        cx.resources.recorder.record(now, Kind::SyncEdge, 0);
     }
 
-    #[task(binds = TIM3, priority = 4, resources = [&counter, out, tim3, &period])]
+    #[task(binds = TIM3, priority = 4, resources = [&asleep, &capture, dac_buf, dac_dma, &dac_level, &heartbeats, ladder, noise, &osc2, out, out2, &params, &pll, &poly, &profiler, ring, sub, sub1, sub2, sync_out, tim3, &voice, &voice2])]
     fn tick(cx: tick::Context) {
-        let c = cx.resources.counter.load(Ordering::Relaxed);
+        static mut SYNC_OUT: u8 = 0;
+        static mut SCOPE: u8 = 0;
+        #[cfg(not(feature = "dac-dma"))]
+        static mut CHORD: Chord = Chord::new();
+
+        let _span = span(cx.resources.profiler, profile::Task::Tick);
+        cx.resources.heartbeats.beat(Beat::Tick);
 
-        if c == 0 {
-            cx.resources.out.set_low().ok();
-        } else if c >= cx.resources.period.load(Ordering::Relaxed) {
-            cx.resources.out.toggle().ok();
-            cx.resources.counter.store(0, Ordering::Relaxed);
+        // Sync input capture, polled since TIM3 raising a second interrupt
+        // would tick the oscillators twice
+        let capture = cx.resources.capture;
+        let tim3 = unsafe { &*pac::TIM3::ptr() };
+        if tim3.sr.read().cc2if().bit_is_set() {
+            // Reading CCR2 clears the flag, the counter is read after it
+            let ccr = tim3.ccr2.read().bits() as u16;
+            let cnt = tim3.cnt.read().bits() as u16;
+            capture.captured(ccr, cnt);
+            cx.resources.pll.edge(cx.resources.voice.osc.phase());
         }
+        capture.update();
 
-        cx.resources.counter.fetch_add(1, Ordering::Relaxed);
+        let osc = &cx.resources.voice.osc;
+        let edge = osc.tick();
+        let params = cx.resources.params;
+        let noise = cx.resources.noise;
+        let paraphonic = params.get(Param::Poly) == POLY_ON;
+        match edge {
+            _ if paraphonic => {}
+            Edge::Reset => set_level(cx.resources.out, osc.is_high()),
+            // 1-bit noise takes a new value on every edge, so it follows the pitch
+            Edge::Toggle if params.get(Param::Square) == SQUARE_NOISE => {
+                set_level(cx.resources.out, noise.bit())
+            }
+            Edge::Toggle => set_level(cx.resources.out, osc.is_high()),
+            Edge::None => {}
+        }
+
+        // The paraphonic voices take over the pins of the square, the
+        // detuned oscillator and the sub-octaves, which stop meanwhile
+        let voices = cx.resources.poly;
+        if paraphonic {
+            let [v1, v2, v3, v4] = &voices.oscs;
+            if v1.tick() != Edge::None {
+                set_level(cx.resources.out, v1.is_high());
+            }
+            if v2.tick() != Edge::None {
+                set_detune(v2.is_high());
+            }
+            if v3.tick() != Edge::None {
+                set_level(cx.resources.sub1, v3.is_high());
+            }
+            if v4.tick() != Edge::None {
+                set_level(cx.resources.sub2, v4.is_high());
+            }
+        }
+        let wrapped = edge == Edge::Reset || (edge == Edge::Toggle && !osc.is_high());
+        if wrapped {
+            custom::HOOKS.on_cycle_wrap();
+        }
+        // Sync pulse for slaving further DCOs, PB11 is the MIDI or console
+        // input instead with the `midi` and `cli` features
+        #[cfg(not(any(feature = "midi", feature = "cli", feature = "ii")))]
+        if wrapped {
+            *SYNC_OUT = SYNC_OUT_TICKS;
+            cx.resources.sync_out.set_high().ok();
+        } else if *SYNC_OUT > 0 {
+            *SYNC_OUT -= 1;
+            if *SYNC_OUT == 0 {
+                cx.resources.sync_out.set_low().ok();
+            }
+        }
+
+        // Scope trigger, only at the true start of the waveform
+        let gpioa = unsafe { &*pac::GPIOA::ptr() };
+        if osc.at_zero() {
+            *SCOPE = SCOPE_TICKS;
+            // BSRR writes are atomic, so this doesn't have to lock the port
+            gpioa.bsrr.write(|w| unsafe { w.bits(1 << board::SCOPE) });
+        } else if *SCOPE > 0 {
+            *SCOPE -= 1;
+            if *SCOPE == 0 {
+                gpioa
+                    .bsrr
+                    .write(|w| unsafe { w.bits(1 << (board::SCOPE + 16)) });
+            }
+        }
+
+        // Audio on the DAC, one sample per tick, unless `dac_refill` streams
+        // it in blocks instead
+        #[cfg(not(feature = "dac-dma"))]
+        {
+            let sample = dac_sample(params, CHORD, noise, voices, 0, osc.phase(), osc.step());
+            // Asleep, a level is left on the DAC rather than sent again every
+            // tick
+            let code = match sample {
+                Some(s) => Some(dac::from_u8(s)),
+                None if cx.resources.asleep.load(Ordering::Relaxed) => None,
+                None => Some(cx.resources.dac_level.load(Ordering::Relaxed)),
+            };
+            #[cfg(not(any(feature = "mcp4922", feature = "dac8568")))]
+            if let Some(code) = code {
+                cx.resources.ladder.write(code);
+            }
+            // Raising CS latches the frame sent on the last tick, long
+            // finished, so the output updates on the tick with a tick of
+            // latency and no jitter
+            #[cfg(any(feature = "mcp4922", feature = "dac8568"))]
+            {
+                gpioa.bsrr.write(|w| unsafe { w.bits(1 << board::SPI_CS) });
+                if let Some(code) = code {
+                    let (dma, buf) = (cx.resources.dac_dma, cx.resources.dac_buf);
+                    dma.stop();
+                    *buf = SpiDac::frame(code);
+                    dma.set_memory_address(buf.as_ptr() as u32, true);
+                    dma.set_transfer_length(SpiDac::LEN);
+                    gpioa
+                        .bsrr
+                        .write(|w| unsafe { w.bits(1 << (board::SPI_CS + 16)) });
+                    dma.start();
+                }
+            }
+        }
+
+        if let Some((sub1, sub2)) = cx.resources.sub.update(edge).filter(|_| !paraphonic) {
+            set_level(cx.resources.sub1, sub1);
+            set_level(cx.resources.sub2, sub2);
+        }
+
+        // Digital ring mod: the sync input's level XOR the square
+        #[cfg(not(any(
+            feature = "midi-out",
+            feature = "cli",
+            feature = "ii",
+            feature = "cv-out"
+        )))]
+        let ring = if params.get(Param::Ring) == RING_XOR {
+            sync_input_high() != osc.is_high()
+        } else {
+            false
+        };
+        #[cfg(not(any(
+            feature = "midi-out",
+            feature = "cli",
+            feature = "ii",
+            feature = "cv-out"
+        )))]
+        set_level(cx.resources.ring, ring);
+
+        let osc2 = cx.resources.osc2;
+        if !paraphonic && osc2.tick() != Edge::None {
+            set_detune(osc2.is_high());
+        }
+
+        #[cfg(feature = "dual")]
+        {
+            let osc = &cx.resources.voice2.osc;
+            if osc.tick() != Edge::None {
+                set_level(cx.resources.out2, osc.is_high());
+            }
+        }
 
         cx.resources.tim3.clear_update_interrupt_flag();
     }
 
-    #[task(binds = TIM2, priority = 2, resources = [adc1, avg_buf, ch0, gpioa, &fine_tune, &period, tim2])]
+    // Renders the ladder's samples a block at a time with `dac-dma`, into the
+    // half of `dac_block` the DMA has just finished playing, from the phase
+    // the oscillator will be at when the DMA gets there. That is a whole
+    // block ahead, so a pitch change or a sync reaches the ladder a block
+    // (160 µs) after the refill that sees it, up to two blocks (320 µs)
+    // after it happened, and the rendered phase jumps there instead of on
+    // the sync edge. The square and sub pins still follow the edge.
+    #[cfg(feature = "dac-dma")]
+    #[task(binds = DMA1_CHANNEL3, priority = 3, resources = [&asleep, dac_block, &dac_level, &params, &poly, &profiler, &voice])]
+    fn dac_refill(cx: dac_refill::Context) {
+        static mut CHORD: Chord = Chord::new();
+        static mut NOISE: Noise = Noise::new();
+        static mut CODE: u16 = 0;
+
+        let _span = span(cx.resources.profiler, profile::Task::DacRefill);
+
+        let dma1 = unsafe { &*pac::DMA1::ptr() };
+        dma1.ifcr.write(|w| w.cgif3().set_bit());
+        // Taken from the transfer count rather than the flag, so a refill
+        // that falls behind still writes the half the DMA isn't in
+        let remaining = dma1.ch3.ndtr.read().bits() as usize;
+        let (half, lead) = dac::free_half(remaining);
+
+        let params = cx.resources.params;
+        let osc = &cx.resources.voice.osc;
+        let step = osc.step();
+        let phase = osc.phase().wrapping_add(step.wrapping_mul(lead as u32));
+        // Asleep, the last code stays on the ladder
+        let level = if cx.resources.asleep.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some(cx.resources.dac_level.load(Ordering::Relaxed))
+        };
+        let voices = cx.resources.poly;
+        let mut ahead = lead as u32;
+        if let Some(block) = cx.resources.dac_block.chunks_mut(dac::BLOCK).nth(half) {
+            dac::fill_r2r(block, board::R2R_LSB, phase, step, |phase| {
+                if let Some(code) = dac_sample(params, CHORD, NOISE, voices, ahead, phase, step)
+                    .map(dac::from_u8)
+                    .or(level)
+                {
+                    *CODE = code;
+                }
+                ahead = ahead.wrapping_add(1);
+                *CODE
+            });
+        }
+    }
+
+    #[task(binds = TIM2, priority = 2, resources = [adc1, &asleep, ch0, ch10, ch11, ch12, ch13, ch14, ch15, &cv_level, &faults, &heartbeats, input, input2, &profiler, &temperature, tim2], spawn = [publish])]
     fn measure(cx: measure::Context) {
         static mut AVG_COUNTER: usize = 0;
+        static mut SLOW: bool = false;
+        static mut MISSED: bool = false;
+        static mut MONITOR: Monitor = Monitor::new();
+        static mut BROKEN: bool = false;
+        #[cfg(feature = "dual")]
+        static mut MONITOR2: Monitor = Monitor::new();
+        #[cfg(feature = "dual")]
+        static mut BROKEN2: bool = false;
+        #[cfg(feature = "cv-out")]
+        static mut DITHER: Dither = Dither::new();
+        // A failed conversion keeps the last envelope level
+        #[cfg(feature = "env-cv")]
+        static mut ENV: u16 = amp::ENV_FULL;
+        // And the last transposition
+        #[cfg(feature = "transpose-cv")]
+        static mut TRANSPOSE: Option<u16> = None;
+        #[cfg(feature = "mod-cv")]
+        static mut MOD_CV: [u16; 2] = [0; 2];
+
+        let _span = span(cx.resources.profiler, profile::Task::Measure);
+        cx.resources.heartbeats.beat(Beat::Measure);
 
-        cx.resources.avg_buf[*AVG_COUNTER % AVG_BUF_SIZE] =
-            cx.resources.adc1.read(cx.resources.ch0).unwrap();
-        *AVG_COUNTER += 1;
+        // Auto-sleep slows the measurement down, still fast enough to see
+        // the CV move and wake up
+        let asleep = cx.resources.asleep.load(Ordering::Relaxed);
+        if asleep != *SLOW {
+            *SLOW = asleep;
+            let hz = if asleep {
+                TIM3_FREQ_HZ / 2 / SLEEP_MEASURE_DIVIDER
+            } else {
+                TIM3_FREQ_HZ / 2
+            };
+            cx.resources.tim2.start(hz.hz());
+        }
 
+        // A fresh compare for the period the update started, out of the
+        // counts the current rate gives it
+        #[cfg(feature = "cv-out")]
+        {
+            let tim2 = unsafe { &*pac::TIM2::ptr() };
+            let top = tim2.arr.read().bits() + 1;
+            let code = cx.resources.cv_level.load(Ordering::Relaxed);
+            tim2.ccr3
+                .write(|w| unsafe { w.bits(DITHER.compare(code, top)) });
+        }
+
+        let index = *AVG_COUNTER % AVG_BUF_SIZE;
+        // A failed conversion, or a sample at a rail or stuck on one code,
+        // keeps the previous sample in the slot, and the pitch from the
+        // buffer it lands in isn't published
+        let faults = cx.resources.faults;
+        match convert_cv(cx.resources.adc1, cx.resources.ch0, faults) {
+            Some(sample) if sane(MONITOR, BROKEN, sample, faults) => {
+                cx.resources.input.store(index, sample)
+            }
+            _ => *MISSED = true,
+        }
+        #[cfg(feature = "dual")]
+        match convert_cv(cx.resources.adc1, cx.resources.ch10, faults) {
+            Some(sample) if sane(MONITOR2, BROKEN2, sample, faults) => {
+                cx.resources.input2.store(index, sample)
+            }
+            _ => *MISSED = true,
+        }
+        *AVG_COUNTER = (*AVG_COUNTER + 1) % (AVG_BUF_SIZE * TEMP_INTERVAL);
+
+        // The conversions stay here with the ADC, the float math and
+        // everything after it runs in `publish`
         if *AVG_COUNTER % AVG_BUF_SIZE == 0 {
-            let avg = avg(cx.resources.avg_buf);
-            let voltage = avg as f32 * 1191.55555 / cx.resources.adc1.read_vref() as f32;
-            // let voltage = avg as f32 * 1.237740204;
-            let mv = MvOct(6000.0 - 2.0 * voltage)
-                + cx.resources.fine_tune.load(Ordering::Relaxed) as f32;
-            // let mv = MvOct(voltage as f32 * 1.5015 as f32);
+            // Pulse width from the menu, moved up to 45% either way by the CV
+            #[cfg(not(feature = "pwm-cv"))]
+            let pw_cv = 0;
+            #[cfg(feature = "pwm-cv")]
+            let pw_cv = convert(cx.resources.adc1, cx.resources.ch11, faults).map_or(0, |sample| {
+                (sample as i32 - PW_CV_CENTER) * PW_CV_RANGE / PW_CV_CENTER
+            });
+            #[cfg(feature = "env-cv")]
+            if let Some(sample) = convert(cx.resources.adc1, cx.resources.ch12, faults) {
+                *ENV = sample;
+            }
+            #[cfg(not(feature = "env-cv"))]
+            let env = amp::ENV_FULL;
+            #[cfg(feature = "env-cv")]
+            let env = *ENV;
+            #[cfg(feature = "transpose-cv")]
+            if let Some(sample) = convert(cx.resources.adc1, cx.resources.ch13, faults) {
+                *TRANSPOSE = Some(sample);
+            }
+            #[cfg(not(feature = "transpose-cv"))]
+            let transpose = None;
+            #[cfg(feature = "transpose-cv")]
+            let transpose = *TRANSPOSE;
+            #[cfg(feature = "mod-cv")]
+            {
+                if let Some(sample) = convert(cx.resources.adc1, cx.resources.ch14, faults) {
+                    MOD_CV[0] = sample;
+                }
+                if let Some(sample) = convert(cx.resources.adc1, cx.resources.ch15, faults) {
+                    MOD_CV[1] = sample;
+                }
+            }
+            let reading = Reading {
+                avg: cx.resources.input.avg(),
+                #[cfg(feature = "dual")]
+                avg2: cx.resources.input2.avg(),
+                vref: cx.resources.adc1.read_vref(),
+                pw_cv,
+                env,
+                transpose,
+                #[cfg(feature = "mod-cv")]
+                mod_cv: *MOD_CV,
+                missed: *MISSED,
+            };
+            *MISSED = false;
+            // Still busy with the last one, which can only happen if the
+            // lower priorities stall for a whole buffer: this one is dropped
+            cx.spawn.publish(reading).ok();
+        }
 
+        if *AVG_COUNTER == 0 {
+            let temp = cx.resources.adc1.read_temp();
             cx.resources
-                .period
-                .store(us_to_period(mv.us()), Ordering::Relaxed);
+                .temperature
+                .store(temp as i16, Ordering::Relaxed);
+        }
+
+        cx.resources.tim2.clear_update_interrupt_flag();
+    }
 
-            cx.resources.gpioa.odr.modify(|r, w| unsafe {
-                w.bits((r.bits() & (0xff << 8)) | (mv.hz() / 16.0) as u32 & 0xff)
+    /// Turns a buffer of measurements into the published pitch and
+    /// everything that follows it.
+    #[task(priority = 1, resources = [&amp_curve, &arp_offset, &bus_offset, &calibration, &capture, &cv_level, &dac_level, &envelope, &frozen, glide, glide2, glitch, glitch2, &kick, note_change, &osc2, &params, &pitch_override, &playing, &pll, &poly, recorder, &track_edge, trigger, &voice, &voice2])]
+    fn publish(cx: publish::Context, reading: Reading) {
+        static mut TRIGGER: u8 = 0;
+        static mut SWEEP: Sweep = Sweep::new();
+        static mut DRIFT: Drift = Drift::new();
+        static mut VIBRATO: Vibrato = Vibrato::new();
+        // New notes restart the vibrato's fade-in: MIDI ones are seen here,
+        // CV ones one publish late, by the note trigger
+        static mut LAST_NOTE: Option<u8> = None;
+        static mut NEW_CV_NOTE: bool = false;
+        static mut HELD_CV: f32 = 0.0;
+        static mut TRANSPOSE: Semitones = Semitones::new();
+        static mut ARBITER: Arbiter = Arbiter::new();
+        // Pitch each paraphonic voice was last tuned to
+        static mut TUNED: [Option<i32>; poly::VOICES] = [None; poly::VOICES];
+        #[cfg(feature = "dual")]
+        static mut HELD_CV2: f32 = 0.0;
+
+        #[cfg(feature = "recorder")]
+        {
+            let mut recorder = cx.resources.recorder;
+            recorder
+                .lock(|r| r.record(DWT::get_cycle_count(), Kind::AdcAverage, reading.avg as i32));
+        }
+        let params = cx.resources.params;
+        // The spare inputs through the modulation matrix
+        #[cfg(not(feature = "mod-cv"))]
+        let mods = Mods::new();
+        #[cfg(feature = "mod-cv")]
+        let mods = {
+            let mut mods = Mods::new();
+            let [cv1, cv2] = reading.mod_cv;
+            mods.route(params.get(Param::Mod1), params.get(Param::ModAmount1), cv1);
+            mods.route(params.get(Param::Mod2), params.get(Param::ModAmount2), cv2);
+            mods
+        };
+        let mut offset = params
+            .get(Param::FineTune)
+            .saturating_add(params.get(Param::Octave).saturating_mul(MV_IN_OCT))
+            .saturating_add(cx.resources.bus_offset.load(Ordering::Relaxed) as i32);
+        // Transpose CV, in whole semitones through the pitch CV's input stage
+        if let Some(sample) = reading.transpose {
+            let mv = pitch::pitch_mv(pitch::cv_mv(sample as u32, reading.vref), 0);
+            offset = offset.saturating_add(TRANSPOSE.update(mv));
+        }
+        // Kick drum: every trigger sweeps down from `kick` above the pitch
+        let kick_mv = SWEEP.update(
+            cx.resources.kick.take(),
+            params.get(Param::Kick) as f32,
+            params.get(Param::KickDecay) as f32,
+            PUBLISH_US as f32,
+        );
+        let drift_mv = DRIFT.update(
+            params.get(Param::Drift) as f32 * MV_IN_OCT as f32 / 1200.0,
+            PUBLISH_US as f32,
+        );
+        let midi_note = cx.resources.playing.get();
+        let new_note = *NEW_CV_NOTE || midi_note != *LAST_NOTE;
+        *LAST_NOTE = midi_note;
+        let vibrato_mv = VIBRATO.update(
+            new_note,
+            params.get(Param::VibratoRate) as f32 / 10.0,
+            (params.get(Param::Vibrato) as f32 + mods.vibrato * matrix::VIBRATO_RANGE_CENTS)
+                .max(0.0)
+                * MV_IN_OCT as f32
+                / 1200.0,
+            params.get(Param::VibratoDelay) as f32,
+            PUBLISH_US as f32,
+        );
+        // Kept out of `offset`, so the note trigger doesn't fire on them
+        let modulation_mv = kick_mv + drift_mv + vibrato_mv + mods.pitch * matrix::PITCH_RANGE_MV;
+        if params.get(Param::Arp) != ARP_OFF {
+            offset = offset.saturating_add(cx.resources.arp_offset.load(Ordering::Relaxed) as i32);
+        }
+        // Track and hold: the CV only gets through while the sync input is
+        // high, or once per sync edge, and the last one holds in between
+        let tracking = !reading.missed
+            && match params.get(Param::Track) {
+                TRACK_GATE => sync_input_high(),
+                TRACK_SYNC => cx.resources.track_edge.swap(false, Ordering::Relaxed),
+                _ => true,
+            };
+        if tracking {
+            *HELD_CV = cx
+                .resources
+                .calibration
+                .apply(pitch::cv_mv(reading.avg, reading.vref));
+            #[cfg(feature = "dual")]
+            {
+                *HELD_CV2 = pitch::cv_mv(reading.avg2, reading.vref);
+            }
+        }
+        // The paraphonic voices share the tuning and the bend with the main
+        // pitch, not its modulation: each retune is an exponential, too slow
+        // for four of them every publish, so they only retune when their
+        // pitch moves
+        let paraphonic = params.get(Param::Poly) == POLY_ON;
+        let bend_mv = cx.resources.playing.bend_mv(params.get(Param::BendRange));
+        let voices = cx.resources.poly;
+        for (voice, tuned) in TUNED.iter_mut().enumerate() {
+            let mv = voices.note(voice).filter(|_| paraphonic).map(|note| {
+                let mv = note::mv(note as i32) + offset as f32 + bend_mv;
+                mv.clamp(pitch::MIN_PITCH_MV, pitch::MAX_PITCH_MV) as i32
             });
+            if mv != *tuned {
+                voices.tune(voice, mv.map(|mv| mv as f32), TIM3_FREQ_HZ);
+                *tuned = mv;
+            }
         }
 
-        cx.resources.tim2.clear_update_interrupt_flag();
+        // The test signals win over MIDI and the CV, which share the pitch
+        // as `src` says
+        let source = ARBITER.select(
+            params.get(Param::Source),
+            pitch::pitch_mv(*HELD_CV, 0),
+            cx.resources.playing.get(),
+        );
+        let mut forced = cx.resources.pitch_override.get();
+        if forced.is_none() {
+            match source {
+                Source::Midi(note) => {
+                    let mv = note::mv(note as i32) + offset as f32 + bend_mv + modulation_mv;
+                    forced = Some(mv.clamp(pitch::MIN_PITCH_MV, pitch::MAX_PITCH_MV) as i32);
+                }
+                // Transposed from C4
+                Source::Sum(note) => {
+                    offset += (note as i32 - MIDI_TRANSPOSE_ROOT) * MV_IN_OCT / 12;
+                    offset += bend_mv as i32;
+                }
+                Source::Cv => {}
+            }
+        }
+        let glide_ms = params.get(Param::Glide) as f32 + mods.glide * matrix::GLIDE_RANGE_MS;
+        let glide_step = if glide_ms > 0.0 {
+            (MV_IN_OCT as f32 * PUBLISH_US as f32) / (glide_ms * 1000.0)
+        } else {
+            0.0
+        };
+        // The tuner reference plays `ref` exactly, held or not
+        let tuner = params.get(Param::Tuner) == TUNER_ON;
+        // Holds the last good pitch rather than publish one from a buffer
+        // with a missing sample
+        let hold = !tuner && (cx.resources.frozen.load(Ordering::Relaxed) || reading.missed);
+        // Follow the sync input while it's running, as a clock utility or
+        // phase-locked
+        let input_hz = cx.resources.capture.hz(SYSCLK_HZ);
+        let lock = if tuner {
+            Some(params.get(Param::Reference) as f32 / 10.0)
+        } else {
+            match clock_ratio(params) {
+                Some(ratio) => input_hz.map(|hz| ratio.of(hz)),
+                None => pll::RATIOS
+                    .get((params.get(Param::Pll) - 1) as usize)
+                    .zip(input_hz)
+                    .map(|(&ratio, hz)| cx.resources.pll.hz(hz, ratio)),
+            }
+        };
+        cx.resources.voice.set_lock(lock);
+
+        let lfo = params.get(Param::Range) == RANGE_LFO;
+        if params.get(Param::Rate) == RATE_CV {
+            cx.resources.voice.set_rate(None);
+        }
+        cx.resources.voice.set_lfo(lfo);
+        #[cfg(feature = "dual")]
+        cx.resources.voice2.set_lfo(lfo);
+        let smooth = params.get(Param::Smooth) == SMOOTH_ON;
+        cx.resources.voice.set_smooth(smooth);
+        #[cfg(feature = "dual")]
+        cx.resources.voice2.set_smooth(smooth);
+
+        let modulated = offset.saturating_add(modulation_mv as i32);
+        let published = cx.resources.voice.update(
+            *HELD_CV,
+            cx.resources.glide,
+            modulated,
+            forced,
+            glide_step,
+            hold,
+        );
+
+        // Trigger on a new note in the averaged CV, before glide
+        let cv_pitch = pitch::pitch_mv(cx.resources.voice.cv_mv() as f32, offset);
+        *NEW_CV_NOTE = cx.resources.note_change.update(cv_pitch);
+        if *NEW_CV_NOTE {
+            defmt::debug!("note cv_pitch_mv={}", cv_pitch as i32);
+            *TRIGGER = TRIGGER_PUBLISHES;
+            cx.resources.trigger.set_high().ok();
+        } else if *TRIGGER > 0 {
+            *TRIGGER -= 1;
+            if *TRIGGER == 0 {
+                cx.resources.trigger.set_low().ok();
+            }
+        }
+
+        let pw = params
+            .get(Param::PulseWidth)
+            .saturating_add(reading.pw_cv)
+            .saturating_add((mods.pw * matrix::PW_RANGE_PCT) as i32);
+        cx.resources.voice.osc.set_duty(pw.max(0) as u32);
+
+        // MIDI-to-CV companion output, whatever `src` plays
+        let playing = cx.resources.playing;
+        if let (DAC_MIDI_CV, Some(note)) = (params.get(Param::Dac), playing.get()) {
+            let bend_mv = playing.bend_mv(params.get(Param::BendRange));
+            let code = dac::from_u8(midi::cv_code(note, bend_mv));
+            cx.resources.dac_level.store(code, Ordering::Relaxed);
+        }
+
+        if let Some(pitch) = published {
+            defmt::trace!("pitch_mv={}", pitch as i32);
+            custom::HOOKS.on_pitch_update(pitch, params);
+
+            let cents = params.get(Param::Detune) as f32 + mods.detune * matrix::DETUNE_RANGE_CENTS;
+            let detuned = pitch::detune_mv(pitch, cents as i32);
+            let voice = cx.resources.voice;
+            let step = pitch::tuning_word(voice.hz_at(detuned), TIM3_FREQ_HZ);
+            // Slewed with the main oscillator, so the beating doesn't step
+            if smooth {
+                cx.resources.osc2.slew_step(step, PUBLISH_TICKS);
+            } else {
+                cx.resources.osc2.set_step(step);
+            }
+        }
+
+        // The amplitude curve at the pitch, held or not, shaped by the envelope
+        // and the matrix
+        let voice = cx.resources.voice;
+        if params.get(Param::Dac) == DAC_AMPLITUDE {
+            let pitch = published.unwrap_or(voice.pitch_mv() as f32);
+            let mut code = amp::shape(cx.resources.amp_curve.code(pitch), reading.env);
+            let gain = mods.amp.clamp(0.0, 1.0) * amp::ENV_FULL as f32;
+            code = amp::shape(code, gain as u16);
+            // Played over MIDI, the last note's velocity scales it too
+            let playing = cx.resources.playing;
+            if source != Source::Cv {
+                let curve = params.get(Param::Velocity);
+                let gain = midi::velocity_gain(playing.velocity(), curve);
+                code = amp::shape(code, gain);
+            }
+            cx.resources.dac_level.store(code, Ordering::Relaxed);
+        }
+        cx.resources.envelope.store(reading.env, Ordering::Relaxed);
+
+        let cv_code = match params.get(Param::CvOut) {
+            CV_OUT_PITCH => cv_out::pitch_code(voice.pitch_mv() as f32),
+            CV_OUT_TRIANGLE => cv_out::triangle_code(voice.osc.phase()),
+            _ => 0,
+        };
+        cx.resources.cv_level.store(cv_code, Ordering::Relaxed);
+
+        #[cfg(feature = "dual")]
+        cx.resources.voice2.update(
+            *HELD_CV2,
+            cx.resources.glide2,
+            modulated,
+            forced,
+            glide_step,
+            hold,
+        );
+
+        // The sync lockouts follow the output periods
+        let holdoff = glitch::holdoff(voice.osc.step(), TIM3_FREQ_HZ, SYSCLK_HZ);
+        let mut filter = cx.resources.glitch;
+        filter.lock(|filter| filter.set_holdoff(holdoff));
+        #[cfg(feature = "dual")]
+        {
+            let holdoff = glitch::holdoff(cx.resources.voice2.osc.step(), TIM3_FREQ_HZ, SYSCLK_HZ);
+            let mut filter = cx.resources.glitch2;
+            filter.lock(|filter| filter.set_holdoff(holdoff));
+        }
+    }
+
+    #[task(binds = USART3, priority = 3, resources = [&amp_curve, arp, &arp_offset, gate, &kick, notes, outbox, &params, &playing, &poly, recorder, usart3, &voice], spawn = [amp_save, cli_exec, preset_recall, sysex_write])]
+    fn serial_rx(cx: serial_rx::Context) {
+        static mut EDITOR: Editor = Editor::new();
+        static mut PARSER: Parser = Parser::new();
+        static mut CONTROLLERS: Controllers = Controllers::new();
+        static mut SYSEX: Receiver = Receiver::new();
+        static mut FOLLOWER: division::Follower = division::Follower::new();
+        static mut ALLOCATOR: Allocator = Allocator::new();
+        // Microsecond clock for the clock follower, carrying the cycles left
+        // over so it doesn't drift
+        static mut NOW_US: u32 = 0;
+        static mut LAST_CYCLES: u32 = 0;
+
+        let usart = cx.resources.usart3;
+        let outbox = cx.resources.outbox;
+        let sr = usart.sr.read();
+        // Replies go out one byte per interrupt
+        if sr.txe().bit_is_set() && usart.cr1.read().bits() & USART_TXEIE != 0 {
+            match outbox.pop() {
+                Some(b) => usart.dr.write(|w| unsafe { w.bits(b as u32) }),
+                None => usart
+                    .cr1
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !USART_TXEIE) }),
+            }
+        }
+        if sr.rxne().bit_is_clear() {
+            return;
+        }
+        // Reading SR then DR clears the flags, overruns included
+        let byte = usart.dr.read().bits() as u8;
+        if sr.fe().bit_is_set() || sr.ne().bit_is_set() {
+            return;
+        }
+
+        if cfg!(feature = "cli") {
+            let echo = |bytes: &[u8]| bytes.iter().for_each(|&b| outbox.push(b));
+            if let Some(line) = EDITOR.feed(byte, echo) {
+                // Lines typed while a command still runs are dropped
+                cx.spawn.cli_exec(line).ok();
+            }
+            if !outbox.is_empty() {
+                usart
+                    .cr1
+                    .modify(|r, w| unsafe { w.bits(r.bits() | USART_TXEIE) });
+            }
+            return;
+        }
+
+        #[cfg(feature = "recorder")]
+        cx.resources
+            .recorder
+            .record(DWT::get_cycle_count(), Kind::MidiByte, byte as i32);
+        let params = cx.resources.params;
+        if let Some(request) = SYSEX.feed(byte) {
+            // Without the output only writes do anything
+            let mut reply = |b| {
+                if cfg!(feature = "midi-out") {
+                    outbox.push(b)
+                }
+            };
+            match request {
+                Request::GetParam { page } => match params::all().nth(page as usize) {
+                    Some(p) => sysex::param(page, params.get(p), &mut reply),
+                    None => sysex::ack(sysex::GET_PARAM, sysex::BAD_REQUEST, &mut reply),
+                },
+                Request::SetParam { page, value } => match params::all().nth(page as usize) {
+                    Some(p) => {
+                        params.set(p, value);
+                        sysex::ack(sysex::PARAM, sysex::OK, &mut reply);
+                    }
+                    None => sysex::ack(sysex::PARAM, sysex::BAD_REQUEST, &mut reply),
+                },
+                Request::Dump => {
+                    for (page, p) in params::all().enumerate() {
+                        sysex::param(page as u8, params.get(p), &mut reply);
+                    }
+                }
+                Request::GetWave { bank } if (bank as usize) < wavetable::BANKS => {
+                    let samples = (0..=255).map(|i| wavetable::sample(bank as usize, i));
+                    sysex::wave(bank, samples, &mut reply);
+                }
+                // Flash writes stall the CPU, so they run at the lowest priority
+                Request::SetWave { bank } => {
+                    if cx.spawn.sysex_write(bank, SYSEX.samples()).is_err() {
+                        sysex::ack(sysex::WAVE, sysex::WRITE_FAILED, &mut reply);
+                    }
+                }
+                Request::GetWave { .. } => {
+                    sysex::ack(sysex::GET_WAVE, sysex::BAD_REQUEST, &mut reply)
+                }
+                Request::Bootloader => bootloader::enter(),
+                Request::GetAmp { point } => match cx.resources.amp_curve.get(point as usize) {
+                    Some(code) => sysex::amp(point, code, &mut reply),
+                    None => sysex::ack(sysex::GET_AMP, sysex::BAD_REQUEST, &mut reply),
+                },
+                Request::SetAmp { point, code } => {
+                    if !cx.resources.amp_curve.set(point as usize, code) {
+                        sysex::ack(sysex::AMP, sysex::BAD_REQUEST, &mut reply);
+                    } else if cx.spawn.amp_save().is_err() {
+                        sysex::ack(sysex::AMP, sysex::WRITE_FAILED, &mut reply);
+                    }
+                }
+                Request::Bad { command } => sysex::ack(command, sysex::BAD_REQUEST, &mut reply),
+            }
+            if !outbox.is_empty() {
+                usart
+                    .cr1
+                    .modify(|r, w| unsafe { w.bits(r.bits() | USART_TXEIE) });
+            }
+            return;
+        }
+
+        let channel = match params.get(Param::Channel) {
+            CHANNEL_OMNI => None,
+            n => Some((n - 1) as u8),
+        };
+        let playing = cx.resources.playing;
+        let notes = cx.resources.notes;
+        let arp = cx.resources.arp;
+        let arp_on = params.get(Param::Arp) != ARP_OFF;
+        let voices = cx.resources.poly;
+        match PARSER.feed(byte, channel) {
+            Some(Message::NoteOn { note, velocity }) => {
+                if params.get(Param::Poly) == POLY_ON {
+                    voices.set_note(ALLOCATOR.note_on(note), Some(note));
+                }
+                notes.press(note);
+                playing.set_velocity(velocity);
+                if !arp_on {
+                    playing.set(notes.current());
+                } else if notes.held().len() == 1 {
+                    // The first note held starts the pattern over, the
+                    // arpeggiator's clock plays the rest
+                    arp.restart();
+                    arp_step(arp, notes, params, playing, cx.resources.arp_offset);
+                }
+                cx.resources.kick.fire();
+                set_level(cx.resources.gate, true);
+            }
+            Some(Message::NoteOff { note }) => {
+                // Released whatever the mode, so no voice hangs after it
+                // changes
+                if let Some(voice) = ALLOCATOR.note_off(note) {
+                    voices.set_note(voice, None);
+                }
+                notes.release(note);
+                // The last note keeps sounding after its release, while the
+                // arpeggiator moves on by itself
+                if !arp_on {
+                    if let Some(note) = notes.current() {
+                        playing.set(Some(note));
+                    }
+                }
+                set_level(cx.resources.gate, notes.current().is_some());
+            }
+            Some(Message::PitchBend { bend }) => playing.set_bend(bend),
+            // Programs count from 0, presets from 1
+            Some(Message::ProgramChange { program }) => {
+                cx.spawn.preset_recall(program.saturating_add(1)).ok();
+            }
+            Some(Message::ControlChange {
+                control: midi::CC_RESET_CONTROLLERS,
+                ..
+            }) => playing.set_bend(0),
+            Some(Message::ControlChange { control, .. }) if control >= midi::CC_MODE => {
+                ALLOCATOR.all_off();
+                for voice in 0..poly::VOICES {
+                    voices.set_note(voice, None);
+                }
+                notes.clear();
+                set_level(cx.resources.gate, false);
+            }
+            Some(Message::ControlChange { control, value }) => {
+                let param = CONTROLLERS.change(control, value).and_then(|(cc, value)| {
+                    let &(_, param) = CC_PARAMS.iter().find(|&&(n, _)| n == cc)?;
+                    Some((param, value))
+                });
+                if let Some((param, value)) = param {
+                    params.set(param, param.info().scale(value));
+                }
+            }
+            Some(Message::Clock) => {
+                let cycles = DWT::get_cycle_count().wrapping_sub(*LAST_CYCLES);
+                let us = cycles / (SYSCLK_HZ / SEC_IN_US);
+                *NOW_US = NOW_US.wrapping_add(us);
+                *LAST_CYCLES = LAST_CYCLES.wrapping_add(us * (SYSCLK_HZ / SEC_IN_US));
+
+                if let Some(tick_us) = FOLLOWER.tick(*NOW_US) {
+                    let division = division::DIVISIONS.get(params.get(Param::Division) as usize);
+                    if let (RATE_MIDI, Some(division)) = (params.get(Param::Rate), division) {
+                        cx.resources
+                            .voice
+                            .set_rate(Some(division.hz_from_tick(tick_us)));
+                    }
+                }
+
+                // Arpeggiator on MIDI clock, a step every `div`
+                if arp_on && params.get(Param::ArpClock) == ARP_CLOCK_MIDI {
+                    let division = division::DIVISIONS.get(params.get(Param::Division) as usize);
+                    if let Some(division) = division {
+                        if arp.clock(division.ticks) {
+                            arp_step(arp, notes, params, playing, cx.resources.arp_offset);
+                        }
+                    }
+                }
+            }
+            Some(Message::Stop) => FOLLOWER.stop(),
+            Some(Message::Start) => arp.restart(),
+            Some(Message::Continue) | None => {}
+        }
+    }
+
+    /// I2C follower: a leader on the bus sets the pitch offset and pages
+    /// through the `ii` registers and reads them back.
+    #[task(binds = I2C2_EV, priority = 3, resources = [&bus_offset, i2c2, &params, &voice])]
+    fn ii_event(cx: ii_event::Context) {
+        static mut RESPONDER: Responder = Responder::new();
+
+        let i2c = cx.resources.i2c2;
+        let params = cx.resources.params;
+        let bus_offset = cx.resources.bus_offset;
+        let sr1 = i2c.sr1.read().bits();
+        if sr1 & I2C_ADDR != 0 {
+            // Reading SR2 after SR1 clears ADDR
+            i2c.sr2.read();
+            RESPONDER.start();
+        }
+        if sr1 & I2C_RXNE != 0 {
+            RESPONDER.receive(i2c.dr.read().bits() as u8);
+        }
+        if sr1 & I2C_TXE != 0 {
+            let voice = cx.resources.voice;
+            let byte = RESPONDER.transmit(|reg| match reg {
+                Register::PitchOffset => bus_offset.load(Ordering::Relaxed),
+                Register::Wave => params.get(Param::Dac) as i16,
+                Register::Sync => params.get(Param::Sync) as i16,
+                Register::FineTune => params.get(Param::FineTune) as i16,
+                Register::Pitch => voice.pitch_mv() as i16,
+            });
+            i2c.dr.write(|w| unsafe { w.bits(byte as u32) });
+        }
+        if sr1 & I2C_STOPF != 0 {
+            // Writing CR1 after reading SR1 clears STOPF
+            i2c.cr1.modify(|r, w| unsafe { w.bits(r.bits()) });
+            match RESPONDER.stop() {
+                Some((Register::PitchOffset, mv)) => bus_offset.store(
+                    mv.clamp(-ii::MAX_OFFSET_MV, ii::MAX_OFFSET_MV),
+                    Ordering::Relaxed,
+                ),
+                Some((Register::Wave, value)) => params.set(Param::Dac, value as i32),
+                Some((Register::Sync, value)) => params.set(Param::Sync, value as i32),
+                Some((Register::FineTune, mv)) => params.set(Param::FineTune, mv as i32),
+                Some((Register::Pitch, _)) | None => {}
+            }
+        }
+    }
+
+    /// The leader NACKs the last byte it reads, which ends a read; bus errors
+    /// just drop the transfer.
+    #[task(binds = I2C2_ER, priority = 3, resources = [i2c2])]
+    fn ii_error(cx: ii_error::Context) {
+        cx.resources
+            .i2c2
+            .sr1
+            .modify(|r, w| unsafe { w.bits(r.bits() & !I2C_ERRORS) });
+    }
+
+    /// Replaces a wavetable received over SysEx and acknowledges it.
+    #[task(priority = 1, resources = [outbox])]
+    fn sysex_write(mut cx: sysex_write::Context, bank: u8, samples: [u8; wavetable::SAMPLES]) {
+        let status = match wavetable::write(bank as usize, &samples) {
+            Ok(()) => sysex::OK,
+            Err(wavetable::Error::BadBank) => sysex::BAD_REQUEST,
+            Err(_) => sysex::WRITE_FAILED,
+        };
+        cx.resources
+            .outbox
+            .lock(|outbox| send_ack(outbox, sysex::WAVE, status));
+    }
+
+    /// Saves the amplitude curve after a SysEx breakpoint write and
+    /// acknowledges it.
+    #[task(priority = 1, resources = [&amp_curve, outbox, storage])]
+    fn amp_save(mut cx: amp_save::Context) {
+        let status = match cx.resources.storage.as_mut() {
+            Some(storage) => match amp::save(storage, cx.resources.amp_curve) {
+                Ok(()) => sysex::OK,
+                Err(_) => sysex::WRITE_FAILED,
+            },
+            None => sysex::WRITE_FAILED,
+        };
+        cx.resources
+            .outbox
+            .lock(|outbox| send_ack(outbox, sysex::AMP, status));
+    }
+
+    /// Recalls a preset picked by a MIDI program change.
+    #[task(priority = 1, resources = [&params, preset, storage])]
+    fn preset_recall(cx: preset_recall::Context, slot: u8) {
+        let r = cx.resources;
+        recall(r.storage, r.params, r.preset, slot);
+    }
+
+    /// Runs one console command line and prompts for the next.
+    #[task(priority = 1, resources = [&amp_curve, &calibration, calibrator, outbox, &params, preset, report, storage, &voice, &watch], spawn = [snapshot])]
+    fn cli_exec(cx: cli_exec::Context, line: cli::Line) {
+        let params = cx.resources.params;
+        let mut out = Console(cx.resources.outbox);
+
+        match Command::parse(line.as_str()) {
+            Command::Empty => Ok(()),
+            Command::Help => out.write_str(cli::HELP),
+            Command::Freq => {
+                let hz = cx.resources.voice.hz();
+                let dhz = (hz * 10.0) as u32;
+                write!(
+                    out,
+                    "{}.{} Hz {} mV",
+                    dhz / 10,
+                    dhz % 10,
+                    cx.resources.voice.pitch_mv()
+                )
+                .ok();
+                match Note::from_hz(hz) {
+                    Some(note) => writeln!(
+                        out,
+                        " {}{} {:+}c",
+                        note.name(),
+                        note.octave(),
+                        note.cents as i32
+                    ),
+                    None => writeln!(out),
+                }
+            }
+            Command::Load => match cx.resources.report {
+                Some(report) => {
+                    let load = report.load_permille;
+                    writeln!(out, "load {}.{}%", load / 10, load % 10).ok();
+                    profile::TASKS
+                        .iter()
+                        .zip(report.tasks.iter())
+                        .try_for_each(|(task, s)| {
+                            writeln!(
+                                out,
+                                "{} n={} min={} avg={} max={}",
+                                task.name(),
+                                s.count,
+                                s.min,
+                                s.avg,
+                                s.max
+                            )
+                        })
+                }
+                None => writeln!(out, "no figures, needs the profile feature"),
+            },
+            Command::Crash => match crash::last() {
+                Some(record) => write_crash(&mut out, &record),
+                None => writeln!(out, "no crash since power-up"),
+            },
+            Command::Pages => params::all().try_for_each(|p| write_value(&mut out, p, params)),
+            Command::Get(name) => match params::by_name(name) {
+                Some(p) => write_value(&mut out, p, params),
+                None => writeln!(out, "unknown page {}", name),
+            },
+            Command::Set(name, text) => match params::by_name(name) {
+                Some(p) => match p.info().parse(text) {
+                    Some(value) => {
+                        params.set(p, value);
+                        write_value(&mut out, p, params)
+                    }
+                    None => writeln!(out, "bad value {}", text),
+                },
+                None => writeln!(out, "unknown page {}", name),
+            },
+            Command::Reset => {
+                params.reset();
+                writeln!(out, "ok")
+            }
+            Command::Presets => (1..=preset::SLOTS).try_for_each(|slot| {
+                match cx
+                    .resources
+                    .storage
+                    .as_ref()
+                    .and_then(|s| preset::load(s, slot))
+                {
+                    Some(preset) => writeln!(out, "{} {}", slot, preset.name()),
+                    None => writeln!(out, "{} empty", slot),
+                }
+            }),
+            Command::Save(text, name) => {
+                match (preset::parse_slot(text), cx.resources.storage.as_mut()) {
+                    (Some(slot), Some(storage)) => {
+                        // Flash writes stall the CPU, the outputs pause briefly
+                        let preset = Preset::capture(name, params);
+                        match preset::save(storage, slot, &preset) {
+                            Ok(()) => {
+                                params.set(Param::Preset, slot as i32);
+                                *cx.resources.preset = slot;
+                                writeln!(out, "ok")
+                            }
+                            Err(_) => writeln!(out, "write failed"),
+                        }
+                    }
+                    (None, _) => writeln!(out, "bad preset {}", text),
+                    (_, None) => writeln!(out, "no storage"),
+                }
+            }
+            Command::Recall(text) => match preset::parse_slot(text) {
+                Some(slot) => {
+                    if recall(cx.resources.storage, params, cx.resources.preset, slot) {
+                        writeln!(out, "ok")
+                    } else {
+                        writeln!(out, "preset {} is empty", slot)
+                    }
+                }
+                None => writeln!(out, "bad preset {}", text),
+            },
+            Command::Amp => (0..amp::POINTS).try_for_each(|point| {
+                let code = cx.resources.amp_curve.get(point).unwrap_or(0);
+                writeln!(out, "{} {} mV {}", point, Curve::mv(point), code)
+            }),
+            Command::SetAmp(point, code) => {
+                let curve = cx.resources.amp_curve;
+                match (point.parse(), code.parse()) {
+                    (Ok(point), Ok(code)) if point < amp::POINTS => {
+                        curve.set(point, code);
+                        // Flash writes stall the CPU, the outputs pause briefly
+                        match cx.resources.storage.as_mut().map(|s| amp::save(s, curve)) {
+                            Some(Ok(())) => writeln!(out, "ok"),
+                            Some(Err(_)) => writeln!(out, "write failed"),
+                            None => writeln!(out, "no storage, kept until power-off"),
+                        }
+                    }
+                    _ => writeln!(out, "bad breakpoint or code"),
+                }
+            }
+            Command::Calibrate => {
+                cx.resources.calibrator.start(cx.resources.calibration);
+                writeln!(
+                    out,
+                    "patch the CV into the reference too, play a note, then one an octave or more away"
+                )
+            }
+            Command::Calibration => {
+                let calibration = cx.resources.calibration;
+                let ppm = ((calibration.scale() - 1.0) * 1e6) as i32;
+                writeln!(
+                    out,
+                    "scale {:+} ppm offset {:+} mV",
+                    ppm,
+                    calibration.offset_mv() as i32
+                )
+            }
+            Command::Watch(args) => match cx.resources.watch.command(args) {
+                Ok(()) => writeln!(out, "ok"),
+                Err(watch::CommandError::BadRate) => writeln!(out, "bad rate"),
+                Err(watch::CommandError::UnknownChannel) => writeln!(out, "unknown channel"),
+            },
+            Command::Snapshot => match cx.spawn.snapshot() {
+                Ok(()) => writeln!(out, "ok, over RTT"),
+                Err(_) => writeln!(out, "busy"),
+            },
+            Command::Bootloader => bootloader::enter(),
+            Command::Update => update::enter(),
+            Command::Unknown(word) => writeln!(out, "unknown command {}, try help", word),
+        }
+        .ok();
+        out.write_str(cli::PROMPT).ok();
+    }
+
+    /// Runs a CV calibration started from the console, and saves the result.
+    #[task(priority = 1, schedule = [cal_tick], resources = [&calibration, calibrator, &capture, outbox, storage, &voice])]
+    fn cal_tick(cx: cal_tick::Context) {
+        let r = cx.resources;
+        let cv = r.voice.cv_mv() as f32;
+        let hz = r.capture.hz(SYSCLK_HZ);
+        let mut out = Console(r.outbox);
+
+        match r.calibrator.poll(cv, hz, r.calibration) {
+            Some(calibrate::Event::FirstNote) => {
+                defmt::info!("cal first note");
+                if cfg!(feature = "cli") {
+                    writeln!(out, "first note in, now the second").ok();
+                }
+            }
+            Some(calibrate::Event::Done { scale, offset_mv }) => {
+                let ppm = ((scale - 1.0) * 1e6) as i32;
+                defmt::info!("cal scale={}ppm offset={}mV", ppm, offset_mv as i32);
+                // Flash writes stall the CPU, the outputs pause briefly
+                let saved = r
+                    .storage
+                    .as_mut()
+                    .map(|s| calibrate::save(s, r.calibration));
+                if cfg!(feature = "cli") {
+                    writeln!(out, "scale {:+} ppm offset {:+} mV", ppm, offset_mv as i32).ok();
+                    match saved {
+                        Some(Ok(())) => writeln!(out, "saved"),
+                        Some(Err(_)) => writeln!(out, "write failed"),
+                        None => writeln!(out, "no storage, kept until power-off"),
+                    }
+                    .ok();
+                }
+            }
+            Some(calibrate::Event::Failed(failure)) => {
+                let reason = match failure {
+                    Failure::Timeout => "no two notes an octave apart within a minute",
+                    Failure::OutOfRange => "correction out of range, check the patch",
+                };
+                defmt::warn!("cal failed");
+                if cfg!(feature = "cli") {
+                    writeln!(out, "failed: {}, calibration unchanged", reason).ok();
+                }
+            }
+            None => {}
+        }
+
+        cx.schedule
+            .cal_tick(cx.scheduled + (CAL_POLL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    #[task(priority = 1, schedule = [led_tick], resources = [&faults, led_buf, led_dma, &voice])]
+    fn led_tick(cx: led_tick::Context) {
+        static mut LAST_SYNCS: u32 = 0;
+        static mut FLASH_UNTIL: Option<Instant> = None;
+
+        let r = cx.resources;
+
+        let syncs = r.voice.osc.syncs();
+        if syncs != *LAST_SYNCS {
+            *LAST_SYNCS = syncs;
+            *FLASH_UNTIL = Some(cx.scheduled + (LED_FLASH_MS * (SYSCLK_HZ / 1000)).cycles());
+        }
+        let flash = match *FLASH_UNTIL {
+            Some(until) if cx.scheduled < until => true,
+            _ => false,
+        };
+
+        let color = status_color(r.voice.pitch_mv(), flash, r.faults);
+
+        // The previous frame finished long ago, re-arm the channel with the new one
+        r.led_dma.stop();
+        ws2812::encode(color, WS2812_T0H, WS2812_T1H, r.led_buf);
+        r.led_dma
+            .set_memory_address(r.led_buf.as_ptr() as u32, true);
+        r.led_dma.set_transfer_length(ws2812::BUF_LEN);
+        r.led_dma.start();
+
+        cx.schedule
+            .led_tick(cx.scheduled + (LED_INTERVAL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    /// Dumps clock, timer, ADC and EXTI configuration with the firmware version
+    /// and a settings CRC in one block, so a single paste describes a unit.
+    #[task(priority = 1, resources = [clocks, &params])]
+    fn snapshot(cx: snapshot::Context) {
+        let c = cx.resources.clocks;
+        // Read only, and nothing reconfigures these after init
+        let (adc1, exti, tim1, tim2, tim3) = unsafe {
+            (
+                &*pac::ADC1::ptr(),
+                &*pac::EXTI::ptr(),
+                &*pac::TIM1::ptr(),
+                &*pac::TIM2::ptr(),
+                &*pac::TIM3::ptr(),
+            )
+        };
+
+        let mut crc = Crc32::new();
+        for p in params::all() {
+            crc.update(&cx.resources.params.get(p).to_le_bytes());
+        }
+
+        defmt::info!("snapshot fw={}", env!("CARGO_PKG_VERSION"));
+        defmt::info!(
+            "clocks sysclk={} hclk={} pclk1={} pclk2={} adcclk={}",
+            c.sysclk().0,
+            c.hclk().0,
+            c.pclk1().0,
+            c.pclk2().0,
+            c.adcclk().0
+        );
+        for &(name, psc, arr) in [
+            ("tim1", tim1.psc.read().bits(), tim1.arr.read().bits()),
+            ("tim2", tim2.psc.read().bits(), tim2.arr.read().bits()),
+            ("tim3", tim3.psc.read().bits(), tim3.arr.read().bits()),
+        ]
+        .iter()
+        {
+            defmt::info!("{} psc={} arr={}", name, psc, arr);
+        }
+        defmt::info!(
+            "adc1 cr1={:x} cr2={:x} smpr1={:x} smpr2={:x} sqr3={:x}",
+            adc1.cr1.read().bits(),
+            adc1.cr2.read().bits(),
+            adc1.smpr1.read().bits(),
+            adc1.smpr2.read().bits(),
+            adc1.sqr3.read().bits()
+        );
+        defmt::info!(
+            "exti imr={:x} rtsr={:x} ftsr={:x}",
+            exti.imr.read().bits(),
+            exti.rtsr.read().bits(),
+            exti.ftsr.read().bits()
+        );
+        defmt::info!("params crc={:x}", crc.finish());
+        defmt::info!("snapshot end");
+    }
+
+    /// Logs the handler timings and the CPU load, and keeps them for the
+    /// console.
+    #[cfg(feature = "profile")]
+    #[task(priority = 1, schedule = [profile_tick], resources = [&profiler, report])]
+    fn profile_tick(cx: profile_tick::Context) {
+        let report = cx.resources.profiler.report(DWT::get_cycle_count());
+        defmt::info!(
+            "load {}.{}%",
+            report.load_permille / 10,
+            report.load_permille % 10
+        );
+        for (task, s) in profile::TASKS.iter().zip(report.tasks.iter()) {
+            defmt::info!(
+                "{} n={} min={} avg={} max={}",
+                task.name(),
+                s.count,
+                s.min,
+                s.avg,
+                s.max
+            );
+        }
+        *cx.resources.report = Some(report);
+
+        cx.schedule
+            .profile_tick(cx.scheduled + (PROFILE_INTERVAL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    /// Logs one status line every second for tracking runs over hours: the
+    /// seconds since boot, the frequency in mHz, the nearest note and its
+    /// cents, the CV, the fine tune and the CPU load in tenths of a percent.
+    /// Goes over RTT, and to the console as well with the `cli` feature.
+    #[cfg(feature = "telemetry")]
+    #[task(priority = 1, schedule = [telemetry_tick], resources = [outbox, &params, report, &voice])]
+    fn telemetry_tick(cx: telemetry_tick::Context) {
+        static mut SECONDS: u32 = 0;
+
+        let hz = cx.resources.voice.hz();
+        let mhz = (hz * 1000.0) as u32;
+        let (note, cents) = Note::from_hz(hz).map_or((0, 0), |n| (n.midi, n.cents as i32));
+        let cv = cx.resources.voice.cv_mv();
+        let fine_tune = cx.resources.params.get(Param::FineTune);
+        // Figures from the profiler's last window
+        let load = cx.resources.report.map_or(0, |r| r.load_permille);
+
+        defmt::info!(
+            "status t={} mhz={} note={} cents={} cv_mv={} fine_tune={} load={}",
+            *SECONDS,
+            mhz,
+            note,
+            cents,
+            cv,
+            fine_tune,
+            load
+        );
+        if cfg!(feature = "cli") {
+            writeln!(
+                Console(cx.resources.outbox),
+                "status t={} mhz={} note={} cents={} cv_mv={} fine_tune={} load={}",
+                *SECONDS,
+                mhz,
+                note,
+                cents,
+                cv,
+                fine_tune,
+                load
+            )
+            .ok();
+        }
+        *SECONDS = SECONDS.wrapping_add(1);
+
+        cx.schedule
+            .telemetry_tick(cx.scheduled + (TELEMETRY_INTERVAL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    /// Multiplexes the 7-segment digits, rebuilding the frame from the current
+    /// pitch every few scans.
+    #[cfg(feature = "segments")]
+    #[task(priority = 1, schedule = [segments_tick], resources = [segments, &voice])]
+    fn segments_tick(cx: segments_tick::Context) {
+        static mut FRAME: segments::Frame = [0; segments::DIGITS];
+        static mut SCAN: u8 = 0;
+
+        if *SCAN % SEGMENT_REFRESH_SCANS == 0 {
+            let hz = cx.resources.voice.hz();
+            *FRAME = match Note::from_hz(hz) {
+                Some(note) if cfg!(feature = "segments-note") => {
+                    segments::note(note.name(), note.octave())
+                }
+                _ => segments::hz(hz),
+            };
+        }
+
+        let digit = *SCAN as usize % segments::DIGITS;
+        cx.resources.segments.show(digit, FRAME[digit]).ok();
+        *SCAN = (*SCAN + 1) % SEGMENT_REFRESH_SCANS;
+
+        cx.schedule
+            .segments_tick(cx.scheduled + (SEGMENT_SCAN_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    /// Steps the arpeggiator at the tapped tempo, and polls for one while
+    /// there is none.
+    #[task(priority = 1, schedule = [arp_tick], resources = [arp, &arp_offset, &arp_period, notes, &params, &playing])]
+    fn arp_tick(cx: arp_tick::Context) {
+        let params = cx.resources.params;
+        let period = cx.resources.arp_period.load(Ordering::Relaxed);
+        let tapped = params.get(Param::Arp) != ARP_OFF
+            && params.get(Param::ArpClock) == ARP_CLOCK_TAP
+            && period != 0;
+        if tapped {
+            let (playing, offset) = (cx.resources.playing, cx.resources.arp_offset);
+            let mut notes = cx.resources.notes;
+            let mut arp = cx.resources.arp;
+            arp.lock(|arp| notes.lock(|notes| arp_step(arp, notes, params, playing, offset)));
+        }
+
+        let next = if tapped {
+            period
+        } else {
+            ARP_POLL_MS * (SYSCLK_HZ / 1000)
+        };
+        cx.schedule.arp_tick(cx.scheduled + next.cycles()).ok();
+    }
+
+    #[task(priority = 1, schedule = [tune_tick], resources = [tune_led, &voice])]
+    fn tune_tick(cx: tune_tick::Context) {
+        static mut ERROR: u32 = 0;
+
+        let hz = cx.resources.voice.hz();
+        let cents = Note::from_hz(hz).map_or(50, |note| (note.cents as i32).unsigned_abs());
+        let led = cx.resources.tune_led;
+
+        if cents <= IN_TUNE_CENTS {
+            *ERROR = 0;
+            set_level(led, !board::TUNE_LED_ACTIVE_LOW);
+        } else {
+            // Blink rate proportional to the error
+            *ERROR += cents;
+            if *ERROR >= TUNE_BLINK_CENTS {
+                *ERROR = 0;
+                led.toggle().ok();
+            }
+        }
+
+        cx.schedule
+            .tune_tick(cx.scheduled + (TUNE_POLL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    #[task(priority = 1, schedule = [ui_tick], resources = [&asleep, button, button_pin, clicks, &envelope, exti, &faults, &frozen, &params, preset, storage, &voice])]
+    fn ui_tick(cx: ui_tick::Context) {
+        static mut SYNC_EDGE: Option<i32> = None;
+        static mut SETTINGS: Option<Settings> = None;
+        static mut SAVE_IN: u16 = 0;
+        static mut AUTO_SLEEP: AutoSleep = AutoSleep::new(SLEEP_AFTER_MS / UI_POLL_MS);
+        static mut PAGE: Option<Param> = None;
+        #[cfg(feature = "env-cv")]
+        static mut LAST_ENV: u16 = 0;
+
+        // Sync edge polarity, applied to the EXTI triggers when it changes
+        let edge = cx.resources.params.get(Param::SyncEdge);
+        if *SYNC_EDGE != Some(edge) {
+            *SYNC_EDGE = Some(edge);
+            let lines = if cfg!(feature = "dual") {
+                (1 << board::HARD_SYNC) | (1 << board::HARD_SYNC2)
+            } else {
+                1 << board::HARD_SYNC
+            };
+            let rising = edge != SYNC_EDGE_FALLING;
+            let falling = edge != SYNC_EDGE_RISING;
+            let exti = cx.resources.exti;
+            exti.rtsr.modify(|r, w| unsafe {
+                w.bits(if rising {
+                    r.bits() | lines
+                } else {
+                    r.bits() & !lines
+                })
+            });
+            exti.ftsr.modify(|r, w| unsafe {
+                w.bits(if falling {
+                    r.bits() | lines
+                } else {
+                    r.bits() & !lines
+                })
+            });
+        }
+
+        // Presets recall as soon as they are picked on the menu. Empty ones
+        // stay picked without changing anything.
+        let slot = cx.resources.params.get(Param::Preset) as u8;
+        if slot != *cx.resources.preset {
+            recall(
+                cx.resources.storage,
+                cx.resources.params,
+                cx.resources.preset,
+                slot,
+            );
+            *cx.resources.preset = slot;
+        }
+
+        if supply::low() && cx.resources.faults.raise(Fault::Supply) {
+            defmt::warn!("supply low, holding off flash writes");
+        }
+
+        // Settings are saved once the pages are left alone for a while, so
+        // turning the encoder doesn't wear the flash. A low supply holds the
+        // save back until it recovers.
+        let settings = Settings::capture(cx.resources.params);
+        let changed = *SETTINGS != Some(settings);
+        if changed {
+            *SETTINGS = Some(settings);
+            *SAVE_IN = (SETTINGS_SAVE_MS / UI_POLL_MS) as u16;
+        } else if *SAVE_IN > 0 {
+            *SAVE_IN -= 1;
+            if let (0, Some(storage)) = (*SAVE_IN, cx.resources.storage.as_mut()) {
+                if supply::low() {
+                    *SAVE_IN = 1;
+                } else if settings::save(storage, &settings).is_err() {
+                    defmt::warn!("settings save failed");
+                }
+            }
+        }
+
+        let pressed = cx.resources.button_pin.is_low().unwrap_or(false);
+
+        let event = cx.resources.button.update(pressed);
+
+        match cx.resources.clicks.update(event) {
+            Some(Click::Single) => cx.resources.params.next_page(),
+            Some(Click::Double) => cx.resources.params.set(Param::FineTune, 0),
+            Some(Click::Long) => {
+                cx.resources.frozen.fetch_xor(true, Ordering::Relaxed);
+            }
+            None => {}
+        }
+
+        // Auto-sleep, woken by the CV or the pitch moving, or by touching
+        // the controls
+        let params = cx.resources.params;
+        let page = params.page();
+        let touched = changed || pressed || *PAGE != Some(page);
+        *PAGE = Some(page);
+        // A moving envelope articulates the amplitude DAC, which sleep holds
+        #[cfg(feature = "env-cv")]
+        let touched = {
+            let env = cx.resources.envelope.load(Ordering::Relaxed);
+            let moved = (env as i32 - *LAST_ENV as i32).abs() > ENV_STILL;
+            if moved {
+                *LAST_ENV = env;
+            }
+            touched || moved
+        };
+        let voice = cx.resources.voice;
+        let asleep = AUTO_SLEEP.update(voice.cv_mv(), voice.pitch_mv(), touched)
+            && params.get(Param::Sleep) == SLEEP_AUTO;
+        if cx.resources.asleep.swap(asleep, Ordering::Relaxed) != asleep {
+            defmt::debug!("asleep: {}", asleep);
+        }
+
+        cx.schedule
+            .ui_tick(cx.scheduled + (UI_POLL_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    #[task(priority = 1, schedule = [watch_tick], resources = [&capture, &faults, &params, &temperature, &voice, &watch])]
+    fn watch_tick(cx: watch_tick::Context) {
+        let r = cx.resources;
+
+        for &ch in watch::CHANNELS.iter() {
+            if !r.watch.is_watched(ch) {
+                continue;
+            }
+
+            let value = match ch {
+                Channel::Cv => r.voice.cv_mv(),
+                Channel::Pitch => r.voice.pitch_mv(),
+                Channel::FineTune => r.params.get(Param::FineTune),
+                Channel::Step => r.voice.osc.step() as i32,
+                Channel::Temperature => r.temperature.load(Ordering::Relaxed) as i32,
+                Channel::SyncHz => r.capture.hz(SYSCLK_HZ).map_or(0, |hz| hz as i32),
+                Channel::SupplyLow => supply::low() as i32,
+                Channel::AdcErrors => r.faults.count(Fault::Adc) as i32,
+            };
+            defmt::info!("{}={}", ch.name(), value);
+        }
+
+        let interval = r.watch.interval_ms() as u32 * (SYSCLK_HZ / 1000);
+        cx.schedule
+            .watch_tick(cx.scheduled + interval.cycles())
+            .ok();
+    }
+
+    /// Feeds the watchdog only while the tick, the measurement and the idle
+    /// loop all keep running. Running at the lowest priority, it also stops
+    /// if any task hogs the CPU.
+    #[task(priority = 1, schedule = [watchdog_tick], resources = [&heartbeats, iwdg])]
+    fn watchdog_tick(cx: watchdog_tick::Context) {
+        if cx.resources.heartbeats.check() {
+            cx.resources.iwdg.feed();
+        }
+
+        cx.schedule
+            .watchdog_tick(cx.scheduled + (WATCHDOG_CHECK_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    #[cfg(feature = "recorder")]
+    #[task(priority = 1, schedule = [replay_drain], resources = [recorder])]
+    fn replay_drain(mut cx: replay_drain::Context) {
+        while let Some(e) = cx.resources.recorder.lock(|r| r.pop()) {
+            defmt::info!("replay {} {} {}", e.time, e.kind as u8, e.value);
+        }
+
+        let dropped = cx.resources.recorder.lock(|r| r.take_dropped());
+        if dropped != 0 {
+            defmt::warn!("replay dropped {}", dropped);
+        }
+
+        cx.schedule
+            .replay_drain(cx.scheduled + (REPLAY_DRAIN_MS * (SYSCLK_HZ / 1000)).cycles())
+            .ok();
+    }
+
+    extern "C" {
+        fn CAN_RX1();
     }
 };
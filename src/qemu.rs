@@ -0,0 +1,131 @@
+//! Integration tests for the task wiring, built into the image with the
+//! `qemu` feature and run under `qemu-system-arm` by `make qemu-test`.
+//!
+//! QEMU can't drive the input pins or the ADC from outside, so idle stops
+//! TIM2 and TIM3 and plays their part: it pends the tick interrupt one tick
+//! at a time and checks the output pins in between. Results go out over
+//! semihosting, and QEMU exits with a failure status if any test failed.
+
+use cortex_m_semihosting::{debug, hprintln};
+use stm32f1xx_hal::pac::{self, Interrupt};
+
+use oxide_dco_core::osc::Oscillator;
+
+use crate::board;
+
+/// Tuning word for a cycle of [`PERIOD_TICKS`].
+const STEP: u32 = 1 << 28;
+const PERIOD_TICKS: u32 = 16;
+const PERIODS: u32 = 10;
+
+type Test = fn(&Oscillator) -> Result<(), &'static str>;
+
+const TESTS: [(&str, Test); 3] = [
+    ("tick toggles the output", toggles),
+    ("hard sync restarts the cycle low", hard_sync),
+    ("scope pulse at phase zero", scope),
+];
+
+/// Runs one tick. The tick outranks idle, so it has run by the time the pend
+/// has taken effect.
+fn tick() {
+    rtfm::pend(Interrupt::TIM3);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+fn out_high() -> bool {
+    let gpiob = unsafe { &*pac::GPIOB::ptr() };
+    gpiob.odr.read().bits() & (1 << board::OUT) != 0
+}
+
+fn scope_high() -> bool {
+    let gpioa = unsafe { &*pac::GPIOA::ptr() };
+    gpioa.odr.read().bits() & (1 << board::SCOPE) != 0
+}
+
+/// Starts a cycle at phase zero with the test tuning word.
+fn restart(osc: &Oscillator) {
+    osc.set_step(STEP);
+    osc.reset();
+    tick();
+}
+
+fn toggles(osc: &Oscillator) -> Result<(), &'static str> {
+    restart(osc);
+    let mut level = out_high();
+    let mut toggles = 0;
+    for _ in 0..PERIOD_TICKS * PERIODS {
+        tick();
+        if out_high() != level {
+            level = !level;
+            toggles += 1;
+        }
+    }
+
+    if toggles == 2 * PERIODS {
+        Ok(())
+    } else {
+        Err("wrong number of toggles for the tuning word")
+    }
+}
+
+fn hard_sync(osc: &Oscillator) -> Result<(), &'static str> {
+    restart(osc);
+    // Into the high half of the cycle
+    for _ in 0..PERIOD_TICKS * 3 / 4 {
+        tick();
+    }
+    if !out_high() {
+        return Err("output low in the second half of the cycle");
+    }
+
+    osc.reset();
+    tick();
+    if out_high() || osc.phase() != 0 {
+        Err("output not restarted")
+    } else {
+        Ok(())
+    }
+}
+
+fn scope(osc: &Oscillator) -> Result<(), &'static str> {
+    restart(osc);
+    if !scope_high() {
+        return Err("no pulse on the wrap");
+    }
+    for _ in 0..crate::SCOPE_TICKS {
+        tick();
+    }
+    if scope_high() {
+        Err("pulse doesn't end")
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs every test and asks QEMU to exit with the result. Returns only
+/// without a semihosting host.
+pub fn run(osc: &Oscillator) {
+    // The timers would tick in between the checks
+    let (tim2, tim3) = unsafe { (&*pac::TIM2::ptr(), &*pac::TIM3::ptr()) };
+    tim2.cr1.modify(|_, w| w.cen().clear_bit());
+    tim3.cr1.modify(|_, w| w.cen().clear_bit());
+
+    let mut failed = false;
+    for &(name, test) in TESTS.iter() {
+        match test(osc) {
+            Ok(()) => hprintln!("ok: {}", name).ok(),
+            Err(reason) => {
+                failed = true;
+                hprintln!("FAILED: {}: {}", name, reason).ok()
+            }
+        };
+    }
+
+    debug::exit(if failed {
+        debug::EXIT_FAILURE
+    } else {
+        debug::EXIT_SUCCESS
+    });
+}
@@ -0,0 +1,31 @@
+//! Supply monitoring with the programmable voltage detector, so a sagging
+//! 3.3 V rail holds off flash writes instead of risking half-written pages.
+//!
+//! The detector compares VDD against its highest threshold, 2.9 V with about
+//! 100 mV of hysteresis, well above where the flash stops programming
+//! reliably.
+
+use stm32f1xx_hal::pac;
+
+// RCC APB1ENR, PWR CR and PWR CSR bits
+const APB1ENR_PWREN: u32 = 1 << 28;
+const CR_PVDE: u32 = 1 << 4;
+const CR_PLS: u32 = 0b111 << 5;
+const CSR_PVDO: u32 = 1 << 2;
+
+/// Starts the detector at 2.9 V. Its output needs a few microseconds to
+/// settle.
+pub fn enable() {
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        rcc.apb1enr.modify(|r, w| w.bits(r.bits() | APB1ENR_PWREN));
+        let pwr = &*pac::PWR::ptr();
+        pwr.cr.modify(|r, w| w.bits(r.bits() | CR_PLS | CR_PVDE));
+    }
+}
+
+/// Whether VDD is below the threshold right now.
+pub fn low() -> bool {
+    let pwr = unsafe { &*pac::PWR::ptr() };
+    pwr.csr.read().bits() & CSR_PVDO != 0
+}
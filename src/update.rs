@@ -0,0 +1,322 @@
+//! Firmware updates over the console with XMODEM, for modules with only the
+//! console's serial port wired out.
+//!
+//! The `update` command leaves a request in a backup register and resets,
+//! and init hands the console over to [`run`] before anything else is
+//! started, the outputs still off and the interrupts masked. The image is
+//! received into the upper half of a 128K part, which the firmware never
+//! uses otherwise, and read back against the CRC-32 of what came in. Only
+//! then is it copied over the running firmware, by a routine in RAM, since
+//! the flash it runs from is being erased. Anything that goes wrong before
+//! the copy resets into the old firmware, untouched.
+//!
+//! F103C8s are sold as 64K parts; most hold 128K, but only the flash size
+//! register says so, and without it the update is refused.
+
+use oxide_dco_core::crc::Crc32;
+use oxide_dco_core::xmodem::{self, Event, Receiver};
+use stm32f1xx_hal::pac;
+
+use crate::{bootloader, flash, supply, SYSCLK_HZ};
+
+/// Backup data register 2, next to the bootloader's request.
+const BKP_DR2: *mut u32 = 0x4000_6c08 as *mut u32;
+const MAGIC: u32 = 0x0dfa;
+
+/// Flash size in K, from the factory.
+const F_SIZE: *const u16 = 0x1fff_f7e0 as *const u16;
+
+/// The running firmware, and the room `memory.x` gives it below the
+/// settings storage. A bin made with the `.wavetables` section left in runs
+/// past it, over the settings, and is refused.
+const ACTIVE: u32 = 0x0800_0000;
+const IMAGE_MAX: u32 = flash::STORAGE_BASE - ACTIVE;
+/// Where the new image is received, the start of the second 64K.
+const STAGED: u32 = 0x0801_0000;
+/// Flash the staging area needs.
+const FLASH_NEEDED: u32 = STAGED - ACTIVE + IMAGE_MAX;
+
+/// Start and end of RAM, for checking the image's stack pointer.
+const RAM: u32 = 0x2000_0000;
+const RAM_END: u32 = RAM + 20 * 1024;
+
+/// Quiet seconds before the first block, while the sender is being started,
+/// and between blocks.
+const START_TIMEOUT_S: u32 = 60;
+const BLOCK_TIMEOUT_S: u32 = 10;
+
+// USART SR bits
+const USART_RXNE: u32 = 1 << 5;
+const USART_ORE: u32 = 1 << 3;
+const USART_TXE: u32 = 1 << 7;
+const USART_TC: u32 = 1 << 6;
+
+/// Resets into the update mode.
+pub fn enter() -> ! {
+    defmt::info!("rebooting into the update mode");
+    unsafe {
+        bootloader::unlock_backup();
+        core::ptr::write_volatile(BKP_DR2, MAGIC);
+    }
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Whether [`enter`] asked for the update mode, clearing the request so
+/// it's only taken once whatever happens.
+pub fn requested() -> bool {
+    unsafe {
+        bootloader::unlock_backup();
+        if core::ptr::read_volatile(BKP_DR2) & 0xffff != MAGIC {
+            return false;
+        }
+        core::ptr::write_volatile(BKP_DR2, 0);
+    }
+    true
+}
+
+/// Receives an image on `usart`, stages it and swaps it in, or resets into
+/// the old firmware with the reason on the console.
+pub fn run(usart: &pac::USART3) -> ! {
+    let flash_size = unsafe { core::ptr::read_volatile(F_SIZE) } as u32 * 1024;
+    if flash_size < FLASH_NEEDED {
+        leave(usart, "update: no room for a second image\r\n");
+    }
+    // Unoptimised, the copy would call into the flash it is erasing
+    if cfg!(debug_assertions) {
+        leave(usart, "update: needs a release build\r\n");
+    }
+    send(usart, b"update: send the image with XMODEM\r\n");
+
+    let mut receiver = Receiver::new();
+    let mut len = 0;
+    let mut crc = Crc32::new();
+    let mut quiet_since = cortex_m::peripheral::DWT::get_cycle_count();
+    let mut quiet_s = 0;
+    loop {
+        let byte = match receive(usart) {
+            Some(byte) => byte,
+            None => {
+                let now = cortex_m::peripheral::DWT::get_cycle_count();
+                if now.wrapping_sub(quiet_since) >= SYSCLK_HZ {
+                    quiet_since = now;
+                    quiet_s += 1;
+                    let timeout = if receiver.started() {
+                        BLOCK_TIMEOUT_S
+                    } else {
+                        START_TIMEOUT_S
+                    };
+                    if quiet_s >= timeout {
+                        cancel(usart, "update: timed out\r\n");
+                    }
+                    send(usart, &[receiver.idle()]);
+                }
+                continue;
+            }
+        };
+        quiet_since = cortex_m::peripheral::DWT::get_cycle_count();
+        quiet_s = 0;
+
+        match receiver.feed(byte) {
+            Event::None => {}
+            Event::Block(data) => {
+                if len + data.len() as u32 > IMAGE_MAX {
+                    cancel(
+                        usart,
+                        "update: image reaches the settings, build it without .wavetables\r\n",
+                    );
+                }
+                if stage(len, data).is_err() {
+                    cancel(usart, "update: flash write failed\r\n");
+                }
+                crc.update(data);
+                len += data.len() as u32;
+                send(usart, &[xmodem::ACK]);
+            }
+            Event::Repeat => send(usart, &[xmodem::ACK]),
+            Event::Bad => send(usart, &[xmodem::NAK]),
+            Event::Lost => cancel(usart, "update: blocks out of order\r\n"),
+            Event::Cancelled => leave(usart, "update: cancelled\r\n"),
+            Event::End => {
+                send(usart, &[xmodem::ACK]);
+                break;
+            }
+        }
+    }
+
+    // Lets the sender finish and give the terminal back before the reply
+    wait_quiet(usart);
+    let mut staged = Crc32::new();
+    for offset in (0..len).step_by(4) {
+        let word = unsafe { core::ptr::read_volatile((STAGED + offset) as *const u32) };
+        staged.update(&word.to_le_bytes());
+    }
+    if staged.finish() != crc.finish() {
+        leave(usart, "update: staged copy doesn't match\r\n");
+    }
+    if !vectors_valid(len) {
+        leave(usart, "update: not a firmware image\r\n");
+    }
+    if supply::low() {
+        leave(usart, "update: supply too low to write the flash\r\n");
+    }
+    defmt::info!("update: swapping in {} bytes", len);
+    send(usart, b"update: swapping, keep the power on\r\n");
+    while usart.sr.read().bits() & USART_TC == 0 {}
+    unsafe { swap(len) }
+}
+
+/// Writes a received block at `offset` into the staging area, erasing each
+/// page as the image reaches it.
+fn stage(offset: u32, data: &[u8]) -> Result<(), flash::Error> {
+    let end = offset + data.len() as u32;
+    if end > IMAGE_MAX {
+        return Err(flash::Error);
+    }
+    let mut page = (offset + flash::PAGE as u32 - 1) / flash::PAGE as u32 * flash::PAGE as u32;
+    while page < end {
+        flash::erase(STAGED + page)?;
+        page += flash::PAGE as u32;
+    }
+    // Blocks are 128 or 1024 bytes, so there's never an odd one out
+    let half_words = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    flash::program(STAGED + offset, half_words)
+}
+
+/// Whether the staged image of `len` bytes starts with a vector table for
+/// this firmware's place in flash: a stack in RAM and a Thumb reset vector
+/// inside the image.
+fn vectors_valid(len: u32) -> bool {
+    let sp = unsafe { core::ptr::read_volatile(STAGED as *const u32) };
+    let reset = unsafe { core::ptr::read_volatile((STAGED + 4) as *const u32) };
+    (RAM..=RAM_END).contains(&sp) && reset & 1 == 1 && (ACTIVE..ACTIVE + len).contains(&reset)
+}
+
+fn receive(usart: &pac::USART3) -> Option<u8> {
+    let sr = usart.sr.read().bits();
+    if sr & (USART_RXNE | USART_ORE) == 0 {
+        return None;
+    }
+    // Reading the data after the status clears an overrun too; the block it
+    // cut into fails its CRC
+    let byte = usart.dr.read().bits() as u8;
+    if sr & USART_RXNE != 0 {
+        Some(byte)
+    } else {
+        None
+    }
+}
+
+fn send(usart: &pac::USART3, bytes: &[u8]) {
+    for &byte in bytes {
+        while usart.sr.read().bits() & USART_TXE == 0 {}
+        usart.dr.write(|w| unsafe { w.bits(byte as u32) });
+    }
+}
+
+/// Waits for a second without anything received.
+fn wait_quiet(usart: &pac::USART3) {
+    let mut since = cortex_m::peripheral::DWT::get_cycle_count();
+    loop {
+        let now = cortex_m::peripheral::DWT::get_cycle_count();
+        if receive(usart).is_some() {
+            since = now;
+        } else if now.wrapping_sub(since) >= SYSCLK_HZ {
+            return;
+        }
+    }
+}
+
+/// Stops the sender mid-transfer, then leaves.
+fn cancel(usart: &pac::USART3, reason: &str) -> ! {
+    send(usart, &[xmodem::CAN, xmodem::CAN]);
+    wait_quiet(usart);
+    leave(usart, reason)
+}
+
+/// Says why on the console and resets into the firmware as it is.
+fn leave(usart: &pac::USART3, reason: &str) -> ! {
+    defmt::warn!("{}", reason.trim_end());
+    send(usart, reason.as_bytes());
+    while usart.sr.read().bits() & USART_TC == 0 {}
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+// FLASH and SCB registers, raw for the copy in RAM
+const FLASH_KEYR: *mut u32 = 0x4002_2004 as *mut u32;
+const FLASH_SR: *mut u32 = 0x4002_200c as *mut u32;
+const FLASH_CR: *mut u32 = 0x4002_2010 as *mut u32;
+const FLASH_AR: *mut u32 = 0x4002_2014 as *mut u32;
+const SR_BSY: u32 = 1 << 0;
+const SR_ERRORS: u32 = (1 << 2) | (1 << 4);
+const CR_PG: u32 = 1 << 0;
+const CR_PER: u32 = 1 << 1;
+const CR_STRT: u32 = 1 << 6;
+const CR_LOCK: u32 = 1 << 7;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+const SCB_AIRCR: *mut u32 = 0xe000_ed0c as *mut u32;
+const AIRCR_SYSRESETREQ: u32 = 0x05fa_0004;
+
+/// Copies the staged image of `len` bytes over the running firmware and
+/// resets into it, erasing and writing it again if the copy doesn't read
+/// back right.
+///
+/// Runs from RAM and touches nothing in flash, no code and no constants the
+/// compiler would leave there, so it only uses raw volatile accesses that a
+/// release build inlines. With the interrupts masked nothing else runs
+/// meanwhile. Cut off halfway, the firmware is gone; the staged copy is
+/// still there for the ROM bootloader to be pointed at.
+#[inline(never)]
+#[link_section = ".data.update_swap"]
+unsafe fn swap(len: u32) -> ! {
+    use core::ptr::{read_volatile, write_volatile};
+    use core::sync::atomic::{compiler_fence, Ordering};
+
+    if read_volatile(FLASH_CR) & CR_LOCK != 0 {
+        write_volatile(FLASH_KEYR, KEY1);
+        write_volatile(FLASH_KEYR, KEY2);
+    }
+    let mut attempts = 0;
+    while attempts < 3 {
+        attempts += 1;
+
+        let mut page = 0;
+        while page < len {
+            write_volatile(FLASH_CR, CR_PER);
+            write_volatile(FLASH_AR, ACTIVE + page);
+            write_volatile(FLASH_CR, CR_PER | CR_STRT);
+            while read_volatile(FLASH_SR) & SR_BSY != 0 {}
+            write_volatile(FLASH_SR, SR_ERRORS);
+            page += 1024;
+        }
+
+        write_volatile(FLASH_CR, CR_PG);
+        let mut offset = 0;
+        while offset < len {
+            let half_word = read_volatile((STAGED + offset) as *const u16);
+            write_volatile((ACTIVE + offset) as *mut u16, half_word);
+            while read_volatile(FLASH_SR) & SR_BSY != 0 {}
+            write_volatile(FLASH_SR, SR_ERRORS);
+            offset += 2;
+        }
+        write_volatile(FLASH_CR, 0);
+
+        let mut offset = 0;
+        while offset < len
+            && read_volatile((ACTIVE + offset) as *const u16)
+                == read_volatile((STAGED + offset) as *const u16)
+        {
+            offset += 2;
+        }
+        if offset >= len {
+            break;
+        }
+    }
+    write_volatile(FLASH_CR, CR_LOCK);
+    write_volatile(SCB_AIRCR, AIRCR_SYSRESETREQ);
+    loop {
+        compiler_fence(Ordering::SeqCst);
+    }
+}